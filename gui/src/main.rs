@@ -3,8 +3,9 @@ use ggez::graphics;
 use ggez::input::mouse;
 use ggez::{timer, Context, ContextBuilder, GameResult};
 use hansing_chess::color::Color;
-use hansing_chess::game::Game;
-use hansing_chess::movegen;
+use hansing_chess::eco::classify;
+use hansing_chess::event::GameEvent;
+use hansing_chess::game::{Game, GameResult as ChessResult};
 use hansing_chess::square::Square;
 use hansing_chess::title::Title;
 
@@ -67,10 +68,20 @@ impl MyGame {
         v.push(graphics::Image::new(ctx, "/black_queen.png").unwrap());
         v.push(graphics::Image::new(ctx, "/black_king.png").unwrap());
 
+        let mut game = Game::new();
+        game.subscribe(Box::new(|event| {
+            if let GameEvent::GameOver(result) = event {
+                match result {
+                    ChessResult::Won(color) => println!("{} has won!", color),
+                    _ => println!("The game has ended in a tie."),
+                }
+            }
+        }));
+
         MyGame {
             //dt: std::time::Duration::new(0, 0),//dead code
             sprites: v,
-            game: Game::new(),
+            game,
             piece_holding: [-1, -1],
         }
     }
@@ -157,6 +168,14 @@ impl MyGame {
         }
         Ok(())
     }
+
+    pub fn draw_opening(&mut self, ctx: &mut Context) -> GameResult<()> {
+        if let Some(opening) = classify(&self.game) {
+            let text = graphics::Text::new(opening.to_string());
+            graphics::draw(ctx, &text, graphics::DrawParam::new().dest([160.0, 30.0]))?;
+        }
+        Ok(())
+    }
 }
 
 impl EventHandler for MyGame {
@@ -173,16 +192,10 @@ impl EventHandler for MyGame {
             && self.piece_holding != [-1, -1]
         {
             if coord_x >= 0 && coord_x <= 7 && coord_y >= 0 && coord_y <= 7 {
-                let all_moves = movegen::generate_action_space(self.game.board.clone());
-                for curr_move in all_moves.iter() {
-                    if curr_move.from
-                        == Square::new(self.piece_holding[1], self.piece_holding[0]).unwrap()
-                        && curr_move.to == Square::new(coord_y, coord_x).unwrap()
-                    {
-                        self.game.make_move(*curr_move);
-                        self.piece_holding = [-1, -1];
-                        break;
-                    }
+                let from = Square::new(self.piece_holding[1], self.piece_holding[0]).unwrap();
+                let to = Square::new(coord_y, coord_x).unwrap();
+                if let Some(curr_move) = self.game.find_move(from, to) {
+                    self.game.make_move(curr_move);
                 }
             }
             self.piece_holding = [-1, -1];
@@ -199,6 +212,7 @@ impl EventHandler for MyGame {
         graphics::clear(ctx, graphics::Color::from_rgb(15, 15, 20));
         self.draw_board(ctx).ok();
         self.draw_pieces(ctx).ok();
+        self.draw_opening(ctx).ok();
         graphics::present(ctx)?;
         Ok(())
     }
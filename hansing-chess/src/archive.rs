@@ -0,0 +1,505 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::encoding::{result_code, result_from_code, write_varint, DecodeError, EncodeError};
+use crate::game::{Game, GameResult};
+use crate::movedata::MoveData;
+use crate::zobrist::hash_board;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+//Index entries kept in memory before they are merged into the index file, about 17 MB
+const FLUSH_ENTRIES: usize = 1 << 20;
+const ENTRY_SIZE: u64 = 17;
+const INDEX_HEADER: u64 = 4; //Number of games indexed
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    Corrupt(DecodeError),
+    Unencodable(EncodeError),
+    TooLong(usize), //Plies past what the index can number
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::Io(e) => write!(f, "archive access failed: {}", e),
+            ArchiveError::Corrupt(e) => write!(f, "archive is corrupt: {}", e),
+            ArchiveError::Unencodable(e) => write!(f, "game cannot be stored: {}", e),
+            ArchiveError::TooLong(plies) => {
+                write!(f, "game of {} plies is too long to index", plies)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+impl From<DecodeError> for ArchiveError {
+    fn from(e: DecodeError) -> Self {
+        ArchiveError::Corrupt(e)
+    }
+}
+
+impl From<EncodeError> for ArchiveError {
+    fn from(e: EncodeError) -> Self {
+        ArchiveError::Unencodable(e)
+    }
+}
+
+//A game that reached a position
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PositionHit {
+    pub game: u32,
+    pub ply: u16,
+    pub next: Option<MoveData>, //None where the game ended
+    pub result: GameResult,
+}
+
+//How often a move was played from a position and how those games ended
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct MoveStats {
+    pub next: MoveData,
+    pub games: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+struct Entry {
+    hash: u64,
+    game: u32,
+    ply: u16,
+    next: u16, //Encoded move, 0 where the game ended
+    result: u8,
+}
+
+impl Entry {
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.hash.to_le_bytes())?;
+        out.write_all(&self.game.to_le_bytes())?;
+        out.write_all(&self.ply.to_le_bytes())?;
+        out.write_all(&self.next.to_le_bytes())?;
+        out.write_all(&[self.result])
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<Self> {
+        let mut b = [0; ENTRY_SIZE as usize];
+        input.read_exact(&mut b)?;
+        Ok(Self {
+            hash: u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+            game: u32::from_le_bytes([b[8], b[9], b[10], b[11]]),
+            ply: u16::from_le_bytes([b[12], b[13]]),
+            next: u16::from_le_bytes([b[14], b[15]]),
+            result: b[16],
+        })
+    }
+
+    fn hit(&self) -> Result<PositionHit, DecodeError> {
+        Ok(PositionHit {
+            game: self.game,
+            ply: self.ply,
+            next: match self.next {
+                0 => None,
+                code => Some(MoveData::from_u16(code).ok_or(DecodeError::BadMove(code))?),
+            },
+            result: result_from_code(self.result).ok_or(DecodeError::BadResult(self.result))?,
+        })
+    }
+}
+
+//Games stored on disk in a directory:
+//  games    each game in the compact encoding, prefixed by its length
+//  offsets  where each game starts, eight bytes per game
+//  index    every position of every game by hash, sorted for binary search
+//Appended games are indexed in memory and merged into the index file on flush. Call flush
+//before dropping the archive to see whether that worked: drop flushes as well, but ignores
+//errors. Games left unindexed by a crash are indexed again on open.
+pub struct Archive {
+    dir: PathBuf,
+    games: File,
+    offsets: File,
+    games_end: u64,
+    len: u32,
+    indexed: u32, //Games in the index file
+    pending: Vec<Entry>,
+}
+
+impl Archive {
+    //Opens the archive in the directory, creating an empty one if there is none
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, ArchiveError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let append = |name: &str| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(name))
+        };
+        let games = append("games")?;
+        let offsets = append("offsets")?;
+        let games_end = games.metadata()?.len();
+        let len = (offsets.metadata()?.len() / 8) as u32;
+        let indexed = match File::open(dir.join("index")) {
+            Ok(mut index) => {
+                let mut count = [0; INDEX_HEADER as usize];
+                index.read_exact(&mut count)?;
+                u32::from_le_bytes(count)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        let mut archive = Self {
+            dir,
+            games,
+            offsets,
+            games_end,
+            len,
+            indexed,
+            pending: Vec::new(),
+        };
+        for n in indexed..len {
+            let game = archive.game(n)?;
+            archive.index_game(n, &game);
+        }
+        Ok(archive)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    //Stores the game and returns its number
+    pub fn append(&mut self, game: &Game) -> Result<u32, ArchiveError> {
+        if game.moves.len() > u16::MAX as usize {
+            return Err(ArchiveError::TooLong(game.moves.len()));
+        }
+        let encoded = game.to_compact()?;
+        let mut record = Vec::with_capacity(encoded.len() + 3);
+        write_varint(&mut record, encoded.len() as u32);
+        record.extend(encoded);
+        self.games.write_all(&record)?;
+        self.offsets.write_all(&self.games_end.to_le_bytes())?;
+        self.games_end += record.len() as u64;
+
+        let n = self.len;
+        self.len += 1;
+        self.index_game(n, game);
+        if self.pending.len() >= FLUSH_ENTRIES {
+            self.flush()?;
+        }
+        Ok(n)
+    }
+
+    pub fn game(&self, n: u32) -> Result<Game, ArchiveError> {
+        let mut offsets = File::open(self.dir.join("offsets"))?;
+        offsets.seek(SeekFrom::Start(n as u64 * 8))?;
+        let mut offset = [0; 8];
+        offsets.read_exact(&mut offset)?;
+        let mut games = BufReader::new(File::open(self.dir.join("games"))?);
+        games.seek(SeekFrom::Start(u64::from_le_bytes(offset)))?;
+        read_game(&mut games)?.ok_or_else(|| DecodeError::Truncated.into())
+    }
+
+    //Reads the games one at a time, in the order they were appended
+    pub fn iter(&self) -> Result<Games, ArchiveError> {
+        let file = File::open(self.dir.join("games"))?;
+        Ok(Games {
+            reader: BufReader::new(file),
+        })
+    }
+
+    //Every time a stored game reached the position, ordered by game and ply
+    pub fn games_reaching(&self, board: &Board) -> Result<Vec<PositionHit>, ArchiveError> {
+        let hash = hash_board(board);
+        let mut entries = self.indexed_entries(hash)?;
+        entries.extend(self.pending.iter().filter(|e| e.hash == hash));
+        entries.sort();
+        let hits: Result<Vec<PositionHit>, DecodeError> = entries.iter().map(Entry::hit).collect();
+        Ok(hits?)
+    }
+
+    //The moves played from the position, most popular first. A game passing through the
+    //position more than once counts once for each different move it played there.
+    pub fn explore(&self, board: &Board) -> Result<Vec<MoveStats>, ArchiveError> {
+        let mut hits = self.games_reaching(board)?;
+        hits.sort_by_key(|h| (h.game, h.next.map(|m| m.to_u16())));
+        hits.dedup_by_key(|h| (h.game, h.next));
+        let mut stats: Vec<MoveStats> = Vec::new();
+        for hit in hits.iter() {
+            let next = match hit.next {
+                Some(m) => m,
+                None => continue,
+            };
+            let i = match stats.iter().position(|s| s.next == next) {
+                Some(i) => i,
+                None => {
+                    stats.push(MoveStats {
+                        next,
+                        games: 0,
+                        white_wins: 0,
+                        draws: 0,
+                        black_wins: 0,
+                    });
+                    stats.len() - 1
+                }
+            };
+            let s = &mut stats[i];
+            s.games += 1;
+            match hit.result {
+                GameResult::Won(Color::White) => s.white_wins += 1,
+                GameResult::Won(Color::Black) => s.black_wins += 1,
+                GameResult::Tied => s.draws += 1,
+                GameResult::Ongoing => (),
+            }
+        }
+        stats.sort_by(|a, b| {
+            b.games
+                .cmp(&a.games)
+                .then(a.next.to_u16().cmp(&b.next.to_u16()))
+        });
+        Ok(stats)
+    }
+
+    //Merges the pending index entries into the index file
+    pub fn flush(&mut self) -> Result<(), ArchiveError> {
+        if self.indexed == self.len {
+            return Ok(());
+        }
+        self.pending.sort();
+        let path = self.dir.join("index");
+        let temp = self.dir.join("index.tmp");
+        {
+            let mut out = BufWriter::new(File::create(&temp)?);
+            out.write_all(&self.len.to_le_bytes())?;
+            let mut pending = self.pending.iter().peekable();
+            if let Ok(file) = File::open(&path) {
+                let count = (file.metadata()?.len() - INDEX_HEADER) / ENTRY_SIZE;
+                let mut input = BufReader::new(file);
+                input.seek(SeekFrom::Start(INDEX_HEADER))?;
+                for _ in 0..count {
+                    let entry = Entry::read(&mut input)?;
+                    while let Some(p) = pending.next_if(|p| **p < entry) {
+                        p.write(&mut out)?;
+                    }
+                    entry.write(&mut out)?;
+                }
+            }
+            for p in pending {
+                p.write(&mut out)?;
+            }
+            out.flush()?;
+        }
+        fs::rename(&temp, &path)?;
+        self.indexed = self.len;
+        self.pending.clear();
+        Ok(())
+    }
+
+    //The game is no longer than u16::MAX plies, append checks it
+    fn index_game(&mut self, n: u32, game: &Game) {
+        let result = result_code(game.result);
+        let mut board = game.start.clone();
+        for (ply, m) in game.moves.iter().enumerate() {
+            self.pending.push(Entry {
+                hash: hash_board(&board),
+                game: n,
+                ply: ply as u16,
+                next: m.to_u16(),
+                result,
+            });
+            board.make_move(*m);
+        }
+        self.pending.push(Entry {
+            hash: hash_board(&board),
+            game: n,
+            ply: game.moves.len() as u16,
+            next: 0,
+            result,
+        });
+    }
+
+    //Binary search for the first entry with the hash, then a scan over the rest
+    fn indexed_entries(&self, hash: u64) -> Result<Vec<Entry>, ArchiveError> {
+        let mut file = match File::open(self.dir.join("index")) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let count = (file.metadata()?.len() - INDEX_HEADER) / ENTRY_SIZE;
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            file.seek(SeekFrom::Start(INDEX_HEADER + mid * ENTRY_SIZE))?;
+            if Entry::read(&mut file)?.hash < hash {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let mut input = BufReader::new(file);
+        input.seek(SeekFrom::Start(INDEX_HEADER + low * ENTRY_SIZE))?;
+        let mut entries = Vec::new();
+        for _ in low..count {
+            let entry = Entry::read(&mut input)?;
+            if entry.hash != hash {
+                break;
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+impl Drop for Archive {
+    fn drop(&mut self) {
+        //Best effort, the index is rebuilt on open if this fails
+        let _ = self.flush();
+    }
+}
+
+pub struct Games {
+    reader: BufReader<File>,
+}
+
+impl Iterator for Games {
+    type Item = Result<Game, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_game(&mut self.reader).transpose()
+    }
+}
+
+//None at the end of the file
+fn read_game(input: &mut impl Read) -> Result<Option<Game>, ArchiveError> {
+    let mut len = 0u32;
+    for shift in (0..32).step_by(7) {
+        let mut b = [0];
+        if input.read(&mut b)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            return Err(DecodeError::Truncated.into());
+        }
+        len |= ((b[0] & 0x7f) as u32) << shift;
+        if b[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut bytes = vec![0; len as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(Some(Game::from_compact(&bytes)?))
+}
+
+#[cfg(test)]
+mod test_archive {
+    use super::*;
+
+    fn play(ucis: &[&str]) -> Game {
+        let mut game = Game::new();
+        for note in ucis.iter() {
+            game.make_move(MoveData::from_uci(&game.board, note).unwrap());
+        }
+        game
+    }
+
+    #[test]
+    fn append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut archive = Archive::open(dir.path()).unwrap();
+        assert!(archive.is_empty());
+        let mate = play(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let mut resigned = play(&["e2e4", "c7c5"]);
+        resigned.resign(Color::Black).unwrap();
+        assert_eq!(archive.append(&mate).unwrap(), 0);
+        assert_eq!(archive.append(&resigned).unwrap(), 1);
+        assert_eq!(archive.game(1).unwrap().moves, resigned.moves);
+
+        let games: Vec<Game> = archive.iter().unwrap().map(|g| g.unwrap()).collect();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves, mate.moves);
+        assert_eq!(games[1].result, GameResult::Won(Color::White));
+
+        let mut endless = play(&["g1f3"]);
+        endless.moves = vec![endless.moves[0]; u16::MAX as usize + 1];
+        assert!(matches!(
+            archive.append(&endless),
+            Err(ArchiveError::TooLong(65536))
+        ));
+        assert_eq!(archive.len(), 2);
+    }
+
+    #[test]
+    fn explorer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut archive = Archive::open(dir.path()).unwrap();
+        let mut drawn = play(&["e2e4", "e7e5", "g1f3"]);
+        drawn.adjudicate(GameResult::Tied).unwrap();
+        archive.append(&drawn).unwrap();
+        archive
+            .append(&play(&["f2f3", "e7e5", "g2g4", "d8h4"]))
+            .unwrap();
+        //Reaches the same position after 1. Nf3 e5 2. e4 by transposition
+        let mut won = play(&["g1f3", "e7e5", "e2e4", "b8c6"]);
+        won.resign(Color::Black).unwrap();
+        archive.append(&won).unwrap();
+        //Half of the games come from the index file, half from memory
+        archive.flush().unwrap();
+        archive.append(&play(&["e2e4", "c7c5"])).unwrap();
+
+        let start = Board::new_standard();
+        let stats = archive.explore(&start).unwrap();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].next.to_uci(), "e2e4");
+        assert_eq!(
+            (stats[0].games, stats[0].draws, stats[0].white_wins),
+            (2, 1, 0)
+        );
+
+        let after = play(&["e2e4", "e7e5", "g1f3"]).board;
+        let hits = archive.games_reaching(&after).unwrap();
+        let games: Vec<(u32, u16)> = hits.iter().map(|h| (h.game, h.ply)).collect();
+        assert_eq!(games, vec![(0, 3), (2, 3)]);
+        assert_eq!(hits[0].next, None);
+        assert_eq!(hits[1].next.unwrap().to_uci(), "b8c6");
+        assert_eq!(hits[1].result, GameResult::Won(Color::White));
+        assert!(archive
+            .games_reaching(&play(&["a2a3"]).board)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut archive = Archive::open(dir.path()).unwrap();
+            archive.append(&play(&["d2d4", "d7d5"])).unwrap();
+            archive.flush().unwrap();
+            archive.append(&play(&["d2d4", "g8f6"])).unwrap();
+            //A crash before the second game reaches the index file
+            std::mem::forget(archive);
+        }
+        let archive = Archive::open(dir.path()).unwrap();
+        assert_eq!(archive.len(), 2);
+        let stats = archive.explore(&play(&["d2d4"]).board).unwrap();
+        assert_eq!(stats.len(), 2);
+        drop(archive);
+        let archive = Archive::open(dir.path()).unwrap();
+        assert!(archive.pending.is_empty());
+        assert_eq!(archive.explore(&Board::new_standard()).unwrap()[0].games, 2);
+    }
+}
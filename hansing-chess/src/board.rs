@@ -9,18 +9,26 @@ use crate::title::Title;
 use std::collections::HashMap;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Board {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_pieces"))]
     pub pieces: HashMap<Square, Piece>,
     pub captures: Vec<Piece>,
     pub to_act: Color,
-    pub in_check: bool,        //Current player
+    //Follow from the pieces, so they are left out when serializing and recalculated when read
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub in_check: bool, //Current player
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub blockers: Vec<Square>, //Current player
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub king_pos: [Square; 2], //Indexed White, Black
     pub en_passant: Option<Square>,
+    pub halfmove_clock: u32, //Half moves since the last capture or pawn move
+    pub fullmove_number: u32,
 }
 
 impl Board {
-    fn new_empty() -> Self {
+    pub fn new_empty() -> Self {
         Self {
             pieces: HashMap::with_capacity(32),
             captures: Vec::with_capacity(32),
@@ -29,6 +37,8 @@ impl Board {
             blockers: Vec::with_capacity(8),
             king_pos: [Square::new(0, 4).unwrap(), Square::new(7, 4).unwrap()],
             en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
         }
     }
     pub fn new_standard() -> Self {
@@ -66,12 +76,34 @@ impl Board {
     }
     pub fn next_turn(&mut self) {
         self.to_act = self.to_act.inverse();
+        self.update_status();
+    }
+    //Recalculates king positions, check and blockers, use after editing pieces directly
+    pub fn update_status(&mut self) {
         self.update_king_pos();
         //check for check and blockers
         let (c, b) = self.check_if_threatend(self.king_pos[self.to_act.index()], self.to_act);
         self.in_check = c;
         self.blockers = b;
     }
+    //Castling rights as given by unmoved kings and rooks, not whether castling is legal right now
+    pub fn can_castle(&self, color: Color, king_side: bool) -> bool {
+        let back_rank = if color == Color::White { 0 } else { 7 };
+        let rook_file = if king_side { 7 } else { 0 };
+        let unmoved = |s: Option<Square>, title: Title| match s.and_then(|s| self.pieces.get(&s)) {
+            Some(p) => p.color == color && p.title == title && !p.has_moved,
+            None => false,
+        };
+        unmoved(Square::new(back_rank, 4), Title::King)
+            && unmoved(Square::new(back_rank, rook_file), Title::Rook)
+    }
+    pub fn captured_piece(&self, move_data: &MoveData) -> Option<Piece> {
+        match move_data.move_type {
+            MoveType::EnPassant(capture) => self.pieces.get(&capture).copied(),
+            MoveType::Casteling(_, _) => None,
+            _ => self.pieces.get(&move_data.to).copied(),
+        }
+    }
     fn remove_piece(&mut self, s: Square) -> Option<Piece> {
         self.pieces.remove(&s)
     }
@@ -108,6 +140,16 @@ impl Board {
     }
 
     pub fn make_move(&mut self, move_data: MoveData) {
+        let irreversible = self.captured_piece(&move_data).is_some()
+            || self.pieces.get(&move_data.from).map(|p| p.title) == Some(Title::Pawn);
+        if irreversible {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.to_act == Color::Black {
+            self.fullmove_number += 1;
+        }
         self.en_passant = None;
         match move_data.move_type {
             MoveType::Standard => self.make_standard_move(move_data),
@@ -171,6 +213,16 @@ impl Board {
                 }
             }
         }
+        // King threats
+        for (rank_offset, file_offset, _) in KING_MOVES.iter() {
+            if let Some(from) = to.offset(*rank_offset, *file_offset) {
+                if self.occupancy(from, color) == Occupancy::OtherColor
+                    && self.pieces[&from].title == Title::King
+                {
+                    threatend = true;
+                }
+            }
+        }
         //Bishop threats
         for (rank_offset, file_offset, _) in BISHOP_MOVES.to_vec() {
             let mut from = to;
@@ -235,6 +287,149 @@ impl Board {
         }
         (threatend, blockers)
     }
+
+    //All pieces of both colors attacking a square
+    pub fn attackers(&self, to: Square) -> Vec<Square> {
+        self.attackers_through(to, &[])
+    }
+
+    //Like attackers, but treats the removed squares as empty so x-ray attackers behind them are found
+    pub fn attackers_through(&self, to: Square, removed: &[Square]) -> Vec<Square> {
+        let piece_at = |s: Square| {
+            if removed.contains(&s) {
+                None
+            } else {
+                self.pieces.get(&s)
+            }
+        };
+        let mut attackers = Vec::new();
+        for (rank_offset, file_offset, _) in KNIGHT_MOVES.iter() {
+            if let Some(from) = to.offset(*rank_offset, *file_offset) {
+                if let Some(p) = piece_at(from) {
+                    if p.title == Title::Knight {
+                        attackers.push(from);
+                    }
+                }
+            }
+        }
+        for (rank_offset, file_offset, _) in KING_MOVES.iter() {
+            if let Some(from) = to.offset(*rank_offset, *file_offset) {
+                if let Some(p) = piece_at(from) {
+                    if p.title == Title::King {
+                        attackers.push(from);
+                    }
+                }
+            }
+        }
+        for &c in [Color::White, Color::Black].iter() {
+            for &file_offset in [1, -1].iter() {
+                if let Some(from) = to.offset(-c.forward(), file_offset) {
+                    if let Some(p) = piece_at(from) {
+                        if p.title == Title::Pawn && p.color == c {
+                            attackers.push(from);
+                        }
+                    }
+                }
+            }
+        }
+        for (rank_offset, file_offset, _) in QUEEN_MOVES.iter() {
+            let diagonal = *rank_offset != 0 && *file_offset != 0;
+            let mut from = to;
+            while let Some(temp) = from.offset(*rank_offset, *file_offset) {
+                from = temp;
+                if let Some(p) = piece_at(from) {
+                    let slides_here = match p.title {
+                        Title::Queen => true,
+                        Title::Bishop => diagonal,
+                        Title::Rook => !diagonal,
+                        _ => false,
+                    };
+                    if slides_here {
+                        attackers.push(from);
+                    }
+                    break;
+                }
+            }
+        }
+        attackers
+    }
+
+    //Whether color could mate by some legal sequence, however unlikely. Lone kings, a single
+    //knight, and bishops on one square color need the other side's help to block an escape.
+    pub fn has_mating_material(&self, color: Color) -> bool {
+        let own: Vec<(&Square, &Piece)> = self
+            .pieces
+            .iter()
+            .filter(|(_, p)| p.color == color && p.title != Title::King)
+            .collect();
+        let other: Vec<(&Square, &Piece)> = self
+            .pieces
+            .iter()
+            .filter(|(_, p)| p.color != color && p.title != Title::King)
+            .collect();
+        let square_color = |s: &Square| (s.rank() + s.file()) % 2;
+        if own
+            .iter()
+            .any(|(_, p)| matches!(p.title, Title::Pawn | Title::Rook | Title::Queen))
+        {
+            return true;
+        }
+        let knights = own.iter().filter(|(_, p)| p.title == Title::Knight).count();
+        let bishops: Vec<i32> = own
+            .iter()
+            .filter(|(_, p)| p.title == Title::Bishop)
+            .map(|(s, _)| square_color(s))
+            .collect();
+        let both_bishop_colors = bishops.iter().any(|c| *c != bishops[0]);
+        match (knights, bishops.len()) {
+            (0, 0) => false,
+            (1, 0) => !other.is_empty(),
+            (0, _) if !both_bishop_colors => other.iter().any(|(s, p)| match p.title {
+                Title::Bishop => square_color(s) != bishops[0],
+                Title::King => false,
+                _ => true,
+            }),
+            _ => true,
+        }
+    }
+}
+
+//Pieces ordered a1 through h8 so the same position always serializes the same way
+#[cfg(feature = "serde")]
+fn serialize_pieces<S: serde::Serializer>(
+    pieces: &HashMap<Square, Piece>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut sorted: Vec<(&Square, &Piece)> = pieces.iter().collect();
+    sorted.sort_by_key(|(s, _)| s.index());
+    serializer.collect_map(sorted)
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct BoardRecord {
+    pieces: HashMap<Square, Piece>,
+    captures: Vec<Piece>,
+    to_act: Color,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Board {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = BoardRecord::deserialize(deserializer)?;
+        let mut board = Board::new_empty();
+        board.pieces = record.pieces;
+        board.captures = record.captures;
+        board.to_act = record.to_act;
+        board.en_passant = record.en_passant;
+        board.halfmove_clock = record.halfmove_clock;
+        board.fullmove_number = record.fullmove_number;
+        board.update_status();
+        Ok(board)
+    }
 }
 
 #[cfg(test)]
@@ -323,6 +518,19 @@ mod test_board {
         assert!(a);
     }
     #[test]
+    fn test_threatend_king() {
+        let mut board = Board::new_empty();
+        let e4 = Square::from_notation("e4").unwrap();
+        let e5 = Square::from_notation("e5").unwrap();
+        let e6 = Square::from_notation("e6").unwrap();
+        board.pieces.insert(e4, Piece::new(Color::White, Title::King));
+        board.pieces.insert(e6, Piece::new(Color::Black, Title::King));
+        let (a, _) = board.check_if_threatend(e5, Color::White);
+        assert!(a);
+        let (a, _) = board.check_if_threatend(e4, Color::White);
+        assert!(!a);
+    }
+    #[test]
     fn make_move_from_move_data() {
         let d2 = Square::new(1, 3).unwrap();
         let d5 = Square::new(4, 3).unwrap();
@@ -362,6 +570,17 @@ mod test_board {
         assert_eq!(board.pieces[&e3], Piece{color: Color::White, title: Title::King, has_moved: true});
     }
     #[test]
+    fn move_counters() {
+        let mut board = Board::new_standard();
+        let sq = |s: &str| Square::from_notation(s).unwrap();
+        board.make_move(MoveData::new_standard(sq("g1"), sq("f3")));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (1, 1));
+        board.make_move(MoveData::new_standard(sq("g8"), sq("f6")));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (2, 2));
+        board.make_move(MoveData::new_double_step(sq("e2"), sq("e4"), sq("e3")));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (0, 2));
+    }
+    #[test]
     fn king_pos() {
         let e1 = Square::new(0, 4).unwrap();
         let e8 = Square::new(7, 4).unwrap();
@@ -381,6 +600,55 @@ mod test_board {
         //assert_eq!(board.king_pos[1], e8);
     }
     #[test]
+    fn attackers() {
+        let sq = |s: &str| Square::from_notation(s).unwrap();
+        let mut board = Board::new_empty();
+        board.pieces.insert(sq("e5"), Piece::new(Color::Black, Title::Pawn));
+        board.pieces.insert(sq("d4"), Piece::new(Color::White, Title::Pawn));
+        board.pieces.insert(sq("f4"), Piece::new(Color::Black, Title::Pawn));
+        board.pieces.insert(sq("c3"), Piece::new(Color::White, Title::Bishop));
+        board.pieces.insert(sq("e1"), Piece::new(Color::White, Title::Rook));
+        board.pieces.insert(sq("e2"), Piece::new(Color::White, Title::Queen));
+        board.pieces.insert(sq("g6"), Piece::new(Color::Black, Title::Knight));
+        board.pieces.insert(sq("a5"), Piece::new(Color::Black, Title::Pawn));
+
+        let direct = board.attackers(sq("e5"));
+        assert_eq!(direct.len(), 3);
+        assert!(direct.contains(&sq("d4")));
+        assert!(direct.contains(&sq("e2")));
+        assert!(direct.contains(&sq("g6")));
+        assert!(!direct.contains(&sq("f4")));
+
+        let xray = board.attackers_through(sq("e5"), &[sq("d4"), sq("e2")]);
+        assert!(xray.contains(&sq("c3")));
+        assert!(xray.contains(&sq("e1")));
+        assert!(!xray.contains(&sq("e2")));
+    }
+    #[test]
+    fn can_castle() {
+        let mut board = Board::new_standard();
+        assert!(board.can_castle(Color::White, true));
+        assert!(board.can_castle(Color::Black, false));
+        let h1 = Square::from_notation("h1").unwrap();
+        let h3 = Square::from_notation("h3").unwrap();
+        board.make_move(MoveData::new_standard(h1, h3));
+        assert!(!board.can_castle(Color::White, true));
+        assert!(board.can_castle(Color::White, false));
+    }
+    #[test]
+    fn captured_piece() {
+        let mut board = Board::new_standard();
+        let e2 = Square::from_notation("e2").unwrap();
+        let e4 = Square::from_notation("e4").unwrap();
+        let e7 = Square::from_notation("e7").unwrap();
+        assert_eq!(board.captured_piece(&MoveData::new_standard(e2, e4)), None);
+        board.make_move(MoveData::new_standard(e2, e4));
+        assert_eq!(
+            board.captured_piece(&MoveData::new_standard(e4, e7)),
+            Some(Piece::new(Color::Black, Title::Pawn))
+        );
+    }
+    #[test]
     fn new_empty() {
         let new_empty = Board::new_empty();
         assert_eq!(new_empty.to_act, Color::White);
@@ -506,6 +774,21 @@ mod test_board {
         assert!(!board.pieces.contains_key(&Square::new(7, 7).unwrap()));
     }
     #[test]
+    fn mating_material() {
+        let material = |fen: &str, color: Color| {
+            Board::from_fen(fen).unwrap().has_mating_material(color)
+        };
+        assert!(!material("4k3/8/8/8/8/8/8/4K3 w - - 0 1", Color::White));
+        assert!(!material("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", Color::White));
+        assert!(!material("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", Color::Black));
+        assert!(material("4k2r/8/8/8/8/8/8/4KN2 w - - 0 1", Color::White));
+        assert!(material("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", Color::White));
+        assert!(!material("4k3/8/8/8/8/8/8/1B2KB2 w - - 0 1", Color::White));
+        assert!(!material("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1", Color::White));
+        assert!(material("3bk3/8/8/8/8/8/8/4KB2 w - - 0 1", Color::White));
+        assert!(material("4k3/8/8/8/8/8/P7/4K3 w - - 0 1", Color::White));
+    }
+    #[test]
     fn move_piece_manipluation() {
         let mut board = Board::new_empty();
         let (a, b, c) = (
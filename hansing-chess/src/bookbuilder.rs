@@ -0,0 +1,355 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::game::GameResult;
+use crate::movedata::MoveData;
+use crate::pgn::{ImportError, Pgn, PgnReader};
+use crate::polyglot::{decode_move, encode_move, polyglot_key, Book, BookEntry};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+#[derive(Clone, Debug)]
+pub struct BookOptions {
+    pub max_ply: usize,          //Moves after this many plies are left out
+    pub min_games: u32,          //Moves played fewer times are left out
+    pub min_rating: Option<u32>, //Only moves by players rated at least this, by the Elo tags
+    pub players: Vec<String>,    //Only moves by these players, all players when empty
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 20,
+            min_games: 1,
+            min_rating: None,
+            players: Vec::new(),
+        }
+    }
+}
+
+//How a move turned out for the side that played it
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct MoveCount {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MoveCount {
+    //Polyglot weight, a win counts twice as much as a draw
+    pub fn weight(&self) -> u32 {
+        2 * self.wins + self.draws
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Position {
+    fen: String,                  //Without the move counters
+    moves: Vec<(u16, MoveCount)>, //Polyglot move code and its count
+}
+
+//Collects move statistics from games and turns them into an opening book
+#[derive(Clone, Debug)]
+pub struct BookBuilder {
+    options: BookOptions,
+    positions: HashMap<u64, Position>, //By Polyglot key
+    games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BookOptions) -> Self {
+        Self {
+            options,
+            positions: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    //Number of games added, including the ones none of the moves were taken from
+    pub fn games(&self) -> usize {
+        self.games
+    }
+    pub fn positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn add_game(&mut self, pgn: &Pgn) {
+        self.games += 1;
+        let result = match pgn.game.result {
+            GameResult::Ongoing => match pgn.tag("Result") {
+                Some("1-0") => GameResult::Won(Color::White),
+                Some("0-1") => GameResult::Won(Color::Black),
+                Some("1/2-1/2") => GameResult::Tied,
+                _ => GameResult::Ongoing,
+            },
+            result => result,
+        };
+        let counted = [
+            self.counts_moves_by(pgn, "White"),
+            self.counts_moves_by(pgn, "Black"),
+        ];
+        let game = &pgn.game;
+        for (ply, m) in game.moves.iter().enumerate().take(self.options.max_ply) {
+            let board = if ply == 0 {
+                &game.start
+            } else {
+                &game.history[ply - 1]
+            };
+            if !counted[board.to_act.index()] {
+                continue;
+            }
+            let position = self
+                .positions
+                .entry(polyglot_key(board))
+                .or_insert_with(|| Position {
+                    fen: board
+                        .to_fen()
+                        .split(' ')
+                        .take(4)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    moves: Vec::new(),
+                });
+            let code = encode_move(m);
+            let at = match position.moves.iter().position(|(c, _)| *c == code) {
+                Some(at) => at,
+                None => {
+                    position.moves.push((code, MoveCount::default()));
+                    position.moves.len() - 1
+                }
+            };
+            let count = &mut position.moves[at].1;
+            count.games += 1;
+            match result {
+                GameResult::Won(c) if c == board.to_act => count.wins += 1,
+                GameResult::Won(_) => count.losses += 1,
+                GameResult::Tied => count.draws += 1,
+                GameResult::Ongoing => (),
+            }
+        }
+    }
+
+    //Whether the player of the side, "White" or "Black", passes the player and rating filters
+    fn counts_moves_by(&self, pgn: &Pgn, side: &str) -> bool {
+        if !self.options.players.is_empty() {
+            match pgn.tag(side) {
+                Some(name) if self.options.players.iter().any(|p| p == name) => (),
+                _ => return false,
+            }
+        }
+        match self.options.min_rating {
+            Some(min) => pgn
+                .tag(&format!("{}Elo", side))
+                .and_then(|elo| elo.parse::<u32>().ok())
+                .is_some_and(|elo| elo >= min),
+            None => true,
+        }
+    }
+
+    //Adds every game of a PGN collection, games that cannot be read are skipped and returned
+    pub fn add_pgn(&mut self, input: impl BufRead) -> Vec<ImportError> {
+        let mut errors = Vec::new();
+        for pgn in PgnReader::new(input) {
+            match pgn {
+                Ok(pgn) => self.add_game(&pgn),
+                Err(e) => errors.push(e),
+            }
+        }
+        errors
+    }
+
+    //Moves played often enough in the position, most played first
+    pub fn moves(&self, board: &Board) -> Vec<(MoveData, MoveCount)> {
+        let position = match self.positions.get(&polyglot_key(board)) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        let mut moves: Vec<(MoveData, MoveCount)> = position
+            .moves
+            .iter()
+            .filter(|(_, count)| count.games >= self.options.min_games)
+            .filter_map(|&(code, count)| Some((decode_move(board, code)?, count)))
+            .collect();
+        moves.sort_by_key(|(_, count)| Reverse(count.games));
+        moves
+    }
+
+    //Moves that never scored anything get no entry, weights are scaled down to fit 16 bits
+    pub fn to_polyglot(&self) -> Book {
+        let mut entries = Vec::new();
+        for (&key, position) in self.positions.iter() {
+            let moves: Vec<&(u16, MoveCount)> = position
+                .moves
+                .iter()
+                .filter(|(_, count)| count.games >= self.options.min_games)
+                .collect();
+            let heaviest = moves.iter().map(|(_, c)| c.weight()).max().unwrap_or(0);
+            let scale = heaviest / u16::MAX as u32 + 1;
+            for (code, count) in moves {
+                let weight = (count.weight() / scale) as u16;
+                if weight > 0 {
+                    entries.push(BookEntry {
+                        key,
+                        move_code: *code,
+                        weight,
+                        learn: 0,
+                    });
+                }
+            }
+        }
+        //Heaviest first within a position, as Polyglot tools expect
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Book::from_entries(entries)
+    }
+
+    pub fn write_polyglot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_polyglot().to_bytes())
+    }
+
+    //Our own text format, one move per line with its position and count:
+    //<fen without move counters> <uci> <games> <wins> <draws> <losses>
+    pub fn to_text(&self) -> String {
+        let mut positions: Vec<&Position> = self.positions.values().collect();
+        positions.sort_by(|a, b| a.fen.cmp(&b.fen));
+        let mut text = String::new();
+        for position in positions {
+            let board = match Board::from_fen(&format!("{} 0 1", position.fen)) {
+                Ok(board) => board,
+                Err(_) => continue,
+            };
+            for (m, count) in self.moves(&board) {
+                writeln!(
+                    text,
+                    "{} {} {} {} {} {}",
+                    position.fen,
+                    m.to_uci(),
+                    count.games,
+                    count.wins,
+                    count.draws,
+                    count.losses
+                )
+                .unwrap();
+            }
+        }
+        text
+    }
+
+    pub fn write_text(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+}
+
+#[cfg(test)]
+mod test_bookbuilder {
+    use super::*;
+    const GAMES: &str = r#"[White "Anna"]
+[Black "Bert"]
+[WhiteElo "2100"]
+[BlackElo "1700"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[White "Bert"]
+[Black "Anna"]
+[WhiteElo "1700"]
+[BlackElo "2100"]
+[Result "1/2-1/2"]
+
+1. e4 c5 2. Nf3 1/2-1/2
+
+[White "Carl"]
+[Black "Anna"]
+[Result "0-1"]
+
+1. d4 d5 0-1
+
+[White "Anna"]
+[Black "Bert"]
+
+1. e4 e5 2. Qh5 *
+"#;
+    fn board(moves: &[&str]) -> Board {
+        let mut board = Board::new_standard();
+        for m in moves {
+            board.make_move(MoveData::from_uci(&board, m).unwrap());
+        }
+        board
+    }
+    fn count(games: u32, wins: u32, draws: u32, losses: u32) -> MoveCount {
+        MoveCount {
+            games,
+            wins,
+            draws,
+            losses,
+        }
+    }
+    #[test]
+    fn statistics() {
+        let mut builder = BookBuilder::new(BookOptions::default());
+        assert!(builder.add_pgn(GAMES.as_bytes()).is_empty());
+        assert_eq!(builder.games(), 4);
+        let start = builder.moves(&Board::new_standard());
+        assert_eq!(start[0].0.to_uci(), "e2e4");
+        assert_eq!(start[0].1, count(3, 1, 1, 0));
+        assert_eq!(start[1].1, count(1, 0, 0, 1));
+        let e5 = builder.moves(&board(&["e2e4"]));
+        assert_eq!(e5[0].0.to_uci(), "e7e5");
+        assert_eq!(e5[0].1, count(2, 0, 0, 1));
+
+        //Only the first two plies
+        let mut builder = BookBuilder::new(BookOptions {
+            max_ply: 2,
+            min_games: 2,
+            ..BookOptions::default()
+        });
+        builder.add_pgn(GAMES.as_bytes());
+        assert_eq!(builder.positions(), 3);
+        assert_eq!(builder.moves(&Board::new_standard()).len(), 1);
+        assert!(builder.moves(&board(&["d2d4"])).is_empty());
+    }
+    #[test]
+    fn player_filters() {
+        let mut builder = BookBuilder::new(BookOptions {
+            min_rating: Some(2000),
+            ..BookOptions::default()
+        });
+        builder.add_pgn(GAMES.as_bytes());
+        //Only Anna's rated games count, and only her moves
+        let start = builder.moves(&Board::new_standard());
+        assert_eq!(start.len(), 1);
+        assert_eq!(start[0].1, count(1, 1, 0, 0));
+        assert_eq!(builder.moves(&board(&["e2e4"]))[0].0.to_uci(), "c7c5");
+
+        let mut builder = BookBuilder::new(BookOptions {
+            players: vec!["Anna".to_string()],
+            ..BookOptions::default()
+        });
+        builder.add_pgn(GAMES.as_bytes());
+        assert_eq!(builder.moves(&board(&["d2d4"]))[0].1, count(1, 1, 0, 0));
+        assert!(builder.moves(&board(&["e2e4", "e7e5", "g1f3"])).is_empty());
+    }
+    #[test]
+    fn output() {
+        let mut builder = BookBuilder::new(BookOptions::default());
+        builder.add_pgn(GAMES.as_bytes());
+        let book = builder.to_polyglot();
+        let start = Board::new_standard();
+        //d4 lost its only game and gets no weight
+        assert_eq!(
+            book.moves(&start),
+            vec![(MoveData::from_uci(&start, "e2e4").unwrap(), 3)]
+        );
+        assert_eq!(book.best_move(&board(&["e2e4"])).unwrap().to_uci(), "c7c5");
+
+        let text = builder.to_text();
+        assert!(text
+            .lines()
+            .any(|l| l == "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - e2e4 3 1 1 0"));
+        assert_eq!(text.lines().count(), 9);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::color::Color;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+//Where the clock reads the time from, so tests can control it
+pub trait TimeSource: Send {
+    fn now(&self) -> Duration;
+}
+
+//Wall clock time since creation
+pub struct SystemTimeSource {
+    start: Instant,
+}
+
+impl SystemTimeSource {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemTimeSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+//Time that only moves when told to. Clones share the same time.
+#[derive(Clone, Default)]
+pub struct ManualTimeSource {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl ManualTimeSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl TimeSource for ManualTimeSource {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Timing {
+    SuddenDeath,
+    Increment(Duration), //Fischer, added after every move
+    Bronstein(Duration), //Gives back the time used, up to the delay
+    Delay(Duration),     //Simple (US) delay, the clock waits this long before running
+    Hourglass,           //Time used is added to the opponent
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Period {
+    pub moves: Option<u32>, //None for the rest of the game
+    pub time: Duration,
+    pub timing: Timing,
+}
+
+//Built by parse or the single period constructors, so there is always a first period
+#[derive(PartialEq, Clone, Debug)]
+pub struct TimeControl {
+    periods: Vec<Period>, //A last period with a move count repeats
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct TimeControlError(pub String);
+
+impl fmt::Display for TimeControlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid time control '{}'", self.0)
+    }
+}
+
+impl std::error::Error for TimeControlError {}
+
+impl TimeControl {
+    pub fn sudden_death(time: Duration) -> Self {
+        Self::single(time, Timing::SuddenDeath)
+    }
+    pub fn single(time: Duration, timing: Timing) -> Self {
+        Self {
+            periods: vec![Period {
+                moves: None,
+                time,
+                timing,
+            }],
+        }
+    }
+
+    //Periods separated by commas, each [moves/]minutes followed by +seconds for an increment,
+    //d for a simple delay or b for Bronstein, e.g. "5+3", "40/90+30, 30+30", "15d5".
+    //"hourglass 3" is three minutes of hourglass.
+    pub fn parse(text: &str) -> Result<Self, TimeControlError> {
+        let bad = || TimeControlError(text.to_string());
+        if let Some(minutes) = text.trim().strip_prefix("hourglass") {
+            let time = minutes_to_duration(minutes.trim()).ok_or_else(bad)?;
+            return Ok(Self::single(time, Timing::Hourglass));
+        }
+        let mut periods = Vec::new();
+        for part in text.split(',') {
+            let part = part.trim();
+            let (moves, rest) = match part.split_once('/') {
+                Some((m, r)) => (
+                    Some(m.parse::<u32>().ok().filter(|m| *m > 0).ok_or_else(bad)?),
+                    r,
+                ),
+                None => (None, part),
+            };
+            let (minutes, timing) = match rest.find(['+', 'd', 'b']) {
+                Some(i) => {
+                    let seconds: f64 = rest[i + 1..].parse().map_err(|_| bad())?;
+                    if seconds < 0.0 {
+                        return Err(bad());
+                    }
+                    let extra = Duration::from_secs_f64(seconds);
+                    let timing = match &rest[i..=i] {
+                        "+" => Timing::Increment(extra),
+                        "d" => Timing::Delay(extra),
+                        _ => Timing::Bronstein(extra),
+                    };
+                    (&rest[..i], timing)
+                }
+                None => (rest, Timing::SuddenDeath),
+            };
+            periods.push(Period {
+                moves,
+                time: minutes_to_duration(minutes).ok_or_else(bad)?,
+                timing,
+            });
+        }
+        Ok(Self { periods })
+    }
+
+    pub fn periods(&self) -> &[Period] {
+        &self.periods
+    }
+
+    fn period(&self, index: usize) -> &Period {
+        &self.periods[index.min(self.periods.len() - 1)]
+    }
+}
+
+fn minutes_to_duration(minutes: &str) -> Option<Duration> {
+    let minutes: f64 = minutes.parse().ok()?;
+    if minutes > 0.0 && minutes.is_finite() {
+        Some(Duration::from_secs_f64(minutes * 60.0))
+    } else {
+        None
+    }
+}
+
+//A two sided chess clock. Only the side to act has its time running.
+pub struct Clock {
+    control: TimeControl,
+    source: Box<dyn TimeSource>,
+    remaining: [Duration; 2], //At the start of the current turn, indexed White, Black
+    period: [usize; 2],
+    moves_in_period: [u32; 2],
+    to_act: Color,
+    turn_started: Option<Duration>, //None while stopped
+}
+
+impl Clock {
+    pub fn new(control: TimeControl, source: Box<dyn TimeSource>) -> Self {
+        let time = control.periods[0].time;
+        Self {
+            control,
+            source,
+            remaining: [time, time],
+            period: [0, 0],
+            moves_in_period: [0, 0],
+            to_act: Color::White,
+            turn_started: None,
+        }
+    }
+
+    //Runs color's time. If the clock was already running, the time used so far is charged
+    //to the side that had the move, without any increment.
+    pub fn start(&mut self, color: Color) {
+        if self.turn_started.is_some() {
+            self.charge();
+        }
+        self.to_act = color;
+        self.turn_started = Some(self.source.now());
+    }
+
+    pub fn stop(&mut self) {
+        if self.turn_started.is_some() {
+            self.charge();
+            self.turn_started = None;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.turn_started.is_some()
+    }
+
+    pub fn to_act(&self) -> Color {
+        self.to_act
+    }
+
+    fn elapsed(&self) -> Duration {
+        match self.turn_started {
+            Some(start) => self.source.now().saturating_sub(start),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    fn timing(&self, color: Color) -> Timing {
+        self.control.period(self.period[color.index()]).timing
+    }
+
+    //Time taken off the clock for the current turn so far
+    fn charged(&self) -> Duration {
+        match self.timing(self.to_act) {
+            Timing::Delay(delay) => self.elapsed().saturating_sub(delay),
+            _ => self.elapsed(),
+        }
+    }
+
+    fn charge(&mut self) {
+        let i = self.to_act.index();
+        let charged = self.charged();
+        if self.timing(self.to_act) == Timing::Hourglass {
+            self.remaining[1 - i] += charged;
+        }
+        self.remaining[i] = self.remaining[i].saturating_sub(charged);
+    }
+
+    //Time left for color right now
+    pub fn remaining(&self, color: Color) -> Duration {
+        let stored = self.remaining[color.index()];
+        if !self.is_running() {
+            stored
+        } else if color == self.to_act {
+            stored.saturating_sub(self.charged())
+        } else if self.timing(self.to_act) == Timing::Hourglass {
+            stored + self.charged()
+        } else {
+            stored
+        }
+    }
+
+    //The side whose time has run out, if any
+    pub fn flagged(&self) -> Option<Color> {
+        if self.is_running() && self.remaining(self.to_act) == Duration::from_secs(0) {
+            Some(self.to_act)
+        } else {
+            None
+        }
+    }
+
+    //Ends the turn of the side to act and starts the opponent's. Returns false without
+    //switching if the side to act had already run out of time.
+    pub fn press(&mut self) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        if self.flagged().is_some() {
+            self.stop();
+            return false;
+        }
+        let elapsed = self.elapsed();
+        let color = self.to_act;
+        let i = color.index();
+        self.charge();
+        match self.timing(color) {
+            Timing::Increment(extra) => self.remaining[i] += extra,
+            Timing::Bronstein(delay) => self.remaining[i] += elapsed.min(delay),
+            _ => (),
+        }
+        self.moves_in_period[i] += 1;
+        if Some(self.moves_in_period[i]) == self.control.period(self.period[i]).moves {
+            self.period[i] += 1;
+            self.moves_in_period[i] = 0;
+            self.remaining[i] += self.control.period(self.period[i]).time;
+        }
+        self.to_act = color.inverse();
+        self.turn_started = Some(self.source.now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_clock {
+    use super::*;
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+    fn started(control: &str) -> (Clock, ManualTimeSource) {
+        let time = ManualTimeSource::new();
+        let mut clock = Clock::new(TimeControl::parse(control).unwrap(), Box::new(time.clone()));
+        clock.start(Color::White);
+        (clock, time)
+    }
+    #[test]
+    fn parsing() {
+        let control = TimeControl::parse("40/90+30, 30+30").unwrap();
+        assert_eq!(
+            control.periods(),
+            vec![
+                Period {
+                    moves: Some(40),
+                    time: secs(90 * 60),
+                    timing: Timing::Increment(secs(30))
+                },
+                Period {
+                    moves: None,
+                    time: secs(30 * 60),
+                    timing: Timing::Increment(secs(30))
+                },
+            ]
+        );
+        assert_eq!(
+            TimeControl::parse("15d5").unwrap(),
+            TimeControl::single(secs(15 * 60), Timing::Delay(secs(5)))
+        );
+        assert_eq!(
+            TimeControl::parse("0.5b2").unwrap(),
+            TimeControl::single(secs(30), Timing::Bronstein(secs(2)))
+        );
+        assert_eq!(
+            TimeControl::parse("hourglass 1").unwrap(),
+            TimeControl::single(secs(60), Timing::Hourglass)
+        );
+        assert_eq!(
+            TimeControl::parse("3").unwrap(),
+            TimeControl::sudden_death(secs(180))
+        );
+        for bad in ["", "x+3", "0/5", "5+", "-1", "5+-2", "hourglass"].iter() {
+            assert!(TimeControl::parse(bad).is_err(), "{}", bad);
+        }
+    }
+    #[test]
+    fn sudden_death_and_increment() {
+        let (mut clock, time) = started("1+2");
+        time.advance(secs(10));
+        assert_eq!(clock.remaining(Color::White), secs(50));
+        assert_eq!(clock.remaining(Color::Black), secs(60));
+        assert!(clock.press());
+        assert_eq!(clock.remaining(Color::White), secs(52));
+        time.advance(secs(60));
+        assert_eq!(clock.flagged(), Some(Color::Black));
+        assert!(!clock.press());
+        assert!(!clock.is_running());
+    }
+    #[test]
+    fn delays() {
+        let (mut clock, time) = started("1d5");
+        time.advance(secs(3));
+        assert_eq!(clock.remaining(Color::White), secs(60));
+        time.advance(secs(4));
+        assert_eq!(clock.remaining(Color::White), secs(58));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), secs(58));
+
+        let (mut clock, time) = started("1b5");
+        time.advance(secs(3));
+        assert_eq!(clock.remaining(Color::White), secs(57));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), secs(60));
+        time.advance(secs(8));
+        clock.press();
+        assert_eq!(clock.remaining(Color::Black), secs(57));
+    }
+    #[test]
+    fn periods() {
+        let (mut clock, time) = started("2/1, 1");
+        for _ in 0..2 {
+            time.advance(secs(20));
+            clock.press();
+            clock.press();
+        }
+        //Two moves made, the second period is added
+        assert_eq!(clock.remaining(Color::White), secs(20 + 60));
+        assert_eq!(clock.remaining(Color::Black), secs(120));
+        //A repeating last period
+        let (mut clock, _) = started("1/1");
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), secs(120));
+    }
+    #[test]
+    fn hourglass() {
+        let (mut clock, time) = started("hourglass 1");
+        time.advance(secs(15));
+        assert_eq!(clock.remaining(Color::White), secs(45));
+        assert_eq!(clock.remaining(Color::Black), secs(75));
+        clock.press();
+        assert_eq!(clock.remaining(Color::Black), secs(75));
+        time.advance(secs(5));
+        assert_eq!(clock.remaining(Color::White), secs(50));
+    }
+    #[test]
+    fn stop_and_restart() {
+        let (mut clock, time) = started("1");
+        time.advance(secs(10));
+        clock.stop();
+        time.advance(secs(100));
+        assert_eq!(clock.remaining(Color::White), secs(50));
+        assert_eq!(clock.flagged(), None);
+        clock.start(Color::Black);
+        time.advance(secs(10));
+        clock.start(Color::White);
+        assert_eq!(clock.remaining(Color::Black), secs(50));
+    }
+}
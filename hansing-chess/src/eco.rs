@@ -0,0 +1,125 @@
+use crate::board::Board;
+use crate::game::Game;
+use crate::movedata::MoveData;
+use crate::zobrist::hash_board;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+//One opening per line: code, name and the moves leading to it, tab separated
+const ECO_TABLE: &str = include_str!("eco.tsv");
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+    pub moves: &'static str, //Movetext from the standard start
+}
+
+impl fmt::Display for Opening {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.eco, self.name)
+    }
+}
+
+//Every line of the table, in table order
+pub fn openings() -> impl Iterator<Item = Opening> {
+    ECO_TABLE.lines().filter_map(|line| {
+        let mut fields = line.split('\t');
+        Some(Opening {
+            eco: fields.next()?,
+            name: fields.next()?,
+            moves: fields.next()?,
+        })
+    })
+}
+
+//The position an opening's moves lead to, None if they are not legal
+pub fn opening_board(opening: &Opening) -> Option<Board> {
+    let mut board = Board::new_standard();
+    for san in opening.moves.split_whitespace() {
+        if san.ends_with('.') {
+            continue;
+        }
+        let m = MoveData::from_san(&board, san)?;
+        board.make_move(m);
+    }
+    Some(board)
+}
+
+//Openings by the hash of their position, so transpositions are found as well
+fn table() -> &'static HashMap<u64, Opening> {
+    static TABLE: OnceLock<HashMap<u64, Opening>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = HashMap::new();
+        for opening in openings() {
+            if let Some(board) = opening_board(&opening) {
+                //The first line for a position names it
+                table.entry(hash_board(&board)).or_insert(opening);
+            }
+        }
+        table
+    })
+}
+
+//The opening of exactly this position
+pub fn lookup(board: &Board) -> Option<Opening> {
+    table().get(&hash_board(board)).copied()
+}
+
+//The opening of the latest position in the game that has a name
+pub fn classify(game: &Game) -> Option<Opening> {
+    game.history
+        .iter()
+        .rev()
+        .chain(std::iter::once(&game.start))
+        .find_map(lookup)
+}
+
+#[cfg(test)]
+mod test_eco {
+    use super::*;
+    fn play(game: &mut Game, moves: &str) {
+        for san in moves.split_whitespace() {
+            let m = MoveData::from_san(&game.board, san).unwrap();
+            game.make_move(m);
+        }
+    }
+    #[test]
+    fn table_is_legal() {
+        for opening in openings() {
+            assert!(opening_board(&opening).is_some(), "{}", opening.moves);
+            assert_eq!(opening.eco.len(), 3);
+        }
+        assert!(openings().count() > 200);
+    }
+    #[test]
+    fn deepest_entry() {
+        let mut game = Game::new();
+        assert_eq!(classify(&game), None);
+        play(&mut game, "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6");
+        let najdorf = classify(&game).unwrap();
+        assert_eq!(najdorf.eco, "B90");
+        assert_eq!(najdorf.name, "Sicilian Defense: Najdorf Variation");
+        //Leaving the table keeps the last named position
+        play(&mut game, "h3");
+        assert_eq!(classify(&game), Some(najdorf));
+        assert_eq!(
+            najdorf.to_string(),
+            "B90 Sicilian Defense: Najdorf Variation"
+        );
+    }
+    #[test]
+    fn transposition() {
+        //The Queen's Gambit Declined reached through the English
+        let mut game = Game::new();
+        play(&mut game, "c4 e6 Nc3 d5 d4");
+        assert_eq!(classify(&game).unwrap().eco, "D31");
+        let mut game = Game::new();
+        play(&mut game, "Nf3 Nf6 c4 g6 Nc3 Bg7 d4 d6 e4 O-O Be2 e5");
+        assert_eq!(
+            classify(&game).unwrap().name,
+            "King's Indian Defense: Orthodox Variation"
+        );
+    }
+}
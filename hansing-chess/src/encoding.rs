@@ -0,0 +1,477 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::game::{Game, GameResult};
+use crate::movedata::{MoveData, MoveType};
+use crate::movegen::generate_action_space;
+use crate::piece::Piece;
+use crate::square::Square;
+use crate::title::Title;
+use std::fmt;
+
+//Move flags, stored in the top four bits of an encoded move
+const STANDARD: u16 = 0;
+const DOUBLE_STEP: u16 = 1;
+const CASTLING: u16 = 2;
+const EN_PASSANT: u16 = 3;
+const PROMOTION: u16 = 4; //Followed by Knight, Bishop, Rook and Queen, 4 itself is no choice
+
+//Piece codes besides color * 6 + title, the color follows from the rank they stand on
+const CASTLING_ROOK: u8 = 12; //A rook its king can still castle with
+const EN_PASSANT_PAWN: u8 = 13; //The pawn that just made a double step
+
+const PROMOTION_TITLES: [Title; 4] = [Title::Knight, Title::Bishop, Title::Rook, Title::Queen];
+const TITLES: [Title; 6] = [
+    Title::Pawn,
+    Title::Knight,
+    Title::Bishop,
+    Title::Rook,
+    Title::Queen,
+    Title::King,
+];
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum DecodeError {
+    Truncated,
+    BadPiece(u8),
+    BadMove(u16),
+    BadResult(u8),
+    IllegalMove { ply: usize, index: u32 }, //Index into the sorted legal moves
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "unexpected end of data"),
+            DecodeError::BadPiece(code) => write!(f, "invalid piece code {}", code),
+            DecodeError::BadMove(code) => write!(f, "invalid move code {:#06x}", code),
+            DecodeError::BadResult(code) => write!(f, "invalid result code {}", code),
+            DecodeError::IllegalMove { ply, index } => {
+                write!(f, "no legal move {} at ply {}", index, ply + 1)
+            }
+            DecodeError::TrailingBytes(n) => write!(f, "{} bytes left after the data", n),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum EncodeError {
+    IllegalMove { ply: usize, note: String }, //Not legal where it was played
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::IllegalMove { ply, note } => {
+                write!(f, "illegal move {} at ply {}", note, ply + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl MoveData {
+    //From and to square index in the low twelve bits, the move type flag above them.
+    //Castling rooks, en passant captures and double step squares follow from the squares.
+    pub fn to_u16(&self) -> u16 {
+        let flag = match self.move_type {
+            MoveType::Standard => STANDARD,
+            MoveType::DoubleStep(_) => DOUBLE_STEP,
+            MoveType::Casteling(_, _) => CASTLING,
+            MoveType::EnPassant(_) => EN_PASSANT,
+            MoveType::Promotion(None) => PROMOTION,
+            MoveType::Promotion(Some(title)) => {
+                PROMOTION
+                    + 1
+                    + PROMOTION_TITLES
+                        .iter()
+                        .position(|&t| t == title)
+                        .unwrap_or(3) as u16
+            }
+        };
+        self.from.index() as u16 | (self.to.index() as u16) << 6 | flag << 12
+    }
+
+    pub fn from_u16(code: u16) -> Option<Self> {
+        let from = Square::from_index((code & 63) as usize)?;
+        let to = Square::from_index((code >> 6 & 63) as usize)?;
+        let m = match code >> 12 {
+            STANDARD => Self::new_standard(from, to),
+            DOUBLE_STEP => {
+                let passed = Square::new((from.rank() + to.rank()) / 2, from.file())?;
+                Self::new_double_step(from, to, passed)
+            }
+            CASTLING => {
+                let king_side = to.file() > from.file();
+                let (rook_file, rook_to) = if king_side { (7, 5) } else { (0, 3) };
+                let rook_from = Square::new(from.rank(), rook_file)?;
+                Self::new_casteling(from, to, rook_from, Square::new(from.rank(), rook_to)?)
+            }
+            EN_PASSANT => Self::new_en_passant(from, to, Square::new(from.rank(), to.file())?),
+            PROMOTION => Self::new_promotion(from, to, None),
+            flag if flag <= PROMOTION + 4 => {
+                let title = PROMOTION_TITLES[(flag - PROMOTION - 1) as usize];
+                Self::new_promotion(from, to, Some(title))
+            }
+            _ => return None,
+        };
+        Some(m)
+    }
+}
+
+impl Board {
+    //An occupancy bitboard followed by a four bit code per piece, the side to move and
+    //the move counters. 27 bytes for the starting position and never more than 30 for
+    //a legal one. What is kept is what a FEN keeps, captured pieces are not.
+    pub fn to_compact(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(30);
+        let mut occupancy = 0u64;
+        let mut codes = Vec::with_capacity(32);
+        for i in 0..64 {
+            let s = Square::from_index(i).unwrap();
+            if let Some(p) = self.pieces.get(&s) {
+                occupancy |= 1 << i;
+                codes.push(self.piece_code(s, p));
+            }
+        }
+        bytes.extend_from_slice(&occupancy.to_le_bytes());
+        for pair in codes.chunks(2) {
+            bytes.push(pair[0] | pair.get(1).map_or(0, |c| c << 4));
+        }
+        bytes.push(self.to_act.index() as u8);
+        write_varint(&mut bytes, self.halfmove_clock);
+        write_varint(&mut bytes, self.fullmove_number);
+        bytes
+    }
+
+    pub fn from_compact(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let board = reader.board()?;
+        reader.finish()?;
+        Ok(board)
+    }
+
+    fn piece_code(&self, s: Square, p: &Piece) -> u8 {
+        let king_side = s.file() == 7;
+        let castling_rook = p.title == Title::Rook
+            && (s.file() == 0 || king_side)
+            && self.can_castle(p.color, king_side)
+            && s.rank() == if p.color == Color::White { 0 } else { 7 };
+        let en_passant_pawn =
+            p.title == Title::Pawn && self.en_passant == s.offset(-p.color.forward(), 0);
+        if castling_rook {
+            CASTLING_ROOK
+        } else if en_passant_pawn {
+            EN_PASSANT_PAWN
+        } else {
+            (p.color.index() * 6 + p.title.index()) as u8
+        }
+    }
+}
+
+impl Game {
+    //The start position, each move as its index among the legal moves ordered by their
+    //16 bit code, and the result. Most moves take a single byte. Fails if the moves were
+    //edited into something that cannot be replayed from the start position.
+    pub fn to_compact(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut bytes = Vec::new();
+        if self.start.to_fen() == Board::new_standard().to_fen() {
+            bytes.push(0);
+        } else {
+            bytes.push(1);
+            bytes.extend(self.start.to_compact());
+        }
+        write_varint(&mut bytes, self.moves.len() as u32);
+        let mut board = self.start.clone();
+        for (ply, m) in self.moves.iter().enumerate() {
+            let index = sorted_moves(&board)
+                .iter()
+                .position(|c| c == m)
+                .ok_or_else(|| EncodeError::IllegalMove {
+                    ply,
+                    note: m.to_uci(),
+                })?;
+            write_varint(&mut bytes, index as u32);
+            board.make_move(*m);
+        }
+        bytes.push(result_code(self.result));
+        Ok(bytes)
+    }
+
+    //A result not reached on the board, e.g. by resignation, is restored without a termination
+    pub fn from_compact(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let start = match reader.byte()? {
+            0 => Board::new_standard(),
+            _ => reader.board()?,
+        };
+        let mut game = Game::from_board(start);
+        for ply in 0..reader.varint()? as usize {
+            let index = reader.varint()?;
+            let m = sorted_moves(&game.board)
+                .get(index as usize)
+                .copied()
+                .ok_or(DecodeError::IllegalMove { ply, index })?;
+            game.make_move(m);
+        }
+        let code = reader.byte()?;
+        let result = result_from_code(code).ok_or(DecodeError::BadResult(code))?;
+        if game.result == GameResult::Ongoing {
+            game.result = result;
+        }
+        reader.finish()?;
+        Ok(game)
+    }
+}
+
+pub fn result_code(result: GameResult) -> u8 {
+    match result {
+        GameResult::Ongoing => 0,
+        GameResult::Won(Color::White) => 1,
+        GameResult::Won(Color::Black) => 2,
+        GameResult::Tied => 3,
+    }
+}
+
+pub fn result_from_code(code: u8) -> Option<GameResult> {
+    match code {
+        0 => Some(GameResult::Ongoing),
+        1 => Some(GameResult::Won(Color::White)),
+        2 => Some(GameResult::Won(Color::Black)),
+        3 => Some(GameResult::Tied),
+        _ => None,
+    }
+}
+
+fn sorted_moves(board: &Board) -> Vec<MoveData> {
+    let mut moves = generate_action_space(board.clone());
+    moves.sort_by_key(|m| m.to_u16());
+    moves
+}
+
+//Seven bits per byte, least significant first, the high bit set on all but the last
+pub fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let b = *self.bytes.get(self.pos).ok_or(DecodeError::Truncated)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn varint(&mut self) -> Result<u32, DecodeError> {
+        let mut value = 0u32;
+        for shift in (0..32).step_by(7) {
+            let b = self.byte()?;
+            value |= ((b & 0x7f) as u32) << shift;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Truncated)
+    }
+
+    fn finish(&self) -> Result<(), DecodeError> {
+        match self.bytes.len() - self.pos {
+            0 => Ok(()),
+            n => Err(DecodeError::TrailingBytes(n)),
+        }
+    }
+
+    //Pieces get has_moved the way Board::from_fen gives it
+    fn board(&mut self) -> Result<Board, DecodeError> {
+        let mut occupancy = [0; 8];
+        for b in occupancy.iter_mut() {
+            *b = self.byte()?;
+        }
+        let occupancy = u64::from_le_bytes(occupancy);
+        let squares: Vec<Square> = (0..64)
+            .filter(|i| occupancy & 1 << i != 0)
+            .map(|i| Square::from_index(i).unwrap())
+            .collect();
+        let mut codes = Vec::with_capacity(squares.len() + 1);
+        for _ in 0..squares.len().div_ceil(2) {
+            let b = self.byte()?;
+            codes.push(b & 15);
+            codes.push(b >> 4);
+        }
+
+        let mut board = Board::new_empty();
+        let mut castling = Vec::new(); //King squares of the castling rooks
+        for (&s, &code) in squares.iter().zip(codes.iter()) {
+            let piece = match code {
+                CASTLING_ROOK => {
+                    let color = if s.rank() == 0 {
+                        Color::White
+                    } else {
+                        Color::Black
+                    };
+                    castling.push(Square::new(s.rank(), 4).unwrap());
+                    Piece::new(color, Title::Rook)
+                }
+                EN_PASSANT_PAWN => {
+                    let color = if s.rank() == 3 {
+                        Color::White
+                    } else {
+                        Color::Black
+                    };
+                    board.en_passant = s.offset(-color.forward(), 0);
+                    let mut pawn = Piece::new(color, Title::Pawn);
+                    pawn.has_moved = true;
+                    pawn
+                }
+                code if code < 12 => {
+                    let color = if code < 6 { Color::White } else { Color::Black };
+                    let title = TITLES[code as usize % 6];
+                    let mut piece = Piece::new(color, title);
+                    piece.has_moved = match title {
+                        Title::Pawn => s.rank() != 7 - color.seventh_rank(),
+                        Title::King | Title::Rook => true,
+                        _ => false,
+                    };
+                    piece
+                }
+                code => return Err(DecodeError::BadPiece(code)),
+            };
+            board.pieces.insert(s, piece);
+        }
+        for king in castling.iter() {
+            if let Some(k) = board.pieces.get_mut(king) {
+                k.has_moved = false;
+            }
+        }
+
+        board.to_act = if self.byte()? == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        board.halfmove_clock = self.varint()?;
+        board.fullmove_number = self.varint()?;
+        board.update_status();
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod test_encoding {
+    use super::*;
+    #[test]
+    fn moves() {
+        let mut game = Game::new();
+        let mut seen = Vec::new();
+        let line = [
+            "e2e4", "g8f6", "e4e5", "d7d5", "e5d6", "e7d6", "g1f3", "f8e7", "f1c4", "e8g8", "e1g1",
+            "b8c6", "d2d4", "c8g4", "c1g5", "d8d7", "b1c3", "a8b8",
+        ];
+        for note in line.iter() {
+            let m = MoveData::from_uci(&game.board, note).unwrap();
+            seen.push(m);
+            game.make_move(m);
+        }
+        for m in seen.iter() {
+            assert_eq!(MoveData::from_u16(m.to_u16()), Some(*m));
+        }
+        let a7 = Square::from_notation("a7").unwrap();
+        let b8 = Square::from_notation("b8").unwrap();
+        for &promote_to in [None, Some(Title::Knight), Some(Title::Queen)].iter() {
+            let m = MoveData::new_promotion(a7, b8, promote_to);
+            assert_eq!(MoveData::from_u16(m.to_u16()), Some(m));
+        }
+        assert_eq!(MoveData::from_u16(0xf000), None);
+    }
+    #[test]
+    fn positions() {
+        let fens = [
+            crate::fen::STANDARD_FEN,
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 3 20",
+            "r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1",
+            "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1",
+            "4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 300",
+            "8/8/8/8/8/8/8/K6k w - - 120 70",
+        ];
+        for fen in fens.iter() {
+            let board = Board::from_fen(fen).unwrap();
+            let bytes = board.to_compact();
+            assert!(bytes.len() < 32);
+            let decoded = Board::from_compact(&bytes).unwrap();
+            assert_eq!(decoded.to_fen(), *fen);
+            assert_eq!(decoded.pieces, board.pieces);
+            assert_eq!(decoded.king_pos, board.king_pos);
+        }
+        assert_eq!(Board::new_standard().to_compact().len(), 27);
+        let bytes = Board::new_standard().to_compact();
+        assert_eq!(
+            Board::from_compact(&bytes[..20]).err(),
+            Some(DecodeError::Truncated)
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            Board::from_compact(&longer).err(),
+            Some(DecodeError::TrailingBytes(1))
+        );
+        let mut bad = bytes;
+        bad[8] = 0xee;
+        assert_eq!(
+            Board::from_compact(&bad).err(),
+            Some(DecodeError::BadPiece(14))
+        );
+    }
+    #[test]
+    fn games() {
+        let mut game = Game::new();
+        for note in ["f2f3", "e7e5", "g2g4", "d8h4"].iter() {
+            game.make_move(MoveData::from_uci(&game.board, note).unwrap());
+        }
+        let bytes = game.to_compact().unwrap();
+        assert_eq!(bytes.len(), 1 + 1 + 4 + 1);
+        let decoded = Game::from_compact(&bytes).unwrap();
+        assert_eq!(decoded.moves, game.moves);
+        assert_eq!(decoded.result, GameResult::Won(Color::Black));
+
+        let mut game = Game::from_board(Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap());
+        game.make_move(MoveData::from_uci(&game.board, "e1c1").unwrap());
+        game.resign(Color::Black).unwrap();
+        let decoded = Game::from_compact(&game.to_compact().unwrap()).unwrap();
+        assert_eq!(decoded.start.to_fen(), game.start.to_fen());
+        assert_eq!(decoded.moves, game.moves);
+        assert_eq!(decoded.result, GameResult::Won(Color::White));
+        assert_eq!(
+            Game::from_compact(&[0, 1, 200, 1, 0]).err(),
+            Some(DecodeError::IllegalMove { ply: 0, index: 200 })
+        );
+
+        //Moves pushed past make_move are refused rather than written as some other move
+        let mut game = Game::new();
+        game.moves.push(MoveData::new_standard(
+            Square::from_notation("e2").unwrap(),
+            Square::from_notation("e5").unwrap(),
+        ));
+        assert_eq!(
+            game.to_compact().err(),
+            Some(EncodeError::IllegalMove {
+                ply: 0,
+                note: "e2e5".to_string()
+            })
+        );
+    }
+}
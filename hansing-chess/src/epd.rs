@@ -0,0 +1,500 @@
+use crate::board::Board;
+use crate::fen::FenError;
+use crate::movedata::MoveData;
+use crate::search::{mate_in, SearchLimits, SearchResult, Searcher};
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum EpdError {
+    BadFen(FenError),
+    BadMove { opcode: String, san: String },
+    BadOperand { opcode: String, operand: String },
+    Unterminated, //A string operand without its closing quote
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::BadFen(e) => write!(f, "invalid position: {}", e),
+            EpdError::BadMove { opcode, san } => {
+                write!(f, "illegal move '{}' in {} operation", san, opcode)
+            }
+            EpdError::BadOperand { opcode, operand } => {
+                write!(f, "invalid operand '{}' in {} operation", operand, opcode)
+            }
+            EpdError::Unterminated => write!(f, "unterminated string operand"),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+//A position with its operations, e.g. a test suite entry
+#[derive(Clone, Debug)]
+pub struct Epd {
+    pub board: Board,
+    pub id: Option<String>,
+    pub best_moves: Vec<MoveData>,    //bm
+    pub avoid_moves: Vec<MoveData>,   //am
+    pub direct_mate: Option<i32>,     //dm, full moves
+    pub depth: Option<i32>,           //acd, analysis count depth
+    pub eval: Option<i32>,            //ce, centipawns for the side to move
+    pub pv: Vec<MoveData>,            //Predicted variation
+    pub comments: Vec<(u8, String)>,  //c0 to c9
+    pub other: Vec<(String, String)>, //Any other opcode with its operands as written
+}
+
+impl Epd {
+    pub fn new(board: Board) -> Self {
+        Self {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            direct_mate: None,
+            depth: None,
+            eval: None,
+            pv: Vec::new(),
+            comments: Vec::new(),
+            other: Vec::new(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self, EpdError> {
+        let mut rest = line.trim();
+        let mut fields = Vec::new();
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        let board = Board::from_fen(&fields.join(" ")).map_err(EpdError::BadFen)?;
+        let mut epd = Self::new(board);
+        for (opcode, operands) in operations(rest)? {
+            epd.apply(&opcode, operands)?;
+        }
+        Ok(epd)
+    }
+
+    fn apply(&mut self, opcode: &str, operands: Vec<String>) -> Result<(), EpdError> {
+        let bad_operand = |operand: &str| EpdError::BadOperand {
+            opcode: opcode.to_string(),
+            operand: operand.to_string(),
+        };
+        let number = |operands: &[String]| -> Result<i32, EpdError> {
+            let operand = operands.first().map(String::as_str).unwrap_or("");
+            operand.parse().map_err(|_| bad_operand(operand))
+        };
+        let counter = |operands: &[String]| -> Result<u32, EpdError> {
+            let operand = operands.first().map(String::as_str).unwrap_or("");
+            operand.parse().map_err(|_| bad_operand(operand))
+        };
+        match opcode {
+            "bm" => self.best_moves = self.moves(opcode, &operands, false)?,
+            "am" => self.avoid_moves = self.moves(opcode, &operands, false)?,
+            "pv" => self.pv = self.moves(opcode, &operands, true)?,
+            "dm" => self.direct_mate = Some(number(&operands)?),
+            "acd" => self.depth = Some(number(&operands)?),
+            "ce" => self.eval = Some(number(&operands)?),
+            "id" => self.id = Some(operands.join(" ")),
+            "hmvc" => self.board.halfmove_clock = counter(&operands)?,
+            "fmvn" => self.board.fullmove_number = counter(&operands)?,
+            c if c.len() == 2 && c.starts_with('c') && c.as_bytes()[1].is_ascii_digit() => {
+                self.comments
+                    .push((c.as_bytes()[1] - b'0', operands.join(" ")));
+            }
+            _ => {
+                let written: Vec<String> = operands.iter().map(|o| quote(o)).collect();
+                self.other.push((opcode.to_string(), written.join(" ")));
+            }
+        }
+        Ok(())
+    }
+
+    //Alternatives from the position, or a line played from it
+    fn moves(&self, opcode: &str, sans: &[String], line: bool) -> Result<Vec<MoveData>, EpdError> {
+        let mut board = self.board.clone();
+        let mut moves = Vec::new();
+        for san in sans {
+            let m = MoveData::from_san(&board, san).ok_or_else(|| EpdError::BadMove {
+                opcode: opcode.to_string(),
+                san: san.clone(),
+            })?;
+            if line {
+                board.make_move(m);
+            }
+            moves.push(m);
+        }
+        Ok(moves)
+    }
+
+    pub fn comment(&self, n: u8) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(c, _)| *c == n)
+            .map(|(_, text)| text.as_str())
+    }
+}
+
+//Splits on semicolons outside of quoted strings
+fn operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, std::mem::take(&mut tokens)));
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(EpdError::Unterminated),
+                    }
+                }
+                tokens.push(string);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    //The last semicolon is sometimes left out
+    if !tokens.is_empty() {
+        let opcode = tokens.remove(0);
+        operations.push((opcode, tokens));
+    }
+    Ok(operations)
+}
+
+fn quote(operand: &str) -> String {
+    if operand.is_empty() || operand.contains(char::is_whitespace) || operand.contains(';') {
+        format!("\"{}\"", operand)
+    } else {
+        operand.to_string()
+    }
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.board.placement_fen())?;
+        let sans = |moves: &[MoveData]| -> Vec<String> {
+            moves.iter().map(|m| m.to_san(&self.board)).collect()
+        };
+        if !self.best_moves.is_empty() {
+            write!(f, " bm {};", sans(&self.best_moves).join(" "))?;
+        }
+        if !self.avoid_moves.is_empty() {
+            write!(f, " am {};", sans(&self.avoid_moves).join(" "))?;
+        }
+        if let Some(n) = self.direct_mate {
+            write!(f, " dm {};", n)?;
+        }
+        if let Some(depth) = self.depth {
+            write!(f, " acd {};", depth)?;
+        }
+        if let Some(eval) = self.eval {
+            write!(f, " ce {};", eval)?;
+        }
+        if !self.pv.is_empty() {
+            let mut board = self.board.clone();
+            let mut line = Vec::new();
+            for m in self.pv.iter() {
+                line.push(m.to_san(&board));
+                board.make_move(*m);
+            }
+            write!(f, " pv {};", line.join(" "))?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, " id \"{}\";", id)?;
+        }
+        for (n, text) in self.comments.iter() {
+            write!(f, " c{} \"{}\";", n, text)?;
+        }
+        //Counters only when they differ from the FEN defaults
+        if self.board.halfmove_clock != 0 || self.board.fullmove_number != 1 {
+            write!(f, " hmvc {};", self.board.halfmove_clock)?;
+            write!(f, " fmvn {};", self.board.fullmove_number)?;
+        }
+        for (opcode, operands) in self.other.iter() {
+            match operands.as_str() {
+                "" => write!(f, " {};", opcode)?,
+                operands => write!(f, " {} {};", opcode, operands)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+//One record per line, blank lines and lines starting with # are skipped.
+//Errors come with their line number.
+pub fn parse_suite(text: &str) -> Result<Vec<Epd>, (usize, EpdError)> {
+    let mut suite = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        suite.push(Epd::parse(line).map_err(|e| (i + 1, e))?);
+    }
+    Ok(suite)
+}
+
+#[derive(Clone, Debug)]
+pub struct PositionResult {
+    pub id: Option<String>,
+    pub result: SearchResult,
+    pub time: Duration,
+    pub solved: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SuiteReport {
+    pub positions: Vec<PositionResult>,
+}
+
+impl SuiteReport {
+    pub fn solved(&self) -> usize {
+        self.positions.iter().filter(|p| p.solved).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.positions.len() - self.solved()
+    }
+}
+
+impl fmt::Display for SuiteReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, p) in self.positions.iter().enumerate() {
+            let number = (i + 1).to_string();
+            writeln!(
+                f,
+                "{:<12} {:<6} {:<7} depth {:>2} {:>10} nodes {:>6} ms",
+                p.id.as_deref().unwrap_or(&number),
+                if p.solved { "solved" } else { "failed" },
+                p.result.best_move.map_or("-".to_string(), |m| m.to_uci()),
+                p.result.depth,
+                p.result.nodes,
+                p.time.as_millis()
+            )?;
+        }
+        write!(
+            f,
+            "solved {} of {}, failed {}",
+            self.solved(),
+            self.positions.len(),
+            self.failed()
+        )
+    }
+}
+
+//A position is solved when the move found is among bm, is not among am and any dm mate
+//was found in time. Records with none of those only count as searched and solved.
+pub fn is_solved(epd: &Epd, result: &SearchResult) -> bool {
+    let best = match result.best_move {
+        Some(m) => m,
+        None => return false,
+    };
+    (epd.best_moves.is_empty() || epd.best_moves.contains(&best))
+        && !epd.avoid_moves.contains(&best)
+        && match epd.direct_mate {
+            Some(n) => matches!(mate_in(result.score), Some(m) if m > 0 && m <= n),
+            None => true,
+        }
+}
+
+//Searches every position to the limits and, if given, for at most the time each.
+//The searcher's table is kept between positions, as when playing a game.
+pub fn run_suite(
+    suite: &[Epd],
+    searcher: &mut Searcher,
+    limits: &SearchLimits,
+    time: Option<Duration>,
+    report: &mut dyn FnMut(&PositionResult),
+) -> SuiteReport {
+    let mut suite_report = SuiteReport::default();
+    for epd in suite {
+        let start = Instant::now();
+        let result = timed_search(searcher, &epd.board, limits, time);
+        let position = PositionResult {
+            id: epd.id.clone(),
+            solved: is_solved(epd, &result),
+            result,
+            time: start.elapsed(),
+        };
+        report(&position);
+        suite_report.positions.push(position);
+    }
+    suite_report
+}
+
+fn timed_search(
+    searcher: &mut Searcher,
+    board: &Board,
+    limits: &SearchLimits,
+    time: Option<Duration>,
+) -> SearchResult {
+    let stop = searcher.stop_flag();
+    stop.store(false, Ordering::Relaxed);
+    //Dropping the sender ends the timer early once the search is done
+    let (done, timer) = match time {
+        Some(time) => {
+            let (done, waiting) = mpsc::channel::<()>();
+            let flag = stop.clone();
+            let timer = thread::spawn(move || {
+                if waiting.recv_timeout(time) == Err(RecvTimeoutError::Timeout) {
+                    flag.store(true, Ordering::Relaxed);
+                }
+            });
+            (Some(done), Some(timer))
+        }
+        None => (None, None),
+    };
+    let result = searcher.search_with(board, limits, &mut |_| ());
+    drop(done);
+    if let Some(timer) = timer {
+        timer.join().ok();
+    }
+    stop.store(false, Ordering::Relaxed);
+    result
+}
+
+#[cfg(test)]
+mod test_epd {
+    use super::*;
+    use crate::title::Title;
+    #[test]
+    fn parse_and_write() {
+        let line = "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; am Ra7 Rb1;id \"mate.1\"; \
+                    c0 \"back rank; easy\"; dm 1; acd 4; ce 29999; pv Ra8+; xyz; noise \"a b\" c";
+        let epd = Epd::parse(line).unwrap();
+        assert_eq!(epd.board.to_fen(), "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(epd.best_moves.len(), 1);
+        assert_eq!(epd.best_moves[0].to_uci(), "a1a8");
+        assert_eq!(epd.avoid_moves.len(), 2);
+        assert_eq!(epd.id.as_deref(), Some("mate.1"));
+        assert_eq!(epd.comment(0), Some("back rank; easy"));
+        assert_eq!(epd.comment(1), None);
+        assert_eq!(
+            (epd.direct_mate, epd.depth, epd.eval),
+            (Some(1), Some(4), Some(29999))
+        );
+        assert_eq!(epd.pv, epd.best_moves);
+        assert_eq!(
+            epd.to_string(),
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; am Ra7 Rb1; dm 1; acd 4; ce 29999; \
+             pv Ra8#; id \"mate.1\"; c0 \"back rank; easy\"; xyz; noise \"a b\" c;"
+        );
+        let again = Epd::parse(&epd.to_string()).unwrap();
+        assert_eq!(again.to_string(), epd.to_string());
+
+        let counters = Epd::parse("8/8/8/8/8/8/8/K6k b - - hmvc 12; fmvn 40;").unwrap();
+        assert_eq!(counters.board.to_fen(), "8/8/8/8/8/8/8/K6k b - - 12 40");
+        assert_eq!(
+            counters.to_string(),
+            "8/8/8/8/8/8/8/K6k b - - hmvc 12; fmvn 40;"
+        );
+        let promotion = Epd::parse("8/P7/8/8/8/8/8/K6k w - - bm a8=N").unwrap();
+        assert!(matches!(
+            promotion.best_moves[0].move_type,
+            crate::movedata::MoveType::Promotion(Some(Title::Knight))
+        ));
+    }
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            Epd::parse("8/8/8 w - - bm e4;"),
+            Err(EpdError::BadFen(_))
+        ));
+        assert_eq!(
+            Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Rh8;").err(),
+            Some(EpdError::BadMove {
+                opcode: "bm".to_string(),
+                san: "Rh8".to_string()
+            })
+        );
+        assert_eq!(
+            Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - dm one;").err(),
+            Some(EpdError::BadOperand {
+                opcode: "dm".to_string(),
+                operand: "one".to_string()
+            })
+        );
+        assert_eq!(
+            Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - hmvc -1;").err(),
+            Some(EpdError::BadOperand {
+                opcode: "hmvc".to_string(),
+                operand: "-1".to_string()
+            })
+        );
+        assert_eq!(
+            Epd::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - id \"open;").err(),
+            Some(EpdError::Unterminated)
+        );
+        let suite = "# a comment\n\n6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#;\nnonsense\n";
+        assert!(matches!(parse_suite(suite), Err((4, EpdError::BadFen(_)))));
+    }
+    #[test]
+    fn runner() {
+        let suite = parse_suite(
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"found\";\n\
+             6k1/5ppp/8/8/8/8/8/R5K1 w - - am Ra8#; id \"avoided\";\n\
+             6k1/5ppp/8/8/8/8/8/R5K1 w - - dm 1; id \"mate\";\n\
+             6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Rb1; id \"wrong\";\n",
+        )
+        .unwrap();
+        let mut searcher = Searcher::new(1);
+        let mut seen = 0;
+        let report = run_suite(
+            &suite,
+            &mut searcher,
+            &SearchLimits::depth(2),
+            None,
+            &mut |_| seen += 1,
+        );
+        assert_eq!(seen, 4);
+        let solved: Vec<bool> = report.positions.iter().map(|p| p.solved).collect();
+        assert_eq!(solved, vec![true, false, true, false]);
+        assert_eq!((report.solved(), report.failed()), (2, 2));
+        let text = report.to_string();
+        assert!(text
+            .lines()
+            .next()
+            .unwrap()
+            .starts_with("found        solved a1a8"));
+        assert!(text.ends_with("solved 2 of 4, failed 2"));
+
+        //A time limit stops an otherwise unbounded search
+        let start = Instant::now();
+        let report = run_suite(
+            &suite[..1],
+            &mut searcher,
+            &SearchLimits::default(),
+            Some(Duration::from_millis(200)),
+            &mut |_| (),
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(report.positions[0].solved);
+        assert!(!searcher.stop_flag().load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::board::Board;
+use crate::title::Title;
+
+//Static evaluation in centipawns from the perspective of the player to act
+pub fn evaluate(board: &Board) -> i32 {
+    let mut score = 0;
+    for p in board.pieces.values() {
+        if p.title == Title::King {
+            continue;
+        }
+        if p.color == board.to_act {
+            score += p.title.value();
+        } else {
+            score -= p.title.value();
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod test_eval {
+    use super::*;
+    use crate::movedata::MoveData;
+    use crate::square::Square;
+    #[test]
+    fn standard_is_even() {
+        assert_eq!(evaluate(&Board::new_standard()), 0);
+    }
+    #[test]
+    fn perspective() {
+        let mut board = Board::new_standard();
+        let d1 = Square::from_notation("d1").unwrap();
+        let d7 = Square::from_notation("d7").unwrap();
+        board.make_move(MoveData::new_standard(d1, d7));
+        assert_eq!(evaluate(&board), -Title::Pawn.value());
+    }
+}
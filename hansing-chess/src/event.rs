@@ -0,0 +1,71 @@
+use crate::color::Color;
+use crate::game::GameResult;
+use crate::movedata::MoveData;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum GameEvent {
+    MoveMade(MoveData),
+    Check(Color), //The side now in check
+    GameOver(GameResult),
+    Undo(MoveData), //The move taken back
+    DrawOffered(Color),
+    ClockTick { white: Duration, black: Duration },
+    Flagged(Color),
+}
+
+pub type Listener = Box<dyn FnMut(&GameEvent) + Send>;
+
+//Hands every event to the registered callbacks and channels, in the order they were added
+#[derive(Default)]
+pub struct EventBus {
+    listeners: Vec<Listener>,
+    senders: Vec<Sender<GameEvent>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
+    //A channel receiving every event from now on, dropped receivers are forgotten
+    pub fn channel(&mut self) -> Receiver<GameEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    pub fn emit(&mut self, event: GameEvent) {
+        for listener in self.listeners.iter_mut() {
+            listener(&event);
+        }
+        self.senders.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test_event {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    #[test]
+    fn listeners_and_channels() {
+        let mut bus = EventBus::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        bus.subscribe(Box::new(move |e| log.lock().unwrap().push(e.clone())));
+        let receiver = bus.channel();
+        let dropped = bus.channel();
+        drop(dropped);
+        bus.emit(GameEvent::Flagged(Color::White));
+        bus.emit(GameEvent::GameOver(GameResult::Won(Color::Black)));
+        assert_eq!(bus.senders.len(), 1);
+        let drained: Vec<GameEvent> = receiver.try_iter().collect();
+        assert_eq!(drained, *seen.lock().unwrap());
+        assert_eq!(drained[0], GameEvent::Flagged(Color::White));
+    }
+}
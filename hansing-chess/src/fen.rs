@@ -0,0 +1,293 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::piece::Piece;
+use crate::square::Square;
+use crate::title::Title;
+use std::fmt;
+
+pub const STANDARD_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum FenError {
+    MissingField(&'static str),
+    BadPlacement(String),
+    BadSideToMove(String),
+    BadCastling(String),
+    BadEnPassant(String),
+    BadCounter(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "FEN is missing the {} field", field),
+            FenError::BadPlacement(s) => write!(f, "invalid piece placement '{}'", s),
+            FenError::BadSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::BadCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::BadEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::BadCounter(s) => write!(f, "invalid move counter '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+pub fn piece_from_char(c: char) -> Option<Piece> {
+    let color = if c.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let title = match c.to_ascii_lowercase() {
+        'p' => Title::Pawn,
+        'n' => Title::Knight,
+        'b' => Title::Bishop,
+        'r' => Title::Rook,
+        'q' => Title::Queen,
+        'k' => Title::King,
+        _ => return None,
+    };
+    Some(Piece::new(color, title))
+}
+
+impl Board {
+    //Castling rights and pawn double steps are stored as has_moved on the pieces involved.
+    //The move counters are optional, as in EPD.
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or(FenError::MissingField("placement"))?;
+        let side = fields
+            .next()
+            .ok_or(FenError::MissingField("side to move"))?;
+        let castling = fields.next().ok_or(FenError::MissingField("castling"))?;
+        let en_passant = fields.next().ok_or(FenError::MissingField("en passant"))?;
+
+        let mut board = Board::new_empty();
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::BadPlacement(placement.to_string()));
+        }
+        for (i, row) in ranks.iter().enumerate() {
+            let rank = 7 - i as i32;
+            let mut file = 0;
+            for c in row.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as i32;
+                    continue;
+                }
+                let mut piece = piece_from_char(c)
+                    .ok_or_else(|| FenError::BadPlacement(placement.to_string()))?;
+                let s = Square::new(rank, file)
+                    .ok_or_else(|| FenError::BadPlacement(placement.to_string()))?;
+                piece.has_moved = match piece.title {
+                    Title::Pawn => rank != 7 - piece.color.seventh_rank(),
+                    Title::King | Title::Rook => true,
+                    _ => false,
+                };
+                board.pieces.insert(s, piece);
+                file += 1;
+            }
+            if file != 8 {
+                return Err(FenError::BadPlacement(placement.to_string()));
+            }
+        }
+
+        board.to_act = match side {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(FenError::BadSideToMove(side.to_string())),
+        };
+
+        if castling != "-" {
+            for c in castling.chars() {
+                let (color, rook_file) = match c {
+                    'K' => (Color::White, 7),
+                    'Q' => (Color::White, 0),
+                    'k' => (Color::Black, 7),
+                    'q' => (Color::Black, 0),
+                    _ => return Err(FenError::BadCastling(castling.to_string())),
+                };
+                let back_rank = if color == Color::White { 0 } else { 7 };
+                for &file in [4, rook_file].iter() {
+                    let s = Square::new(back_rank, file).unwrap();
+                    match board.pieces.get_mut(&s) {
+                        Some(p) if p.color == color && (file == 4) == (p.title == Title::King) => {
+                            p.has_moved = false
+                        }
+                        _ => return Err(FenError::BadCastling(castling.to_string())),
+                    }
+                }
+            }
+        }
+
+        if en_passant != "-" {
+            board.en_passant = Some(
+                Square::from_notation(en_passant)
+                    .ok_or_else(|| FenError::BadEnPassant(en_passant.to_string()))?,
+            );
+        }
+
+        if let Some(halfmove) = fields.next() {
+            board.halfmove_clock = halfmove
+                .parse()
+                .map_err(|_| FenError::BadCounter(halfmove.to_string()))?;
+        }
+        if let Some(fullmove) = fields.next() {
+            board.fullmove_number = fullmove
+                .parse()
+                .map_err(|_| FenError::BadCounter(fullmove.to_string()))?;
+        }
+
+        board.update_status();
+        Ok(board)
+    }
+
+    pub fn to_fen(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.placement_fen(),
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    //The first four FEN fields, which is also the position part of an EPD record
+    pub fn placement_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.pieces.get(&Square::new(rank, file).unwrap()) {
+                    Some(p) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(p.to_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if self.to_act == Color::White {
+            " w "
+        } else {
+            " b "
+        });
+
+        let mut castling = String::new();
+        for &(c, king_side, sign) in [
+            (Color::White, true, 'K'),
+            (Color::White, false, 'Q'),
+            (Color::Black, true, 'k'),
+            (Color::Black, false, 'q'),
+        ]
+        .iter()
+        {
+            if self.can_castle(c, king_side) {
+                castling.push(sign);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        match self.en_passant {
+            Some(s) => fen.push_str(&format!(" {}", s)),
+            None => fen.push_str(" -"),
+        }
+        fen
+    }
+}
+
+#[cfg(test)]
+mod test_fen {
+    use super::*;
+    use crate::movedata::MoveData;
+    use crate::movegen::generate_action_space;
+    #[test]
+    fn standard_round_trip() {
+        let board = Board::from_fen(STANDARD_FEN).unwrap();
+        assert_eq!(board.to_fen(), STANDARD_FEN);
+        assert_eq!(Board::new_standard().to_fen(), STANDARD_FEN);
+        assert_eq!(board.pieces, Board::new_standard().pieces);
+    }
+    #[test]
+    fn after_moves() {
+        let mut board = Board::new_standard();
+        let sq = |s: &str| Square::from_notation(s).unwrap();
+        board.make_move(MoveData::new_double_step(sq("e2"), sq("e4"), sq("e3")));
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        board.make_move(MoveData::new_standard(sq("g8"), sq("f6")));
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"
+        );
+    }
+    #[test]
+    fn partial_castling_and_moved_pawns() {
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 20";
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.to_fen(), fen);
+        assert!(board.can_castle(Color::White, true));
+        assert!(!board.can_castle(Color::White, false));
+        let moves = generate_action_space(board.clone());
+        let e5 = Square::from_notation("e5").unwrap();
+        //En passant is available, double steps are not
+        assert!(moves
+            .iter()
+            .any(|m| m.from == e5 && m.to == Square::from_notation("d6").unwrap()));
+        assert!(!moves
+            .iter()
+            .any(|m| m.from == e5 && m.to == Square::from_notation("e7").unwrap()));
+    }
+    #[test]
+    fn optional_counters() {
+        let board = Board::from_fen("8/8/8/8/8/8/8/K6k w - -").unwrap();
+        assert_eq!(board.halfmove_clock, 0);
+        assert_eq!(board.fullmove_number, 1);
+    }
+    #[test]
+    fn in_check_is_detected() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K2r w - - 0 1").unwrap();
+        assert!(board.in_check);
+    }
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Board::from_fen("8/8/8 w - - 0 1").unwrap_err(),
+            FenError::BadPlacement("8/8/8".to_string())
+        );
+        assert!(matches!(
+            Board::from_fen("8/8/8/8/8/8/8/K6k x - - 0 1"),
+            Err(FenError::BadSideToMove(_))
+        ));
+        assert!(matches!(
+            Board::from_fen("8/8/8/8/8/8/8/K6k w K - 0 1"),
+            Err(FenError::BadCastling(_))
+        ));
+        assert!(matches!(
+            Board::from_fen("8/8/8/8/8/8/8/K6k w - z9 0 1"),
+            Err(FenError::BadEnPassant(_))
+        ));
+        assert!(matches!(
+            Board::from_fen("8/8/8/8/8/8/8/K6k w"),
+            Err(FenError::MissingField(_))
+        ));
+        assert!(matches!(
+            Board::from_fen("8/8/8/8/8/8/8/K6k w - - x 1"),
+            Err(FenError::BadCounter(_))
+        ));
+    }
+}
@@ -1,79 +1,365 @@
 use crate::board::Board;
+use crate::clock::Clock;
 use crate::color::Color;
-use crate::movedata::MoveData;
+use crate::event::{EventBus, GameEvent, Listener};
+use crate::matepattern::{classify, Classification};
+use crate::movedata::{MoveData, MoveType};
 use crate::movegen::*;
 use crate::square::Square;
 use crate::title::Title;
+use std::fmt;
+use std::sync::mpsc::Receiver;
 pub struct Game {
     pub board: Board,
     pub action_space: Vec<MoveData>,
-    pub history: Vec<Board>,
+    pub history: Vec<Board>, //Position after each move
+    pub moves: Vec<MoveData>,
+    pub undone: Vec<MoveData>, //Taken back moves that can be redone, latest last
+    pub start: Board,
     pub result: GameResult,
+    pub termination: Option<Termination>, //How the game ended, None while ongoing
+    pub mate_patterns: Vec<Classification>, //Of the checkmate that ended the game, else empty
+    pub draw_offer: Option<Color>,         //Pending offer and who made it
+    pub actions: Vec<(usize, Action)>,     //With the number of moves played at the time
+    pub default_promotion: Title,
+    pub clock: Option<Clock>,
+    pub events: EventBus,
 }
 
 impl Game {
     pub fn new() -> Self {
+        Self::from_board(Board::new_standard())
+    }
+    //Game starting from any position, e.g. one loaded from FEN
+    pub fn from_board(board: Board) -> Self {
         let mut game = Self {
-            board: Board::new_standard(),
+            board: board.clone(),
             action_space: Vec::new(),
             history: Vec::new(),
+            moves: Vec::new(),
+            undone: Vec::new(),
+            start: board,
             result: GameResult::Ongoing,
+            termination: None,
+            mate_patterns: Vec::new(),
+            draw_offer: None,
+            actions: Vec::new(),
+            default_promotion: Title::Queen,
+            clock: None,
+            events: EventBus::new(),
         };
         game.action_space = generate_action_space(game.board.clone());
+        game.check_for_game_over();
         game
     }
-    pub fn set_deafault_promotion(&mut self, title: Title) {
-        unsafe {
-            //oof
-            PROMOTE_TO = title;
+    pub fn subscribe(&mut self, listener: Listener) {
+        self.events.subscribe(listener);
+    }
+
+    //Events from now on, to be drained by the caller
+    pub fn event_channel(&mut self) -> Receiver<GameEvent> {
+        self.events.channel()
+    }
+
+    //Reports the time left on the clock and ends the game on a flag fall.
+    //Meant to be called regularly, e.g. once per frame.
+    pub fn tick(&mut self) {
+        if let Some(clock) = self.clock.as_ref() {
+            if clock.is_running() {
+                let white = clock.remaining(Color::White);
+                let black = clock.remaining(Color::Black);
+                self.events.emit(GameEvent::ClockTick { white, black });
+            }
+        }
+        self.check_time();
+    }
+
+    //Attaches a clock and starts it for the side to move
+    pub fn set_clock(&mut self, mut clock: Clock) {
+        if self.result == GameResult::Ongoing {
+            clock.start(self.board.to_act);
+        }
+        self.clock = Some(clock);
+    }
+
+    //Ends the game if the side to move has run out of time. The opponent wins, or it is a
+    //draw if the opponent could not possibly mate.
+    pub fn check_time(&mut self) -> GameResult {
+        if self.result != GameResult::Ongoing {
+            return self.result;
+        }
+        let flagged = self.clock.as_ref().and_then(|c| c.flagged());
+        if let Some(color) = flagged {
+            self.events.emit(GameEvent::Flagged(color));
+            let result = if self.board.has_mating_material(color.inverse()) {
+                GameResult::Won(color.inverse())
+            } else {
+                GameResult::Tied
+            };
+            self.end(result, Termination::Timeout);
+        }
+        self.result
+    }
+
+    //Either side may resign at any time while the game is ongoing
+    pub fn resign(&mut self, color: Color) -> Result<(), ActionError> {
+        self.check_ongoing()?;
+        self.end(GameResult::Won(color.inverse()), Termination::Resignation);
+        self.actions.push((self.moves.len(), Action::Resign(color)));
+        Ok(())
+    }
+
+    //Offers are made on the offering side's own turn and stand until the opponent moves
+    pub fn offer_draw(&mut self, color: Color) -> Result<(), ActionError> {
+        self.check_ongoing()?;
+        self.check_turn(color)?;
+        match self.draw_offer {
+            Some(c) if c == color => return Err(ActionError::AlreadyOffered),
+            Some(_) => return Err(ActionError::OfferPending),
+            None => (),
+        }
+        self.draw_offer = Some(color);
+        self.actions.push((self.moves.len(), Action::OfferDraw(color)));
+        self.events.emit(GameEvent::DrawOffered(color));
+        Ok(())
+    }
+
+    pub fn accept_draw(&mut self, color: Color) -> Result<(), ActionError> {
+        self.check_response(color)?;
+        self.draw_offer = None;
+        self.end(GameResult::Tied, Termination::Agreement);
+        self.actions.push((self.moves.len(), Action::AcceptDraw(color)));
+        Ok(())
+    }
+
+    pub fn decline_draw(&mut self, color: Color) -> Result<(), ActionError> {
+        self.check_response(color)?;
+        self.draw_offer = None;
+        self.actions.push((self.moves.len(), Action::DeclineDraw(color)));
+        Ok(())
+    }
+
+    //An arbiter's decision, e.g. for an unfinished game or an illegal move
+    pub fn adjudicate(&mut self, result: GameResult) -> Result<(), ActionError> {
+        self.check_ongoing()?;
+        if result == GameResult::Ongoing {
+            return Err(ActionError::NoResult);
         }
+        self.draw_offer = None;
+        self.end(result, Termination::Adjudication);
+        self.actions.push((self.moves.len(), Action::Adjudicate(result)));
+        Ok(())
     }
 
+    fn check_ongoing(&mut self) -> Result<(), ActionError> {
+        if self.check_time() == GameResult::Ongoing {
+            Ok(())
+        } else {
+            Err(ActionError::GameOver)
+        }
+    }
+
+    fn check_turn(&self, color: Color) -> Result<(), ActionError> {
+        if color == self.board.to_act {
+            Ok(())
+        } else {
+            Err(ActionError::NotYourTurn)
+        }
+    }
+
+    //Answering an offer is done on one's own turn, after the offering side has moved
+    fn check_response(&mut self, color: Color) -> Result<(), ActionError> {
+        self.check_ongoing()?;
+        if self.draw_offer != Some(color.inverse()) {
+            return Err(ActionError::NoOffer);
+        }
+        self.check_turn(color)
+    }
+
+    fn end(&mut self, result: GameResult, termination: Termination) {
+        self.result = result;
+        self.termination = Some(termination);
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop();
+        }
+        self.events.emit(GameEvent::GameOver(result));
+    }
+
+    pub fn set_deafault_promotion(&mut self, title: Title) {
+        self.default_promotion = title;
+    }
+
+    //The legal move between two squares, promoting to the default promotion if there is a choice
+    pub fn find_move(&self, from: Square, to: Square) -> Option<MoveData> {
+        let preferred = MoveType::Promotion(Some(self.default_promotion));
+        let candidates: Vec<&MoveData> = self
+            .action_space
+            .iter()
+            .filter(|m| m.from == from && m.to == to)
+            .collect();
+        candidates
+            .iter()
+            .find(|m| m.move_type == preferred)
+            .or_else(|| candidates.first())
+            .map(|m| **m)
+    }
+
+    //Moves made after the mover's time ran out are not played, the game is lost on time instead
     pub fn make_move(&mut self, m: MoveData) {
+        if self.check_time() != GameResult::Ongoing {
+            return;
+        }
         if self.action_space.contains(&m) {
+            if self.draw_offer == Some(self.board.to_act.inverse()) {
+                self.draw_offer = None;
+            }
             self.board.make_move(m);
             self.action_space = generate_action_space(self.board.clone());
-            self.check_for_game_over();
             self.history.push(self.board.clone());
+            self.moves.push(m);
+            self.undone.clear();
+            if let Some(clock) = self.clock.as_mut() {
+                clock.press();
+            }
+            self.events.emit(GameEvent::MoveMade(m));
+            if self.board.in_check {
+                self.events.emit(GameEvent::Check(self.board.to_act));
+            }
+            self.check_for_game_over();
         }
     }
 
-    
+    //With a clock, the time already used stays used and the clock is handed to the side to move.
+    //Actions taken in the position being returned to or later are dropped.
     pub fn undo_move(&mut self) {
-        if self.history.len() != 0 {
-            let i = self.history.len() - 2;
-            self.board = self.history[i].clone();
-            self.history.remove(i + 1);
+        if self.history.pop().is_some() {
+            let m = self.moves.pop().unwrap();
+            self.undone.push(m);
+            self.board = self.history.last().unwrap_or(&self.start).clone();
+            self.action_space = generate_action_space(self.board.clone());
+            self.result = GameResult::Ongoing;
+            self.termination = None;
+            self.mate_patterns.clear();
+            let played = self.moves.len();
+            self.actions.retain(|(ply, _)| *ply < played);
+            self.draw_offer = None;
+            if let Some(clock) = self.clock.as_mut() {
+                clock.start(self.board.to_act);
+            }
+            self.events.emit(GameEvent::Undo(m));
+        }
+    }
+
+    //Plays the last undone move again, returns false if there is nothing to redo
+    pub fn redo_move(&mut self) -> bool {
+        match self.undone.pop() {
+            Some(m) => {
+                let rest = std::mem::take(&mut self.undone);
+                self.make_move(m);
+                self.undone = rest;
+                true
+            }
+            None => false,
         }
     }
 
     pub fn make_move_from_notation(&mut self, note: String) {
-        for m in self.action_space.clone().iter() {
-            if m.get_move_notation() == note {
-                self.make_move(*m);
+        let found = self
+            .action_space
+            .iter()
+            .find(|m| m.get_move_notation() == note)
+            .copied();
+        if let Some(m) = found {
+            if let Some(m) = self.find_move(m.from, m.to) {
+                self.make_move(m);
             }
         }
     }
 
     pub fn check_for_game_over(&mut self) {
-        if self.action_space.len() == 0 {
+        if self.action_space.is_empty() && self.result == GameResult::Ongoing {
             if self.board.in_check {
-                self.result = GameResult::Won(self.board.to_act.inverse());
-                println!("{} has won!", self.board.to_act.inverse())
+                self.mate_patterns = classify(&self.board);
+                self.end(
+                    GameResult::Won(self.board.to_act.inverse()),
+                    Termination::Checkmate,
+                );
             } else {
-                self.result = GameResult::Tied;
-                println!("The game has ended in a tie.")
+                self.end(GameResult::Tied, Termination::Stalemate);
             }
         }
     }
 }
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GameResult {
     Won(Color),
     Tied,
     Ongoing,
 }
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Timeout,
+    Resignation,
+    Agreement,
+    Adjudication,
+}
+
+//Things players and arbiters do besides moving
+#[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Action {
+    Resign(Color),
+    OfferDraw(Color),
+    AcceptDraw(Color),
+    DeclineDraw(Color),
+    Adjudicate(GameResult),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Resign(c) => write!(f, "{} resigns", c),
+            Action::OfferDraw(c) => write!(f, "{} offers a draw", c),
+            Action::AcceptDraw(c) => write!(f, "{} accepts the draw", c),
+            Action::DeclineDraw(c) => write!(f, "{} declines the draw", c),
+            Action::Adjudicate(GameResult::Won(c)) => write!(f, "Adjudicated as a win for {}", c),
+            Action::Adjudicate(_) => write!(f, "Adjudicated as a draw"),
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ActionError {
+    GameOver,
+    NotYourTurn,
+    NoOffer,        //Nothing to accept or decline
+    AlreadyOffered, //The same side offering twice
+    OfferPending,   //Offering while the opponent's offer is open, accept it instead
+    NoResult,       //Adjudicating to an ongoing game
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ActionError::GameOver => "the game is already over",
+            ActionError::NotYourTurn => "it is not your turn",
+            ActionError::NoOffer => "there is no draw offer to answer",
+            ActionError::AlreadyOffered => "a draw has already been offered",
+            ActionError::OfferPending => "the opponent's draw offer is still open",
+            ActionError::NoResult => "an adjudication needs a result",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for ActionError {}
+
 pub fn print_board_state(board: &Board) {
     println!();
     println!("CHESS! {} to play.", board.to_act);
@@ -98,3 +384,359 @@ pub fn print_board_state(board: &Board) {
     }
     println!("  -a-b-c-d-e-f-g-h-")
 }
+
+//A game is stored as its start position and the moves in UCI notation, which are replayed
+//when reading it back. The clock and event subscribers are not part of it.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GameRecord {
+    start: Board,
+    moves: Vec<String>,
+    undone: Vec<String>, //Latest last, as in Game
+    result: GameResult,
+    termination: Option<Termination>,
+    draw_offer: Option<Color>,
+    actions: Vec<(usize, Action)>,
+    default_promotion: Title,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Game {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        GameRecord {
+            start: self.start.clone(),
+            moves: self.moves.iter().map(|m| m.to_uci()).collect(),
+            undone: self.undone.iter().map(|m| m.to_uci()).collect(),
+            result: self.result,
+            termination: self.termination,
+            draw_offer: self.draw_offer,
+            actions: self.actions.clone(),
+            default_promotion: self.default_promotion,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Game {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let record = GameRecord::deserialize(deserializer)?;
+        let illegal = |note: &str| D::Error::custom(format!("illegal move '{}'", note));
+        let mut game = Game::from_board(record.start);
+        for note in record.moves.iter() {
+            let m = MoveData::from_uci(&game.board, note).ok_or_else(|| illegal(note))?;
+            game.make_move(m);
+        }
+        //The next move to redo is the last one, so they are read in reverse
+        let mut board = game.board.clone();
+        for note in record.undone.iter().rev() {
+            let m = MoveData::from_uci(&board, note).ok_or_else(|| illegal(note))?;
+            board.make_move(m);
+            game.undone.insert(0, m);
+        }
+        game.result = record.result;
+        game.termination = record.termination;
+        game.draw_offer = record.draw_offer;
+        game.actions = record.actions;
+        game.default_promotion = record.default_promotion;
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod test_game {
+    use super::*;
+    use crate::clock::{ManualTimeSource, TimeControl};
+    use crate::piece::Piece;
+    use std::time::Duration;
+    #[test]
+    fn undo() {
+        let mut game = Game::new();
+        game.undo_move();
+        assert_eq!(game.history.len(), 0);
+        game.make_move_from_notation("e2 e4".to_string());
+        game.make_move_from_notation("e7 e5".to_string());
+        assert_eq!(game.moves.len(), 2);
+        game.undo_move();
+        assert_eq!(game.moves.len(), 1);
+        assert_eq!(game.board.to_act, Color::Black);
+        assert_eq!(game.action_space.len(), 20);
+        game.undo_move();
+        assert_eq!(game.board.pieces, game.start.pieces);
+        assert!(game.moves.is_empty());
+    }
+    #[test]
+    fn redo() {
+        let mut game = Game::new();
+        assert!(!game.redo_move());
+        game.make_move_from_notation("e2 e4".to_string());
+        game.make_move_from_notation("e7 e5".to_string());
+        let after = game.board.to_fen();
+        game.undo_move();
+        game.undo_move();
+        assert!(game.redo_move());
+        assert!(game.redo_move());
+        assert!(!game.redo_move());
+        assert_eq!(game.board.to_fen(), after);
+        //A new move drops what could have been redone
+        game.undo_move();
+        game.make_move_from_notation("d7 d5".to_string());
+        assert!(!game.redo_move());
+        assert_eq!(game.moves.len(), 2);
+    }
+    fn clocked_game(fen: &str, control: &str) -> (Game, ManualTimeSource) {
+        let time = ManualTimeSource::new();
+        let mut game = Game::from_board(Board::from_fen(fen).unwrap());
+        game.set_clock(Clock::new(
+            TimeControl::parse(control).unwrap(),
+            Box::new(time.clone()),
+        ));
+        (game, time)
+    }
+    #[test]
+    fn clock_runs_with_moves() {
+        let (mut game, time) = clocked_game(crate::fen::STANDARD_FEN, "1+1");
+        time.advance(Duration::from_secs(10));
+        game.make_move_from_notation("e2 e4".to_string());
+        let clock = game.clock.as_ref().unwrap();
+        assert_eq!(clock.remaining(Color::White), Duration::from_secs(51));
+        assert_eq!(clock.to_act(), Color::Black);
+        time.advance(Duration::from_secs(5));
+        game.undo_move();
+        let clock = game.clock.as_ref().unwrap();
+        assert_eq!(clock.remaining(Color::Black), Duration::from_secs(55));
+        assert_eq!(clock.to_act(), Color::White);
+    }
+    #[test]
+    fn flag_fall() {
+        let (mut game, time) = clocked_game(crate::fen::STANDARD_FEN, "1");
+        time.advance(Duration::from_secs(61));
+        game.make_move_from_notation("e2 e4".to_string());
+        assert!(game.moves.is_empty());
+        assert_eq!(game.result, GameResult::Won(Color::Black));
+        assert_eq!(game.termination, Some(Termination::Timeout));
+    }
+    #[test]
+    fn flag_fall_against_lone_king() {
+        let (mut game, time) = clocked_game("4k3/8/8/8/8/8/8/4K2Q w - - 0 1", "1");
+        time.advance(Duration::from_secs(60));
+        assert_eq!(game.check_time(), GameResult::Tied);
+        assert_eq!(game.termination, Some(Termination::Timeout));
+        //The result stays once decided
+        time.advance(Duration::from_secs(60));
+        assert_eq!(game.check_time(), GameResult::Tied);
+    }
+    #[test]
+    fn resignation() {
+        let mut game = Game::new();
+        game.make_move_from_notation("e2 e4".to_string());
+        game.resign(Color::White).unwrap();
+        assert_eq!(game.result, GameResult::Won(Color::Black));
+        assert_eq!(game.termination, Some(Termination::Resignation));
+        assert_eq!(game.actions, vec![(1, Action::Resign(Color::White))]);
+        assert_eq!(game.resign(Color::Black), Err(ActionError::GameOver));
+        game.make_move_from_notation("e7 e5".to_string());
+        assert_eq!(game.moves.len(), 1);
+    }
+    #[test]
+    fn draw_offers() {
+        let mut game = Game::new();
+        assert_eq!(game.offer_draw(Color::Black), Err(ActionError::NotYourTurn));
+        assert_eq!(game.accept_draw(Color::Black), Err(ActionError::NoOffer));
+        game.offer_draw(Color::White).unwrap();
+        assert_eq!(game.offer_draw(Color::White), Err(ActionError::AlreadyOffered));
+        //Black cannot answer before White has moved
+        assert_eq!(game.decline_draw(Color::Black), Err(ActionError::NotYourTurn));
+        game.make_move_from_notation("e2 e4".to_string());
+        assert_eq!(game.draw_offer, Some(Color::White));
+        assert_eq!(game.offer_draw(Color::Black), Err(ActionError::OfferPending));
+        game.decline_draw(Color::Black).unwrap();
+        assert_eq!(game.draw_offer, None);
+
+        game.make_move_from_notation("e7 e5".to_string());
+        game.offer_draw(Color::White).unwrap();
+        game.make_move_from_notation("g1 f3".to_string());
+        //Moving instead of answering lets the offer expire
+        game.make_move_from_notation("b8 c6".to_string());
+        assert_eq!(game.draw_offer, None);
+        assert_eq!(game.accept_draw(Color::White), Err(ActionError::NoOffer));
+
+        game.offer_draw(Color::White).unwrap();
+        game.make_move_from_notation("f1 b5".to_string());
+        game.accept_draw(Color::Black).unwrap();
+        assert_eq!(game.result, GameResult::Tied);
+        assert_eq!(game.termination, Some(Termination::Agreement));
+        assert_eq!(
+            game.actions,
+            vec![
+                (0, Action::OfferDraw(Color::White)),
+                (1, Action::DeclineDraw(Color::Black)),
+                (2, Action::OfferDraw(Color::White)),
+                (4, Action::OfferDraw(Color::White)),
+                (5, Action::AcceptDraw(Color::Black)),
+            ]
+        );
+        //Taking back the last move also takes back the agreement
+        game.undo_move();
+        assert_eq!(game.result, GameResult::Ongoing);
+        assert_eq!(game.actions.len(), 3);
+    }
+    #[test]
+    fn adjudication() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.adjudicate(GameResult::Ongoing),
+            Err(ActionError::NoResult)
+        );
+        game.adjudicate(GameResult::Won(Color::Black)).unwrap();
+        assert_eq!(game.result, GameResult::Won(Color::Black));
+        assert_eq!(game.termination, Some(Termination::Adjudication));
+        assert_eq!(
+            game.adjudicate(GameResult::Tied),
+            Err(ActionError::GameOver)
+        );
+    }
+    #[test]
+    fn actions_stop_the_clock() {
+        let (mut game, time) = clocked_game(crate::fen::STANDARD_FEN, "1");
+        time.advance(Duration::from_secs(10));
+        game.resign(Color::Black).unwrap();
+        time.advance(Duration::from_secs(100));
+        let clock = game.clock.as_ref().unwrap();
+        assert!(!clock.is_running());
+        assert_eq!(clock.remaining(Color::White), Duration::from_secs(50));
+    }
+    #[test]
+    fn events() {
+        let mut game = Game::from_board(Board::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap());
+        let events = game.event_channel();
+        let checks = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let counter = checks.clone();
+        game.subscribe(Box::new(move |e| {
+            if let GameEvent::Check(_) = e {
+                *counter.lock().unwrap() += 1;
+            }
+        }));
+        game.offer_draw(Color::White).unwrap();
+        let m = game.find_move(
+            Square::from_notation("a1").unwrap(),
+            Square::from_notation("a8").unwrap(),
+        );
+        game.make_move(m.unwrap());
+        game.undo_move();
+        let m = m.unwrap();
+        assert_eq!(
+            events.try_iter().collect::<Vec<GameEvent>>(),
+            vec![
+                GameEvent::DrawOffered(Color::White),
+                GameEvent::MoveMade(m),
+                GameEvent::Check(Color::Black),
+                GameEvent::GameOver(GameResult::Won(Color::White)),
+                GameEvent::Undo(m),
+            ]
+        );
+        assert_eq!(*checks.lock().unwrap(), 1);
+    }
+    #[test]
+    fn clock_events() {
+        let (mut game, time) = clocked_game(crate::fen::STANDARD_FEN, "1");
+        let events = game.event_channel();
+        time.advance(Duration::from_secs(45));
+        game.tick();
+        time.advance(Duration::from_secs(15));
+        game.tick();
+        game.tick();
+        assert_eq!(
+            events.try_iter().collect::<Vec<GameEvent>>(),
+            vec![
+                GameEvent::ClockTick {
+                    white: Duration::from_secs(15),
+                    black: Duration::from_secs(60)
+                },
+                GameEvent::ClockTick {
+                    white: Duration::from_secs(0),
+                    black: Duration::from_secs(60)
+                },
+                GameEvent::Flagged(Color::White),
+                GameEvent::GameOver(GameResult::Won(Color::Black)),
+            ]
+        );
+    }
+    #[test]
+    fn from_board() {
+        let board = Board::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let game = Game::from_board(board);
+        assert_eq!(game.result, GameResult::Won(Color::White));
+        assert_eq!(game.termination, Some(Termination::Checkmate));
+    }
+    #[test]
+    fn find_move_uses_default_promotion() {
+        let mut game = Game::new();
+        let a7 = Square::new(6, 0).unwrap();
+        let a8 = Square::new(7, 0).unwrap();
+        let mut board = Board::new_empty();
+        board.pieces.insert(a7, Piece::new(Color::White, Title::Pawn));
+        board.pieces.insert(Square::new(0, 4).unwrap(), Piece::new(Color::White, Title::King));
+        board.pieces.insert(Square::new(7, 4).unwrap(), Piece::new(Color::Black, Title::King));
+        board.update_status();
+        game.board = board;
+        game.action_space = generate_action_space(game.board.clone());
+
+        let m = game.find_move(a7, a8).unwrap();
+        assert_eq!(m.move_type, MoveType::Promotion(Some(Title::Queen)));
+        game.set_deafault_promotion(Title::Knight);
+        let m = game.find_move(a7, a8).unwrap();
+        assert_eq!(m.move_type, MoveType::Promotion(Some(Title::Knight)));
+        game.make_move_from_notation("a7 a8".to_string());
+        assert_eq!(game.board.pieces[&a8].title, Title::Knight);
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let e4 = Square::from_notation("e4").unwrap();
+        assert_eq!(serde_json::to_string(&e4).unwrap(), "\"e4\"");
+        assert!(serde_json::from_str::<Square>("\"i9\"").is_err());
+
+        let mut game = Game::new();
+        let notes = ["e2 e4", "e7 e5", "e1 e2", "e8 e7", "e2 e1", "e7 e8", "g1 f3"];
+        for note in notes.iter() {
+            game.make_move_from_notation(note.to_string());
+        }
+        game.undo_move();
+        game.offer_draw(Color::White).unwrap();
+        let json = serde_json::to_string(&game).unwrap();
+        assert!(json.contains("\"moves\":[\"e2e4\",\"e7e5\",\"e1e2\""));
+        assert!(json.contains("\"undone\":[\"g1f3\"]"));
+
+        let mut read: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.board.to_fen(), game.board.to_fen());
+        assert_eq!(read.moves, game.moves);
+        assert_eq!(read.actions, game.actions);
+        assert_eq!(read.draw_offer, Some(Color::White));
+        //The kings walked back, castling stays lost
+        assert!(!read.board.can_castle(Color::White, true));
+        assert!(!read.board.can_castle(Color::Black, false));
+        assert!(read.redo_move());
+        let f3 = Square::from_notation("f3").unwrap();
+        assert_eq!(read.board.pieces[&f3].title, Title::Knight);
+
+        //Boards keep has_moved on their own as well
+        let board: Board =
+            serde_json::from_str(&serde_json::to_string(&game.board).unwrap()).unwrap();
+        assert_eq!(board.pieces, game.board.pieces);
+        assert_eq!(board.to_fen(), game.board.to_fen());
+        assert_eq!(board.king_pos, game.board.king_pos);
+
+        //Moves are written as UCI strings and derived board state is left out and recalculated
+        let checked = Board::from_fen("4k3/8/8/8/8/8/8/4RK2 b - - 0 1").unwrap();
+        let written = serde_json::to_string(&checked).unwrap();
+        assert!(!written.contains("in_check") && !written.contains("king_pos"));
+        let read: Board = serde_json::from_str(&written).unwrap();
+        assert!(read.in_check);
+        assert_eq!(read.king_pos, checked.king_pos);
+        let m = MoveData::from_uci(&game.board, "g1f3").unwrap();
+        assert_eq!(serde_json::to_string(&m).unwrap(), "\"g1f3\"");
+        assert!(serde_json::from_str::<Game>(&json.replace("e7e5", "e7e4")).is_err());
+    }
+}
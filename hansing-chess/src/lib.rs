@@ -1,25 +1,39 @@
+pub mod archive;
 pub mod board;
+pub mod bookbuilder;
+pub mod clock;
 pub mod color;
+pub mod eco;
+pub mod encoding;
+pub mod epd;
+pub mod eval;
+pub mod event;
+pub mod fen;
 pub mod game;
+pub mod matepattern;
 pub mod movedata;
 pub mod movegen;
+pub mod movepick;
 pub mod moverules;
 pub mod occupancy;
+pub mod pawnstructure;
+pub mod pgn;
 pub mod piece;
+pub mod polyglot;
+pub mod problem;
+pub mod retro;
+pub mod san;
+pub mod search;
+pub mod see;
+pub mod smp;
 pub mod square;
 pub mod standardstart;
+pub mod tablebase;
+pub mod tactics;
+#[cfg(test)]
+mod testutil;
 pub mod title;
+pub mod tt;
+pub mod uciclient;
+pub mod zobrist;
 
-/*
-//Loop for testing with terminal interface (convert lib.rs to main.rs to use)
-use std::io::{self, BufRead};
-fn main() {
-    let stdin = io::stdin();
-    let mut game = game::Game::new();
-    loop {
-        game::print_board_state(&game.board);
-        //stdin.lock().read_line(&mut input);
-        let input = stdin.lock().lines().next().unwrap().unwrap();
-        game.make_move_from_notation(input);
-    }
-}*/
@@ -0,0 +1,308 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movegen::generate_action_space;
+use crate::moverules::KING_MOVES;
+use crate::square::Square;
+use crate::title::Title;
+use std::fmt;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum MatePattern {
+    BackRank,
+    Smothered,
+    Anastasia,
+    Arabian,
+    Boden,
+    Epaulette,
+    Damiano,
+    Legal,
+    Opera,
+}
+
+impl fmt::Display for MatePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MatePattern::BackRank => "back-rank mate",
+            MatePattern::Smothered => "smothered mate",
+            MatePattern::Anastasia => "Anastasia's mate",
+            MatePattern::Arabian => "Arabian mate",
+            MatePattern::Boden => "Boden's mate",
+            MatePattern::Epaulette => "epaulette mate",
+            MatePattern::Damiano => "Damiano's mate",
+            MatePattern::Legal => "Legal's mate",
+            MatePattern::Opera => "Opera mate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//A pattern with the squares that make it: the mated king, the checker and the pieces covering
+//or blocking its escape, in square order
+#[derive(PartialEq, Clone, Debug)]
+pub struct Classification {
+    pub pattern: MatePattern,
+    pub key_squares: Vec<Square>,
+}
+
+impl Classification {
+    fn new(pattern: MatePattern, king: Square, mut key_squares: Vec<Square>) -> Self {
+        key_squares.push(king);
+        key_squares.sort_by_key(|s| s.index());
+        key_squares.dedup();
+        Classification {
+            pattern,
+            key_squares,
+        }
+    }
+}
+
+fn adjacent(a: Square, b: Square) -> bool {
+    a != b && (a.rank() - b.rank()).abs() <= 1 && (a.file() - b.file()).abs() <= 1
+}
+
+fn edge(i: i32) -> bool {
+    i == 0 || i == 7
+}
+
+//Every pattern the mate fits, empty when the side to move is not checkmated
+pub fn classify(board: &Board) -> Vec<Classification> {
+    if !board.in_check || !generate_action_space(board.clone()).is_empty() {
+        return Vec::new();
+    }
+    let defender = board.to_act;
+    let attacker = defender.inverse();
+    let king = board.king_pos[defender.index()];
+    let back_rank = if defender == Color::White { 0 } else { 7 };
+    let own = |s: &Square| matches!(board.pieces.get(s), Some(p) if p.color == defender);
+    let title = |s: &Square| board.pieces[s].title;
+    //Attackers of a square, seeing through the king as it cannot hide behind itself
+    let covering = |s: Square| {
+        let mut found: Vec<Square> = board
+            .attackers_through(s, &[king])
+            .into_iter()
+            .filter(|a| board.pieces[a].color == attacker)
+            .collect();
+        found.sort_by_key(|a| a.index());
+        found
+    };
+    let neighbours: Vec<Square> = KING_MOVES
+        .iter()
+        .filter_map(|&(rank_offset, file_offset, _)| king.offset(rank_offset, file_offset))
+        .collect();
+    let blockers: Vec<Square> = neighbours.iter().copied().filter(own).collect();
+    let escapes: Vec<Square> = neighbours.iter().copied().filter(|s| !own(s)).collect();
+    //The attacking pieces of a title covering one of the escapes
+    let guards = |t: Title, except: Square| {
+        let mut found: Vec<Square> = escapes
+            .iter()
+            .flat_map(|&s| covering(s))
+            .filter(|a| *a != except && title(a) == t)
+            .collect();
+        found.sort_by_key(|a| a.index());
+        found.dedup();
+        found
+    };
+
+    let mut found = Vec::new();
+    for &checker in covering(king).iter() {
+        let checker_title = title(&checker);
+        let supporters = |t: Title| -> Vec<Square> {
+            covering(checker)
+                .into_iter()
+                .filter(|s| title(s) == t)
+                .collect()
+        };
+        let heavy = matches!(checker_title, Title::Rook | Title::Queen);
+
+        //Caged on its own first rank by its own pieces
+        if heavy && king.rank() == back_rank && checker.rank() == king.rank() {
+            let forward: Vec<Square> = neighbours
+                .iter()
+                .copied()
+                .filter(|s| s.rank() == king.rank() + defender.forward())
+                .collect();
+            let walls: Vec<Square> = forward.iter().copied().filter(own).collect();
+            if !walls.is_empty() && forward.iter().all(|s| own(s) || !covering(*s).is_empty()) {
+                let mut squares = walls;
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::BackRank, king, squares));
+            }
+        }
+        if checker_title == Title::Knight && escapes.is_empty() {
+            found.push(Classification::new(
+                MatePattern::Smothered,
+                king,
+                vec![checker],
+            ));
+        }
+        //Down the edge file with a knight taking the squares beside the king's own piece
+        if heavy && edge(king.file()) && checker.file() == king.file() {
+            let knights = guards(Title::Knight, checker);
+            let walls: Vec<Square> = blockers
+                .iter()
+                .copied()
+                .filter(|s| s.rank() == king.rank())
+                .collect();
+            if !knights.is_empty() && !walls.is_empty() {
+                let mut squares = [knights, walls].concat();
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::Anastasia, king, squares));
+            }
+        }
+        if checker_title == Title::Rook
+            && edge(king.rank())
+            && edge(king.file())
+            && adjacent(checker, king)
+        {
+            let knights = supporters(Title::Knight);
+            if !knights.is_empty() {
+                let mut squares = knights;
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::Arabian, king, squares));
+            }
+        }
+        //Two bishops on crossing diagonals with the king hemmed in by its own pieces
+        if checker_title == Title::Bishop && !blockers.is_empty() {
+            let bishops = guards(Title::Bishop, checker);
+            if !bishops.is_empty() {
+                let mut squares = bishops;
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::Boden, king, squares));
+            }
+        }
+        if checker_title == Title::Queen && checker.file() == king.file() {
+            let sides: Vec<Square> = [king.offset(0, -1), king.offset(0, 1)]
+                .iter()
+                .flatten()
+                .copied()
+                .filter(own)
+                .collect();
+            if sides.len() == 2 {
+                let mut squares = sides;
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::Epaulette, king, squares));
+            }
+        }
+        if checker_title == Title::Queen && adjacent(checker, king) {
+            let pawns = supporters(Title::Pawn);
+            if !pawns.is_empty() {
+                let mut squares = pawns;
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::Damiano, king, squares));
+            }
+        }
+        if checker_title == Title::Rook && adjacent(checker, king) {
+            let bishops = supporters(Title::Bishop);
+            if !bishops.is_empty() {
+                let mut squares = bishops;
+                squares.push(checker);
+                found.push(Classification::new(MatePattern::Opera, king, squares));
+            }
+        }
+        //Only minor pieces, two knights and a bishop, take part
+        if checker_title == Title::Knight {
+            let mut pieces: Vec<Square> = covering(king)
+                .into_iter()
+                .chain(escapes.iter().flat_map(|&s| covering(s)))
+                .collect();
+            pieces.sort_by_key(|s| s.index());
+            pieces.dedup();
+            let count = |t: Title| pieces.iter().filter(|s| title(s) == t).count();
+            if count(Title::Knight) >= 2
+                && count(Title::Bishop) >= 1
+                && count(Title::Knight) + count(Title::Bishop) == pieces.len()
+            {
+                found.push(Classification::new(MatePattern::Legal, king, pieces));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test_matepattern {
+    use super::*;
+    use crate::game::{Game, Termination};
+    use crate::movedata::MoveData;
+    use crate::testutil::sqs;
+    fn patterns(fen: &str) -> Vec<MatePattern> {
+        classify(&Board::from_fen(fen).unwrap())
+            .iter()
+            .map(|c| c.pattern)
+            .collect()
+    }
+
+    #[test]
+    fn back_rank_game() {
+        let mut game =
+            Game::from_board(Board::from_fen("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1").unwrap());
+        assert!(game.mate_patterns.is_empty());
+        let m = MoveData::from_uci(&game.board, "d1d8").unwrap();
+        game.make_move(m);
+        assert_eq!(
+            game.mate_patterns,
+            vec![Classification {
+                pattern: MatePattern::BackRank,
+                key_squares: sqs(&["f7", "g7", "h7", "d8", "g8"]),
+            }]
+        );
+        assert_eq!(MatePattern::BackRank.to_string(), "back-rank mate");
+        game.undo_move();
+        assert!(game.mate_patterns.is_empty());
+    }
+
+    #[test]
+    fn knight_mates() {
+        let smothered = classify(&Board::from_fen("6rk/5Npp/8/8/8/8/8/6K1 b - - 0 1").unwrap());
+        assert_eq!(smothered.len(), 1);
+        assert_eq!(smothered[0].pattern, MatePattern::Smothered);
+        assert_eq!(smothered[0].key_squares, sqs(&["f7", "h8"]));
+
+        let anastasia = classify(&Board::from_fen("8/4N1pk/8/7R/8/8/8/6K1 b - - 0 1").unwrap());
+        assert_eq!(anastasia[0].pattern, MatePattern::Anastasia);
+        assert_eq!(anastasia[0].key_squares, sqs(&["h5", "e7", "g7", "h7"]));
+
+        assert_eq!(
+            patterns("7k/7R/5N2/8/8/8/8/6K1 b - - 0 1"),
+            vec![MatePattern::Arabian]
+        );
+    }
+
+    #[test]
+    fn bishop_and_queen_mates() {
+        let boden = classify(&Board::from_fen("2kr4/3p4/B7/8/5B2/8/8/6K1 b - - 0 1").unwrap());
+        assert_eq!(boden[0].pattern, MatePattern::Boden);
+        assert_eq!(boden[0].key_squares, sqs(&["f4", "a6", "c8"]));
+        assert_eq!(
+            patterns("3rkr2/8/4Q3/8/8/8/8/4K3 b - - 0 1"),
+            vec![MatePattern::Epaulette]
+        );
+        assert_eq!(
+            patterns("5rk1/7Q/6P1/8/8/8/8/6K1 b - - 0 1"),
+            vec![MatePattern::Damiano]
+        );
+        assert_eq!(
+            patterns("3Rk3/4pp2/8/B7/8/8/8/6K1 b - - 0 1"),
+            vec![MatePattern::BackRank, MatePattern::Opera]
+        );
+        //Not mate
+        assert!(patterns("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1").is_empty());
+    }
+
+    #[test]
+    fn legal_mate() {
+        let mut game = Game::new();
+        for san in [
+            "e4", "e5", "Nf3", "d6", "Bc4", "Bg4", "Nc3", "g6", "Nxe5", "Bxd1", "Bxf7+", "Ke7",
+            "Nd5#",
+        ] {
+            let m = MoveData::from_san(&game.board, san).unwrap();
+            game.make_move(m);
+        }
+        assert_eq!(game.termination, Some(Termination::Checkmate));
+        let found = game.mate_patterns;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pattern, MatePattern::Legal);
+        assert_eq!(found[0].key_squares, sqs(&["d5", "e5", "e7", "f7"]));
+    }
+}
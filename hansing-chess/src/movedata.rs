@@ -1,3 +1,5 @@
+use crate::board::Board;
+use crate::movegen::generate_action_space;
 use crate::square::Square;
 use crate::title::Title;
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -47,6 +49,58 @@ impl MoveData {
     pub fn get_move_notation(&self) -> String {
         format!("{} {}", self.from, self.to)
     }
+
+    //Long algebraic notation as used by UCI, e.g. e2e4 or e7e8q
+    pub fn to_uci(&self) -> String {
+        match self.move_type {
+            MoveType::Promotion(promote_to) => {
+                let title = promote_to.unwrap_or(Title::Queen);
+                let sign = match title {
+                    Title::Knight => 'n',
+                    Title::Bishop => 'b',
+                    Title::Rook => 'r',
+                    _ => 'q',
+                };
+                format!("{}{}{}", self.from, self.to, sign)
+            }
+            _ => format!("{}{}", self.from, self.to),
+        }
+    }
+
+    //The legal move in the position matching the UCI notation
+    pub fn from_uci(board: &Board, note: &str) -> Option<Self> {
+        let note = note.to_ascii_lowercase();
+        generate_action_space(board.clone())
+            .into_iter()
+            .find(|m| m.to_uci() == note)
+    }
+}
+
+//Written in UCI notation, e.g. "e2e4"
+#[cfg(feature = "serde")]
+impl serde::Serialize for MoveData {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.to_uci())
+    }
+}
+
+//Reads a move written as above. The notation alone does not tell castling, en passant and
+//double steps from other moves between the same squares, so the board it is played on is needed.
+#[cfg(feature = "serde")]
+pub struct MoveSeed<'a>(pub &'a Board);
+
+#[cfg(feature = "serde")]
+impl<'de, 'a> serde::de::DeserializeSeed<'de> for MoveSeed<'a> {
+    type Value = MoveData;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<MoveData, D::Error> {
+        let note = <std::borrow::Cow<str> as serde::Deserialize>::deserialize(deserializer)?;
+        MoveData::from_uci(self.0, &note)
+            .ok_or_else(|| serde::de::Error::custom(format!("illegal move '{}'", note)))
+    }
 }
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum MoveType {
@@ -80,6 +134,56 @@ mod test {
         let _casteling = MoveData::new_casteling(from, to, rook_from, rook_to);
     }
 
+    #[test]
+    fn uci() {
+        let e2 = Square::new(1, 4).unwrap();
+        let e4 = Square::new(3, 4).unwrap();
+        let a7 = Square::new(6, 0).unwrap();
+        let b8 = Square::new(7, 1).unwrap();
+        assert_eq!(MoveData::new_double_step(e2, e4, e4).to_uci(), "e2e4");
+        assert_eq!(
+            MoveData::new_promotion(a7, b8, Some(Title::Knight)).to_uci(),
+            "a7b8n"
+        );
+        let board = Board::new_standard();
+        let m = MoveData::from_uci(&board, "e2e4").unwrap();
+        assert_eq!(
+            m.move_type,
+            MoveType::DoubleStep(Square::new(2, 4).unwrap())
+        );
+        assert_eq!(MoveData::from_uci(&board, "e2e5"), None);
+        assert_eq!(MoveData::from_uci(&board, "nonsense"), None);
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_moves() {
+        use serde::de::DeserializeSeed;
+        let round_trip = |fen: &str, note: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            let m = MoveData::from_uci(&board, note).unwrap();
+            let json = serde_json::to_string(&m).unwrap();
+            assert_eq!(json, format!("\"{}\"", note));
+            let mut reader = serde_json::Deserializer::from_str(&json);
+            assert_eq!(MoveSeed(&board).deserialize(&mut reader).unwrap(), m);
+            m
+        };
+        //A rook between the king's castling squares is not castling
+        let rook = round_trip("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1", "e1c1");
+        assert_eq!(rook.move_type, MoveType::Standard);
+        let queen = round_trip("4k3/8/8/8/8/8/3Q4/4K3 w - - 0 1", "d2d4");
+        assert_eq!(queen.move_type, MoveType::Standard);
+        let en_passant = round_trip("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1", "d5e6");
+        assert_eq!(
+            en_passant.move_type,
+            MoveType::EnPassant(Square::new(4, 4).unwrap())
+        );
+        let castling = round_trip("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1", "e1c1");
+        assert!(matches!(castling.move_type, MoveType::Casteling(..)));
+
+        let board = Board::new_standard();
+        let mut reader = serde_json::Deserializer::from_str("\"e2e5\"");
+        assert!(MoveSeed(&board).deserialize(&mut reader).is_err());
+    }
     #[test]
     fn eq() {
         let (from, to, _capture, rook_from, rook_to) = (
@@ -1,13 +1,11 @@
 use crate::board::Board;
-use crate::movedata::MoveData;
+use crate::movedata::{MoveData, MoveType};
 use crate::occupancy::Occupancy;
 use crate::square::Square;
 use crate::title::Title;
 
 use crate::moverules::*;
 
-pub static mut PROMOTE_TO: Title = Title::Queen;
-
 pub fn generate_action_space(board: Board) -> Vec<MoveData> {
     let mut action_space: Vec<MoveData> = Vec::new();
 
@@ -30,23 +28,18 @@ fn pawn_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
     if let Some(to_step) = from.offset(piece.color.forward(), 0) {
         if board.occupancy(to_step, piece.color) == Occupancy::Empty {
             if from.rank() == piece.color.seventh_rank() {
-                unsafe {
-                    //oof
-                    add_move(
-                        MoveData::new_promotion(from, to_step, Some(PROMOTE_TO)),
-                        &mut moves,
-                        board,
-                    );
-                }
+                add_promotions(from, to_step, &mut moves, board);
             } else {
                 add_move(MoveData::new_standard(from, to_step), &mut moves, board);
-                if !piece.has_moved && board.occupancy(to_step, piece.color) == Occupancy::Empty {
+                if !piece.has_moved {
                     if let Some(to_double_step) = to_step.offset(piece.color.forward(), 0) {
-                        add_move(
-                            MoveData::new_double_step(from, to_double_step, to_step),
-                            &mut moves,
-                            board,
-                        );
+                        if board.occupancy(to_double_step, piece.color) == Occupancy::Empty {
+                            add_move(
+                                MoveData::new_double_step(from, to_double_step, to_step),
+                                &mut moves,
+                                board,
+                            );
+                        }
                     }
                 }
             }
@@ -57,25 +50,22 @@ fn pawn_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
         if let Some(to_capture) = from.offset(piece.color.forward(), file_offset) {
             if board.occupancy(to_capture, piece.color) == Occupancy::OtherColor {
                 if from.rank() == piece.color.seventh_rank() {
-                    unsafe {
-                        //oof
-                        add_move(
-                            MoveData::new_promotion(from, to_capture, Some(PROMOTE_TO)),
-                            &mut moves,
-                            board,
-                        );
-                    }
+                    add_promotions(from, to_capture, &mut moves, board);
                 } else {
                     add_move(MoveData::new_standard(from, to_capture), &mut moves, board);
                 }
             } else if board.en_passant == Some(to_capture) {
-                moves.push(MoveData::new_en_passant(
-                    from,
-                    to_capture,
-                    to_capture
-                        .offset(piece.color.inverse().forward(), 0)
-                        .unwrap(),
-                ))
+                add_move(
+                    MoveData::new_en_passant(
+                        from,
+                        to_capture,
+                        to_capture
+                            .offset(piece.color.inverse().forward(), 0)
+                            .unwrap(),
+                    ),
+                    &mut moves,
+                    board,
+                )
             }
         }
     }
@@ -119,7 +109,8 @@ fn piece_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
     //Casteling moves
     if piece.title == Title::King && !piece.has_moved && !board.in_check {
         // King side
-        if board.occupancy(from.offset(0, 1).unwrap(), piece.color) == Occupancy::Empty
+        if board.can_castle(piece.color, true)
+            && board.occupancy(from.offset(0, 1).unwrap(), piece.color) == Occupancy::Empty
             && board.occupancy(from.offset(0, 2).unwrap(), piece.color) == Occupancy::Empty
             && board.occupancy(from.offset(0, 3).unwrap(), piece.color) == Occupancy::SameColor
         {
@@ -143,7 +134,8 @@ fn piece_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
             }
         }
         // Queen side
-        if board.occupancy(from.offset(0, -1).unwrap(), piece.color) == Occupancy::Empty
+        if board.can_castle(piece.color, false)
+            && board.occupancy(from.offset(0, -1).unwrap(), piece.color) == Occupancy::Empty
             && board.occupancy(from.offset(0, -2).unwrap(), piece.color) == Occupancy::Empty
             && board.occupancy(from.offset(0, -3).unwrap(), piece.color) == Occupancy::Empty
             && board.occupancy(from.offset(0, -4).unwrap(), piece.color) == Occupancy::SameColor
@@ -151,9 +143,9 @@ fn piece_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
             if !board.pieces[&from.offset(0, -4).unwrap()].has_moved {
                 let m = MoveData::new_casteling(
                     from,
-                    from.offset(0, -3).unwrap(),
-                    from.offset(0, -4).unwrap(),
                     from.offset(0, -2).unwrap(),
+                    from.offset(0, -4).unwrap(),
+                    from.offset(0, -1).unwrap(),
                 );
                 let mut result = board.clone();
                 result.make_move(m);
@@ -161,9 +153,8 @@ fn piece_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
                 let (c1, _) = result.check_if_threatend(from, board.to_act);
                 let (c2, _) = result.check_if_threatend(from.offset(0, -1).unwrap(), board.to_act);
                 let (c3, _) = result.check_if_threatend(from.offset(0, -2).unwrap(), board.to_act);
-                let (c4, _) = result.check_if_threatend(from.offset(0, -3).unwrap(), board.to_act);
 
-                if !c1 && !c2 && !c3 && !c4 {
+                if !c1 && !c2 && !c3 {
                     moves.push(m)
                 }
             }
@@ -173,8 +164,16 @@ fn piece_moves_from(board: &Board, from: Square) -> Vec<MoveData> {
     moves
 }
 
+fn add_promotions(from: Square, to: Square, buffer: &mut Vec<MoveData>, board: &Board) {
+    for &title in PROMOTION_TITLES.iter() {
+        add_move(MoveData::new_promotion(from, to, Some(title)), buffer, board);
+    }
+}
+
 fn add_move(m: MoveData, buffer: &mut Vec<MoveData>, board: &Board) {
-    if board.blockers.contains(&m.from) || board.in_check {
+    //En passant removes a second piece that may have been shielding the king
+    let en_passant = matches!(m.move_type, MoveType::EnPassant(_));
+    if board.blockers.contains(&m.from) || board.in_check || en_passant {
         let mut result = board.clone();
         result.make_move(m);
         let (c, _) = result.check_if_threatend(result.king_pos[board.to_act.index()], board.to_act);
@@ -184,3 +183,115 @@ fn add_move(m: MoveData, buffer: &mut Vec<MoveData>, board: &Board) {
     }
     buffer.push(m)
 }
+
+#[cfg(test)]
+mod test_movegen {
+    use super::*;
+    use crate::color::Color;
+    use crate::piece::Piece;
+    fn perft(board: &Board, depth: u32) -> u64 {
+        let moves = generate_action_space(board.clone());
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|m| {
+                let mut next = board.clone();
+                next.make_move(*m);
+                perft(&next, depth - 1)
+            })
+            .sum()
+    }
+    #[test]
+    fn perft_reference_positions() {
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 3, 8902),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 2, 2039),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3, 2812),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 2, 264),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 2, 1486),
+        ];
+        for (fen, depth, nodes) in positions.iter() {
+            assert_eq!(perft(&Board::from_fen(fen).unwrap(), *depth), *nodes, "{}", fen);
+        }
+    }
+    #[test]
+    fn standard_move_count() {
+        assert_eq!(generate_action_space(Board::new_standard()).len(), 20);
+    }
+    #[test]
+    fn casteling() {
+        let mut board = Board::new_standard();
+        for s in ["b1", "c1", "d1", "f1", "g1"].iter() {
+            board.pieces.remove(&Square::from_notation(s).unwrap());
+        }
+        board.update_status();
+        let e1 = Square::from_notation("e1").unwrap();
+        let moves = generate_action_space(board);
+        let king_side = MoveData::new_casteling(
+            e1,
+            Square::from_notation("g1").unwrap(),
+            Square::from_notation("h1").unwrap(),
+            Square::from_notation("f1").unwrap(),
+        );
+        let queen_side = MoveData::new_casteling(
+            e1,
+            Square::from_notation("c1").unwrap(),
+            Square::from_notation("a1").unwrap(),
+            Square::from_notation("d1").unwrap(),
+        );
+        assert!(moves.contains(&king_side));
+        assert!(moves.contains(&queen_side));
+    }
+    #[test]
+    fn double_step_blocked() {
+        let board = Board::from_fen("4k3/8/8/8/4n3/8/4P3/4K3 w - - 0 1").unwrap();
+        let e2 = Square::from_notation("e2").unwrap();
+        let pawn_moves: Vec<MoveData> = generate_action_space(board)
+            .into_iter()
+            .filter(|m| m.from == e2)
+            .collect();
+        assert_eq!(
+            pawn_moves,
+            vec![MoveData::new_standard(e2, Square::from_notation("e3").unwrap())]
+        );
+    }
+    #[test]
+    fn en_passant_exposing_king() {
+        let board = Board::from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 2").unwrap();
+        assert!(!generate_action_space(board)
+            .iter()
+            .any(|m| matches!(m.move_type, MoveType::EnPassant(_))));
+    }
+    #[test]
+    fn unmoved_king_off_start_square() {
+        let mut board = Board::new_empty();
+        board.pieces.insert(Square::new(0, 7).unwrap(), Piece::new(Color::White, Title::King));
+        board.pieces.insert(Square::new(7, 0).unwrap(), Piece::new(Color::Black, Title::King));
+        board.update_status();
+        assert_eq!(generate_action_space(board).len(), 3);
+    }
+    #[test]
+    fn all_promotions() {
+        let mut board = Board::new_empty();
+        let a7 = Square::new(6, 0).unwrap();
+        let a8 = Square::new(7, 0).unwrap();
+        board.pieces.insert(a7, Piece::new(Color::White, Title::Pawn));
+        board.pieces.insert(Square::new(0, 4).unwrap(), Piece::new(Color::White, Title::King));
+        board.pieces.insert(Square::new(7, 7).unwrap(), Piece::new(Color::Black, Title::King));
+        board.update_status();
+        let promotions: Vec<MoveData> = generate_action_space(board)
+            .into_iter()
+            .filter(|m| m.from == a7)
+            .collect();
+        assert_eq!(promotions.len(), 4);
+        for &title in PROMOTION_TITLES.iter() {
+            assert!(promotions.contains(&MoveData {
+                from: a7,
+                to: a8,
+                move_type: MoveType::Promotion(Some(title)),
+            }));
+        }
+    }
+}
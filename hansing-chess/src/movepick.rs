@@ -0,0 +1,327 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movedata::MoveData;
+use crate::movegen::generate_action_space;
+use crate::search::is_noisy;
+use crate::see::see;
+
+const MAX_HISTORY: i32 = 1 << 20;
+
+//Quiet move statistics gathered during search, used to order moves in sibling and later nodes
+pub struct Heuristics {
+    killers: Vec<[Option<MoveData>; 2]>, //Indexed by ply
+    countermoves: Vec<Option<MoveData>>, //Indexed by previous move from/to
+    history: Vec<i32>,                   //Indexed by color, from, to
+}
+
+impl Heuristics {
+    pub fn new() -> Self {
+        Self {
+            killers: Vec::new(),
+            countermoves: vec![None; 64 * 64],
+            history: vec![0; 2 * 64 * 64],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn killers(&self, ply: usize) -> [Option<MoveData>; 2] {
+        match self.killers.get(ply) {
+            Some(k) => *k,
+            None => [None, None],
+        }
+    }
+
+    pub fn add_killer(&mut self, ply: usize, m: MoveData) {
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None, None]);
+        }
+        let k = &mut self.killers[ply];
+        if k[0] != Some(m) {
+            k[1] = k[0];
+            k[0] = Some(m);
+        }
+    }
+
+    pub fn countermove(&self, previous: Option<MoveData>) -> Option<MoveData> {
+        previous.and_then(|p| self.countermoves[move_index(&p)])
+    }
+
+    pub fn set_countermove(&mut self, previous: Option<MoveData>, m: MoveData) {
+        if let Some(p) = previous {
+            self.countermoves[move_index(&p)] = Some(m);
+        }
+    }
+
+    pub fn history(&self, color: Color, m: &MoveData) -> i32 {
+        self.history[color.index() * 64 * 64 + move_index(m)]
+    }
+
+    pub fn add_history(&mut self, color: Color, m: &MoveData, depth: i32) {
+        let i = color.index() * 64 * 64 + move_index(m);
+        self.history[i] += depth * depth;
+        if self.history[i] > MAX_HISTORY {
+            for h in self.history.iter_mut() {
+                *h /= 2;
+            }
+        }
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn move_index(m: &MoveData) -> usize {
+    m.from.index() * 64 + m.to.index()
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Stage {
+    HashMove,
+    GoodCaptures,
+    Killers,
+    Countermove,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+//Hands out moves in the order: hash move, winning captures (MVV-LVA), killers, countermove,
+//quiets by history, losing captures (SEE). Each stage is only scored when reached and moves
+//are picked one at a time, so a cutoff early on skips the remaining work.
+pub struct MovePicker<'a> {
+    board: &'a Board,
+    stage: Stage,
+    hash_move: Option<MoveData>,
+    killers: [Option<MoveData>; 2],
+    countermove: Option<MoveData>,
+    noisy: Vec<MoveData>,
+    quiets: Vec<(i32, MoveData)>,
+    good_captures: Vec<(i32, MoveData)>,
+    bad_captures: Vec<(i32, MoveData)>,
+}
+
+impl<'a> MovePicker<'a> {
+    pub fn new(
+        board: &'a Board,
+        moves: Vec<MoveData>,
+        hash_move: Option<MoveData>,
+        heuristics: &Heuristics,
+        ply: usize,
+        previous: Option<MoveData>,
+    ) -> Self {
+        let (noisy, quiets): (Vec<MoveData>, Vec<MoveData>) =
+            moves.into_iter().partition(|m| is_noisy(board, m));
+        let quiets = quiets
+            .into_iter()
+            .map(|m| (heuristics.history(board.to_act, &m), m))
+            .collect();
+        Self {
+            board,
+            stage: Stage::HashMove,
+            hash_move,
+            killers: heuristics.killers(ply),
+            countermove: heuristics.countermove(previous),
+            noisy,
+            quiets,
+            good_captures: Vec::new(),
+            bad_captures: Vec::new(),
+        }
+    }
+
+    fn score_captures(&mut self) {
+        for m in self.noisy.drain(..) {
+            if Some(m) == self.hash_move {
+                continue;
+            }
+            let exchange = see(self.board, &m);
+            if exchange >= 0 {
+                let victim = match self.board.captured_piece(&m) {
+                    Some(p) => p.title.value(),
+                    None => 0,
+                };
+                let attacker = self.board.pieces[&m.from].title.value();
+                self.good_captures.push((victim * 10 - attacker, m));
+            } else {
+                self.bad_captures.push((exchange, m));
+            }
+        }
+    }
+
+    //Removes a quiet move so it is not given out twice, None if it is not available
+    fn take_quiet(&mut self, m: Option<MoveData>) -> Option<MoveData> {
+        let m = m?;
+        if Some(m) == self.hash_move {
+            return None;
+        }
+        let i = self.quiets.iter().position(|(_, q)| *q == m)?;
+        Some(self.quiets.swap_remove(i).1)
+    }
+}
+
+//Selection of the best remaining move, cheaper than sorting when only a few are used
+fn pick_best(list: &mut Vec<(i32, MoveData)>) -> Option<MoveData> {
+    let best = list
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (score, _))| *score)
+        .map(|(i, _)| i)?;
+    Some(list.swap_remove(best).1)
+}
+
+impl<'a> Iterator for MovePicker<'a> {
+    type Item = MoveData;
+
+    fn next(&mut self) -> Option<MoveData> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GoodCaptures;
+                    if let Some(m) = self.hash_move {
+                        if self.noisy.contains(&m) || self.quiets.iter().any(|(_, q)| *q == m) {
+                            self.quiets.retain(|(_, q)| *q != m);
+                            return Some(m);
+                        }
+                        self.hash_move = None;
+                    }
+                }
+                Stage::GoodCaptures => {
+                    if !self.noisy.is_empty() {
+                        self.score_captures();
+                    }
+                    match pick_best(&mut self.good_captures) {
+                        Some(m) => return Some(m),
+                        None => self.stage = Stage::Killers,
+                    }
+                }
+                Stage::Killers => {
+                    for i in 0..2 {
+                        let killer = self.killers[i].take();
+                        if let Some(m) = self.take_quiet(killer) {
+                            return Some(m);
+                        }
+                    }
+                    self.stage = Stage::Countermove;
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::Quiets;
+                    if let Some(m) = self.take_quiet(self.countermove) {
+                        return Some(m);
+                    }
+                }
+                Stage::Quiets => match pick_best(&mut self.quiets) {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => match pick_best(&mut self.bad_captures) {
+                    Some(m) => return Some(m),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+//All legal moves, most forcing first, without any search history
+pub fn order_moves(board: &Board) -> Vec<MoveData> {
+    let moves = generate_action_space(board.clone());
+    MovePicker::new(board, moves, None, &Heuristics::new(), 0, None).collect()
+}
+
+#[cfg(test)]
+mod test_movepick {
+    use super::*;
+    use crate::testutil::{place, sq};
+    use crate::title::Title;
+    fn position() -> Board {
+        let mut board = Board::new_empty();
+        place(&mut board, "a1", Color::White, Title::King);
+        place(&mut board, "h8", Color::Black, Title::King);
+        place(&mut board, "d1", Color::White, Title::Queen);
+        place(&mut board, "d5", Color::Black, Title::Pawn);
+        place(&mut board, "e6", Color::Black, Title::Pawn);
+        place(&mut board, "c3", Color::White, Title::Knight);
+        place(&mut board, "b5", Color::Black, Title::Bishop);
+        board.update_status();
+        board
+    }
+    #[test]
+    fn yields_every_move_once() {
+        let board = position();
+        let mut ordered = order_moves(&board);
+        let mut all = generate_action_space(board.clone());
+        assert_eq!(ordered.len(), all.len());
+        let key = |m: &MoveData| (m.from.index(), m.to.index());
+        ordered.sort_by_key(key);
+        all.sort_by_key(key);
+        assert_eq!(ordered, all);
+    }
+    #[test]
+    fn captures_then_quiets_then_losing_captures() {
+        let board = position();
+        let ordered = order_moves(&board);
+        //NxB wins a piece, NxP loses the knight for two pawns and QxP loses the queen
+        assert_eq!(ordered[0], MoveData::new_standard(sq("c3"), sq("b5")));
+        let len = ordered.len();
+        assert_eq!(ordered[len - 2], MoveData::new_standard(sq("c3"), sq("d5")));
+        assert_eq!(
+            *ordered.last().unwrap(),
+            MoveData::new_standard(sq("d1"), sq("d5"))
+        );
+    }
+    #[test]
+    fn hash_move_killers_and_countermove() {
+        let board = position();
+        let hash_move = MoveData::new_standard(sq("d1"), sq("d2"));
+        let killer = MoveData::new_standard(sq("a1"), sq("b2"));
+        let counter = MoveData::new_standard(sq("d1"), sq("f3"));
+        let previous = MoveData::new_standard(sq("g8"), sq("h8"));
+        let mut heuristics = Heuristics::new();
+        heuristics.add_killer(3, killer);
+        heuristics.set_countermove(Some(previous), counter);
+        let moves = generate_action_space(board.clone());
+        let ordered: Vec<MoveData> = MovePicker::new(
+            &board,
+            moves,
+            Some(hash_move),
+            &heuristics,
+            3,
+            Some(previous),
+        )
+        .collect();
+        assert_eq!(ordered[0], hash_move);
+        assert_eq!(ordered[1], MoveData::new_standard(sq("c3"), sq("b5")));
+        assert_eq!(ordered[2], killer);
+        assert_eq!(ordered[3], counter);
+        assert_eq!(ordered.iter().filter(|m| **m == hash_move).count(), 1);
+        assert_eq!(ordered.iter().filter(|m| **m == killer).count(), 1);
+    }
+    #[test]
+    fn history_orders_quiets() {
+        let board = position();
+        let favourite = MoveData::new_standard(sq("d1"), sq("h5"));
+        let mut heuristics = Heuristics::new();
+        heuristics.add_history(Color::White, &favourite, 4);
+        let moves = generate_action_space(board.clone());
+        let ordered: Vec<MoveData> =
+            MovePicker::new(&board, moves, None, &heuristics, 0, None).collect();
+        assert_eq!(ordered[1], favourite);
+    }
+    #[test]
+    fn unavailable_hash_move_is_skipped() {
+        let board = position();
+        let bogus = MoveData::new_standard(sq("h1"), sq("h2"));
+        let moves = generate_action_space(board.clone());
+        let count = moves.len();
+        let ordered: Vec<MoveData> =
+            MovePicker::new(&board, moves, Some(bogus), &Heuristics::new(), 0, None).collect();
+        assert_eq!(ordered.len(), count);
+        assert!(!ordered.contains(&bogus));
+    }
+}
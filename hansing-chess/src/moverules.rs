@@ -1,3 +1,7 @@
+use crate::title::Title;
+
+//In the order they are generated
+pub const PROMOTION_TITLES: [Title; 4] = [Title::Queen, Title::Knight, Title::Rook, Title::Bishop];
 pub const KNIGHT_MOVES: [(i32, i32, bool); 8] = [
     (2, 1, false),
     (2, -1, false),
@@ -0,0 +1,232 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::square::Square;
+use crate::title::Title;
+
+//A passed pawn's race against the defending king
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PawnSquare {
+    pub pawn: Square,
+    pub promotion: Square,
+    pub moves: i32,        //To promote, using the double step if it has it
+    pub king_inside: bool, //The king catches the pawn, counting whose move it is
+}
+
+//The pawns of one side, squares in square order and files as indexes from 0 for the a-file
+#[derive(PartialEq, Clone, Debug)]
+pub struct PawnStructure {
+    pub color: Color,
+    pub passed: Vec<Square>,
+    pub isolated: Vec<Square>,
+    pub doubled: Vec<Square>,
+    pub backward: Vec<Square>,
+    pub connected: Vec<Square>,
+    pub islands: Vec<Vec<Square>>, //From the a-file side
+    pub open_files: Vec<i32>,      //Without pawns of either side
+    pub half_open_files: Vec<i32>, //Without own pawns but with the opponent's
+    pub outposts: Vec<Square>,     //On the 4th to 6th ranks, where no opponent pawn can attack
+    pub pawn_squares: Vec<PawnSquare>,
+}
+
+fn pawns(board: &Board, color: Color) -> Vec<Square> {
+    let mut found: Vec<Square> = board
+        .pieces
+        .iter()
+        .filter(|(_, p)| p.color == color && p.title == Title::Pawn)
+        .map(|(&s, _)| s)
+        .collect();
+    found.sort_by_key(|s| s.index());
+    found
+}
+
+impl PawnStructure {
+    pub fn new(board: &Board, color: Color) -> Self {
+        let own = pawns(board, color);
+        let enemy = pawns(board, color.inverse());
+        let forward = color.forward();
+        //Whether b is further up the board than a, seen from this side
+        let ahead = |a: Square, b: Square| (b.rank() - a.rank()) * forward > 0;
+        let beside = |a: Square, b: Square| (a.file() - b.file()).abs() == 1;
+        let on_file = |list: &[Square], file: i32| list.iter().any(|s| s.file() == file);
+        let filter = |test: &dyn Fn(Square) -> bool| -> Vec<Square> {
+            own.iter().copied().filter(|&s| test(s)).collect()
+        };
+        //Opponent pawns on neighbouring files further up the board could attack the square
+        let contestable = |s: Square| enemy.iter().any(|&e| beside(s, e) && ahead(s, e));
+        let attacked_by_enemy = |s: Square| {
+            enemy
+                .iter()
+                .any(|&e| beside(s, e) && e.rank() == s.rank() + forward)
+        };
+
+        let passed = filter(&|s| {
+            !enemy
+                .iter()
+                .any(|&e| (e.file() - s.file()).abs() <= 1 && ahead(s, e))
+        });
+        let isolated = filter(&|s| !own.iter().any(|&o| beside(s, o)));
+        let doubled = filter(&|s| own.iter().any(|&o| o != s && o.file() == s.file()));
+        let connected = filter(&|s| {
+            own.iter()
+                .any(|&o| beside(s, o) && (o.rank() - s.rank()).abs() <= 1)
+        });
+        //Its neighbours have all gone ahead and an opponent pawn guards the square in front
+        let backward = filter(&|s| {
+            let neighbours: Vec<&Square> = own.iter().filter(|&&o| beside(s, o)).collect();
+            !neighbours.is_empty()
+                && neighbours.iter().all(|&&o| ahead(s, o))
+                && s.offset(forward, 0).is_some_and(attacked_by_enemy)
+        });
+
+        let mut islands: Vec<Vec<Square>> = Vec::new();
+        for file in 0..8 {
+            let on: Vec<Square> = own.iter().copied().filter(|s| s.file() == file).collect();
+            if on.is_empty() {
+                continue;
+            }
+            match islands.last_mut() {
+                Some(island) if on_file(island, file - 1) => {
+                    island.extend(on);
+                    island.sort_by_key(|s| s.index());
+                }
+                _ => islands.push(on),
+            }
+        }
+        let open_files = (0..8)
+            .filter(|&f| !on_file(&own, f) && !on_file(&enemy, f))
+            .collect();
+        let half_open_files = (0..8)
+            .filter(|&f| !on_file(&own, f) && on_file(&enemy, f))
+            .collect();
+
+        //The fourth to sixth ranks seen from this side
+        let outposts = (0..64)
+            .filter_map(Square::from_index)
+            .filter(|&s| {
+                let relative = if color == Color::White {
+                    s.rank()
+                } else {
+                    7 - s.rank()
+                };
+                (3..=5).contains(&relative)
+                    && !matches!(board.pieces.get(&s), Some(p) if p.title == Title::Pawn)
+                    && own
+                        .iter()
+                        .any(|&o| beside(s, o) && o.rank() == s.rank() - forward)
+                    && !contestable(s)
+            })
+            .collect();
+
+        let king = board.king_pos[color.inverse().index()];
+        let last_rank = if color == Color::White { 7 } else { 0 };
+        let pawn_squares = passed
+            .iter()
+            .map(|&pawn| {
+                let promotion = Square::new(last_rank, pawn.file()).unwrap();
+                let mut moves = (last_rank - pawn.rank()).abs();
+                if pawn.rank() == color.seventh_rank() - 5 * forward {
+                    moves -= 1;
+                }
+                let distance = (king.rank() - promotion.rank())
+                    .abs()
+                    .max((king.file() - promotion.file()).abs());
+                let tempo = (board.to_act != color) as i32;
+                PawnSquare {
+                    pawn,
+                    promotion,
+                    moves,
+                    king_inside: distance <= moves + tempo,
+                }
+            })
+            .collect();
+
+        PawnStructure {
+            color,
+            passed,
+            isolated,
+            doubled,
+            backward,
+            connected,
+            islands,
+            open_files,
+            half_open_files,
+            outposts,
+            pawn_squares,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_pawnstructure {
+    use super::*;
+    use crate::testutil::{sq, sqs};
+
+    #[test]
+    fn standard() {
+        let board = Board::new_standard();
+        for color in [Color::White, Color::Black] {
+            let structure = PawnStructure::new(&board, color);
+            assert!(structure.passed.is_empty());
+            assert!(structure.isolated.is_empty());
+            assert!(structure.doubled.is_empty());
+            assert!(structure.backward.is_empty());
+            assert_eq!(structure.connected.len(), 8);
+            assert_eq!(structure.islands.len(), 1);
+            assert!(structure.open_files.is_empty());
+            assert!(structure.half_open_files.is_empty());
+            assert!(structure.outposts.is_empty());
+        }
+    }
+
+    #[test]
+    fn weaknesses() {
+        let fen = "4k3/8/3p2p1/P3p3/2P1P3/3P3P/7P/6K1 w - - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        let white = PawnStructure::new(&board, Color::White);
+        assert_eq!(white.passed, sqs(&["a5"]));
+        assert_eq!(white.isolated, sqs(&["h2", "h3", "a5"]));
+        assert_eq!(white.doubled, sqs(&["h2", "h3"]));
+        assert_eq!(white.backward, sqs(&["d3"]));
+        assert_eq!(white.connected, sqs(&["d3", "c4", "e4"]));
+        assert_eq!(
+            white.islands,
+            vec![sqs(&["a5"]), sqs(&["d3", "c4", "e4"]), sqs(&["h2", "h3"])]
+        );
+        assert_eq!(white.open_files, vec![1, 5]);
+        assert_eq!(white.half_open_files, vec![6]);
+        assert_eq!(white.outposts, sqs(&["g4", "b5", "d5", "b6"]));
+
+        let black = PawnStructure::new(&board, Color::Black);
+        assert!(black.passed.is_empty());
+        assert_eq!(black.isolated, sqs(&["g6"]));
+        assert_eq!(black.backward, sqs(&["d6"]));
+        assert_eq!(black.connected, sqs(&["e5", "d6"]));
+        assert_eq!(black.half_open_files, vec![0, 2, 7]);
+    }
+
+    #[test]
+    fn square_of_the_pawn() {
+        let fen = "4k3/8/8/P7/8/8/8/6K1 w - - 0 1";
+        let white = PawnStructure::new(&Board::from_fen(fen).unwrap(), Color::White);
+        let a8 = sq("a8");
+        assert_eq!(
+            white.pawn_squares,
+            vec![PawnSquare {
+                pawn: sq("a5"),
+                promotion: a8,
+                moves: 3,
+                king_inside: false,
+            }]
+        );
+        let fen = "4k3/8/8/P7/8/8/8/6K1 b - - 0 1";
+        let white = PawnStructure::new(&Board::from_fen(fen).unwrap(), Color::White);
+        assert!(white.pawn_squares[0].king_inside);
+
+        //The double step saves a move
+        let fen = "7k/7p/8/8/8/8/8/K7 w - - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        let black = PawnStructure::new(&board, Color::Black);
+        assert_eq!(black.pawn_squares[0].moves, 5);
+        assert!(!black.pawn_squares[0].king_inside);
+    }
+}
@@ -0,0 +1,860 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::eco::classify;
+use crate::fen::{FenError, STANDARD_FEN};
+use crate::game::{Game, GameResult};
+use crate::movedata::MoveData;
+use crate::movegen::generate_action_space;
+use crate::uciclient::Score;
+use std::fmt;
+use std::io::BufRead;
+use std::time::Duration;
+
+//Tags every PGN export has, in the order they are written
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const LINE_WIDTH: usize = 80;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum PgnError {
+    BadTag(String),
+    BadFen(FenError),
+    //Ply counted from the start of the movetext
+    IllegalMove {
+        ply: usize,
+        san: String,
+    },
+    //A comment or variation that never ends
+    Unterminated(&'static str),
+    //Only reported when reading strictly
+    NonCanonical {
+        ply: usize,
+        san: String,
+        expected: String,
+    },
+    ResultMismatch {
+        tag: String,
+        actual: String,
+    },
+    Unreadable(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::BadTag(line) => write!(f, "invalid tag pair '{}'", line),
+            PgnError::BadFen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnError::IllegalMove { ply, san } => {
+                write!(f, "illegal move '{}' at ply {}", san, ply + 1)
+            }
+            PgnError::Unterminated(what) => write!(f, "unterminated {}", what),
+            PgnError::NonCanonical { ply, san, expected } => write!(
+                f,
+                "move '{}' at ply {} should be written '{}'",
+                san,
+                ply + 1,
+                expected
+            ),
+            PgnError::ResultMismatch { tag, actual } => {
+                write!(f, "result tag '{}' but the game ended {}", tag, actual)
+            }
+            PgnError::Unreadable(e) => write!(f, "could not read input: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+pub fn result_string(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Won(Color::White) => "1-0",
+        GameResult::Won(Color::Black) => "0-1",
+        GameResult::Tied => "1/2-1/2",
+        GameResult::Ongoing => "*",
+    }
+}
+
+//A game with its tag pairs
+pub struct Pgn {
+    pub tags: Vec<(String, String)>,
+    pub game: Game,
+    pub commands: Vec<(usize, CommentCommand)>, //With the number of moves played at the time
+}
+
+impl Pgn {
+    pub fn new(game: Game) -> Self {
+        Self {
+            tags: Vec::new(),
+            game,
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    //Reads a single game. Variations and NAGs are skipped, comments only keep their commands.
+    pub fn parse(text: &str) -> Result<Self, PgnError> {
+        let lines = text.lines().enumerate().map(|(i, l)| (i + 1, l));
+        parse_game(lines, false).map_err(|(_, e)| e)
+    }
+}
+
+//Errors come with the line they were found on
+fn parse_game<'a>(
+    lines: impl Iterator<Item = (usize, &'a str)>,
+    strict: bool,
+) -> Result<Pgn, (usize, PgnError)> {
+    let mut tags = Vec::new();
+    let mut fen_line = 0;
+    let mut movetext = String::new();
+    let mut movetext_lines = Vec::new(); //Source line of each movetext line
+    for (n, line) in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && movetext.trim().is_empty() {
+            let tag = parse_tag(trimmed).map_err(|e| (n, e))?;
+            if tag.0 == "FEN" {
+                fen_line = n;
+            }
+            tags.push(tag);
+        } else if !trimmed.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+            movetext_lines.push(n);
+        }
+    }
+    let source_line = |i: usize| movetext_lines.get(i).copied().unwrap_or(0);
+
+    let start = match tags.iter().find(|(n, _)| n == "FEN") {
+        Some((_, fen)) => Board::from_fen(fen).map_err(|e| (fen_line, PgnError::BadFen(e)))?,
+        None => Board::new_standard(),
+    };
+    let movetext = movetext_tokens(&movetext).map_err(|(i, e)| (source_line(i), e))?;
+    let mut game = Game::from_board(start);
+    let mut last_line = 0;
+    for (ply, (san, line)) in movetext.moves.into_iter().enumerate() {
+        last_line = source_line(line);
+        let m = match MoveData::from_san(&game.board, &san) {
+            Some(m) => m,
+            None => return Err((source_line(line), PgnError::IllegalMove { ply, san })),
+        };
+        if strict {
+            let expected = m.to_san(&game.board);
+            if !generate_action_space(game.board.clone()).contains(&m) {
+                return Err((source_line(line), PgnError::IllegalMove { ply, san }));
+            }
+            if san.trim_end_matches(&['!', '?'][..]) != expected {
+                let error = PgnError::NonCanonical { ply, san, expected };
+                return Err((source_line(line), error));
+            }
+        }
+        game.make_move(m);
+    }
+    if strict && game.result != GameResult::Ongoing {
+        let actual = result_string(game.result);
+        match tags.iter().find(|(n, _)| n == "Result") {
+            Some((_, tag)) if tag != actual => {
+                let error = PgnError::ResultMismatch {
+                    tag: tag.clone(),
+                    actual: actual.to_string(),
+                };
+                return Err((last_line, error));
+            }
+            _ => (),
+        }
+    }
+    Ok(Pgn {
+        tags,
+        game,
+        commands: movetext.commands,
+    })
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), PgnError> {
+    let bad = || PgnError::BadTag(line.to_string());
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(bad)?;
+    let (name, value) = inner.split_once(char::is_whitespace).ok_or_else(bad)?;
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(bad)?;
+    Ok((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+#[derive(Default)]
+struct Movetext {
+    moves: Vec<(String, usize)>, //SAN and the movetext line it is on
+    commands: Vec<(usize, CommentCommand)>,
+}
+
+//The SAN moves of the main line and the commands in its comments
+fn movetext_tokens(movetext: &str) -> Result<Movetext, (usize, PgnError)> {
+    let mut out = Movetext::default();
+    let mut opened = Vec::new(); //Line of each variation still open
+    let mut line = 0;
+    let mut chars = movetext.chars();
+    let mut token = String::new();
+    let mut token_line = 0;
+    while let Some(c) = chars.next() {
+        let depth = opened.len();
+        match c {
+            '{' => {
+                flush(&mut token, token_line, depth, &mut out.moves);
+                let start = line;
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            comment.push(c);
+                        }
+                        None => return Err((start, PgnError::Unterminated("comment"))),
+                    }
+                }
+                if depth == 0 {
+                    for command in comment_commands(&comment) {
+                        out.commands.push((out.moves.len(), command));
+                    }
+                }
+            }
+            ';' => {
+                flush(&mut token, token_line, depth, &mut out.moves);
+                if chars.by_ref().any(|c| c == '\n') {
+                    line += 1;
+                }
+            }
+            '(' => {
+                flush(&mut token, token_line, depth, &mut out.moves);
+                opened.push(line);
+            }
+            ')' => {
+                flush(&mut token, token_line, depth, &mut out.moves);
+                opened.pop();
+            }
+            c if c.is_whitespace() => {
+                flush(&mut token, token_line, depth, &mut out.moves);
+                if c == '\n' {
+                    line += 1;
+                }
+            }
+            c => {
+                if token.is_empty() {
+                    token_line = line;
+                }
+                token.push(c);
+            }
+        }
+    }
+    flush(&mut token, token_line, opened.len(), &mut out.moves);
+    match opened.first() {
+        Some(&start) => Err((start, PgnError::Unterminated("variation"))),
+        None => Ok(out),
+    }
+}
+
+fn flush(token: &mut String, line: usize, depth: usize, moves: &mut Vec<(String, usize)>) {
+    //Move numbers may be glued to the move, as in 1.e4
+    let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let is_result = matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*");
+    if depth == 0 && !san.is_empty() && !is_result && !san.starts_with('$') {
+        moves.push((san.to_string(), line));
+    }
+    token.clear();
+}
+
+//Commands embedded in comments, e.g. [%clk 0:05:00] or [%eval -1.25]
+#[derive(PartialEq, Clone, Debug)]
+pub enum CommentCommand {
+    Clock(Duration),
+    Eval(Score),
+    Other { name: String, value: String },
+}
+
+impl fmt::Display for CommentCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommentCommand::Clock(time) => {
+                let seconds = time.as_secs();
+                let h_m_s = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+                write!(f, "[%clk {}:{:02}:{:02}", h_m_s.0, h_m_s.1, h_m_s.2)?;
+                match time.subsec_millis() / 100 {
+                    0 => write!(f, "]"),
+                    tenths => write!(f, ".{}]", tenths),
+                }
+            }
+            CommentCommand::Eval(Score::Centipawns(cp)) => {
+                write!(f, "[%eval {:.2}]", *cp as f64 / 100.0)
+            }
+            CommentCommand::Eval(Score::Mate(moves)) => write!(f, "[%eval #{}]", moves),
+            CommentCommand::Other { name, value } => write!(f, "[%{} {}]", name, value),
+        }
+    }
+}
+
+fn comment_commands(comment: &str) -> Vec<CommentCommand> {
+    let mut commands = Vec::new();
+    for part in comment.split("[%").skip(1) {
+        let body = match part.split_once(']') {
+            Some((body, _)) => body.trim(),
+            None => continue,
+        };
+        let (name, value) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+        let value = value.trim();
+        let parsed = match name {
+            "clk" => parse_clock(value).map(CommentCommand::Clock),
+            "eval" => parse_eval(value).map(CommentCommand::Eval),
+            _ => None,
+        };
+        commands.push(parsed.unwrap_or_else(|| CommentCommand::Other {
+            name: name.to_string(),
+            value: value.to_string(),
+        }));
+    }
+    commands
+}
+
+//h:mm:ss with optional fractions of a second
+fn parse_clock(value: &str) -> Option<Duration> {
+    let mut millis = 0u64;
+    for part in value.split(':') {
+        let seconds: f64 = part.parse().ok()?;
+        if seconds < 0.0 {
+            return None;
+        }
+        millis = millis * 60 + (seconds * 1000.0).round() as u64;
+    }
+    Some(Duration::from_millis(millis))
+}
+
+//Pawns from White's point of view, or #n for a mate, with an optional ,depth suffix
+fn parse_eval(value: &str) -> Option<Score> {
+    let value = value.split(',').next()?;
+    match value.strip_prefix('#') {
+        Some(moves) => moves.parse().ok().map(Score::Mate),
+        None => {
+            let pawns: f64 = value.parse().ok()?;
+            Some(Score::Centipawns((pawns * 100.0).round() as i32))
+        }
+    }
+}
+
+//Where a game in a multi-game file went wrong, games counted from 1
+#[derive(PartialEq, Clone, Debug)]
+pub struct ImportError {
+    pub game: usize,
+    pub line: usize,
+    pub error: PgnError,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "game {}, line {}: {}", self.game, self.line, self.error)
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+//Reads games one at a time from a file of any size. A game that fails to parse is reported
+//and reading continues at the next [Event tag.
+pub struct PgnReader<R> {
+    input: R,
+    line: usize,
+    peeked: Option<(usize, String)>,
+    games: usize,
+    strict: bool,
+    done: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            line: 0,
+            peeked: None,
+            games: 0,
+            strict: false,
+            done: false,
+        }
+    }
+
+    //Also checks that every move is written in standard SAN with the right check marks and
+    //that the Result tag agrees with a mate or stalemate on the board
+    pub fn new_strict(input: R) -> Self {
+        Self {
+            strict: true,
+            ..Self::new(input)
+        }
+    }
+
+    fn next_line(&mut self) -> Result<Option<(usize, String)>, ImportError> {
+        if let Some(line) = self.peeked.take() {
+            return Ok(Some(line));
+        }
+        let mut bytes = Vec::new();
+        match self.input.read_until(b'\n', &mut bytes) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                self.line += 1;
+                //Corpora are not always UTF-8, a mangled name is better than a lost game
+                let text = String::from_utf8_lossy(&bytes);
+                Ok(Some((
+                    self.line,
+                    text.trim_end_matches(&['\r', '\n'][..]).to_string(),
+                )))
+            }
+            Err(e) => Err(ImportError {
+                game: self.games,
+                line: self.line + 1,
+                error: PgnError::Unreadable(e.to_string()),
+            }),
+        }
+    }
+
+    //The lines of the next game, which ends where the tags of another begin. Lines inside a
+    //{...} comment belong to the movetext even if they look like tags, except an Event tag:
+    //a comment never closed ends there rather than swallowing the rest of the file.
+    fn game_lines(&mut self) -> Result<Vec<(usize, String)>, ImportError> {
+        let mut lines: Vec<(usize, String)> = Vec::new();
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        while let Some((n, line)) = self.next_line()? {
+            let trimmed = line.trim();
+            if in_comment && !trimmed.starts_with("[Event \"") {
+                in_comment = ends_in_comment(trimmed, true);
+                lines.push((n, line));
+                continue;
+            }
+            let starts_game =
+                trimmed.starts_with("[Event ") || (in_movetext && trimmed.starts_with('['));
+            if starts_game && !lines.is_empty() {
+                self.peeked = Some((n, line));
+                break;
+            }
+            if trimmed.is_empty() && lines.is_empty() {
+                continue;
+            }
+            if !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%') {
+                in_movetext = true;
+                in_comment = ends_in_comment(trimmed, false);
+            }
+            lines.push((n, line));
+        }
+        Ok(lines)
+    }
+
+    fn skip_to_event(&mut self) -> Result<(), ImportError> {
+        while let Some((n, line)) = self.next_line()? {
+            if line.trim_start().starts_with("[Event") {
+                self.peeked = Some((n, line));
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Whether a {...} comment is still open at the end of a movetext line. Comments do not
+//nest, and a ; outside one comments out the rest of the line.
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    for c in line.chars() {
+        match c {
+            '{' if !in_comment => in_comment = true,
+            '}' if in_comment => in_comment = false,
+            ';' if !in_comment => break,
+            _ => (),
+        }
+    }
+    in_comment
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Pgn, ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let lines = match self.game_lines() {
+            Ok(lines) if lines.is_empty() => return None,
+            Ok(lines) => lines,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        self.games += 1;
+        let parsed = parse_game(lines.iter().map(|(n, l)| (*n, l.as_str())), self.strict);
+        match parsed {
+            Ok(pgn) => Some(Ok(pgn)),
+            Err((line, error)) => {
+                if let Err(e) = self.skip_to_event() {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                let line = if line == 0 { lines[0].0 } else { line };
+                Some(Err(ImportError {
+                    game: self.games,
+                    line,
+                    error,
+                }))
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct ValidationReport {
+    pub games: usize, //Including the broken ones
+    pub moves: usize,
+    pub errors: Vec<ImportError>,
+}
+
+//Reads the whole file strictly, keeping only the counts and the errors
+pub fn validate_pgn(input: impl BufRead) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    for result in PgnReader::new_strict(input) {
+        report.games += 1;
+        match result {
+            Ok(pgn) => report.moves += pgn.game.moves.len(),
+            Err(e) => report.errors.push(e),
+        }
+    }
+    report
+}
+
+impl fmt::Display for Pgn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = match self.game.result {
+            GameResult::Ongoing => self.tag("Result").unwrap_or("*"),
+            r => result_string(r),
+        };
+        for &name in SEVEN_TAG_ROSTER.iter() {
+            let value = match name {
+                "Result" => result,
+                "Date" => self.tag(name).unwrap_or("????.??.??"),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            writeln!(f, "[{} \"{}\"]", name, escape(value))?;
+        }
+        let start_fen = self.game.start.to_fen();
+        let custom_start = start_fen != STANDARD_FEN;
+        if custom_start {
+            writeln!(f, "[SetUp \"1\"]")?;
+            writeln!(f, "[FEN \"{}\"]", start_fen)?;
+        }
+        //Named from the table unless the game already says which opening it is
+        let named = self.tag("ECO").is_some() || self.tag("Opening").is_some();
+        if let (false, Some(opening)) = (named, classify(&self.game)) {
+            writeln!(f, "[ECO \"{}\"]", opening.eco)?;
+            writeln!(f, "[Opening \"{}\"]", escape(opening.name))?;
+        }
+        for (name, value) in self.tags.iter() {
+            let written = SEVEN_TAG_ROSTER.contains(&name.as_str())
+                || (custom_start && (name == "SetUp" || name == "FEN"));
+            if !written {
+                writeln!(f, "[{} \"{}\"]", name, escape(value))?;
+            }
+        }
+        writeln!(f)?;
+
+        //Resignations, draw offers and the like are written as comments where they happened
+        let comments = |words: &mut Vec<String>, played: usize| {
+            let mut any = false;
+            for (_, action) in self.game.actions.iter().filter(|(p, _)| *p == played) {
+                words.push(format!("{{{}}}", action));
+                any = true;
+            }
+            let commands: Vec<String> = self
+                .commands
+                .iter()
+                .filter(|(p, _)| *p == played)
+                .map(|(_, c)| c.to_string())
+                .collect();
+            if !commands.is_empty() {
+                words.push(format!("{{{}}}", commands.join(" ")));
+                any = true;
+            }
+            any
+        };
+        let mut words = Vec::new();
+        let mut board = self.game.start.clone();
+        let mut after_comment = comments(&mut words, 0);
+        for (i, m) in self.game.moves.iter().enumerate() {
+            if board.to_act == Color::White {
+                words.push(format!("{}.", board.fullmove_number));
+            } else if i == 0 || after_comment {
+                words.push(format!("{}...", board.fullmove_number));
+            }
+            words.push(m.to_san(&board));
+            board.make_move(*m);
+            after_comment = comments(&mut words, i + 1);
+        }
+        words.push(result.to_string());
+
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.len() + 1 + word.len() > LINE_WIDTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test_pgn {
+    use super::*;
+    #[test]
+    fn round_trip() {
+        let text = "[Event \"Casual\"]\n[Site \"?\"]\n[Date \"2020.01.01\"]\n[Round \"1\"]\n\
+                    [White \"Morris\"]\n[Black \"Björn\"]\n[Result \"*\"]\n[Opening \"Ruy Lopez\"]\n\n\
+                    1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 *\n";
+        let pgn = Pgn::parse(text).unwrap();
+        assert_eq!(pgn.game.moves.len(), 6);
+        assert_eq!(pgn.tag("White"), Some("Morris"));
+        assert_eq!(pgn.to_string(), text);
+    }
+    #[test]
+    fn opening_tags() {
+        let pgn = Pgn::parse("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. h3 *").unwrap();
+        let text = pgn.to_string();
+        assert!(text
+            .contains("[Result \"*\"]\n[ECO \"C68\"]\n[Opening \"Ruy Lopez: Morphy Defense\"]\n"));
+        assert_eq!(Pgn::parse(&text).unwrap().tag("ECO"), Some("C68"));
+        let mut pgn = pgn;
+        pgn.set_tag("ECO", "C77");
+        assert!(!pgn.to_string().contains("[Opening"));
+        assert!(!Pgn::new(Game::new()).to_string().contains("[ECO"));
+    }
+    #[test]
+    fn comments_variations_and_nags() {
+        let text = "1.e4 {best by test} e5 (1... c5 2. Nf3 (2. c3) d6) 2. Nf3 $1 ; a comment\n\
+                    Nc6?! 3. Bc4 1-0";
+        let pgn = Pgn::parse(text).unwrap();
+        let moves: Vec<String> = pgn.game.moves.iter().map(|m| m.to_uci()).collect();
+        assert_eq!(moves, vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"]);
+        //The result of an unfinished game is kept from the tags
+        let mut pgn = pgn;
+        pgn.set_tag("Result", "1-0");
+        assert!(pgn.to_string().ends_with("3. Bc4 1-0\n"));
+    }
+    #[test]
+    fn custom_start_and_mate() {
+        let mut game =
+            Game::from_board(Board::from_fen("7k/8/6K1/7p/8/8/8/R7 b - - 0 40").unwrap());
+        game.make_move(MoveData::from_san(&game.board, "h4").unwrap());
+        game.make_move(MoveData::from_san(&game.board, "Ra8").unwrap());
+        let text = Pgn::new(game).to_string();
+        assert!(text.contains("[SetUp \"1\"]\n[FEN \"7k/8/6K1/7p/8/8/8/R7 b - - 0 40\"]"));
+        assert!(text.contains("[Result \"1-0\"]"));
+        assert!(text.ends_with("40... h4 41. Ra8# 1-0\n"));
+        let parsed = Pgn::parse(&text).unwrap();
+        assert_eq!(parsed.game.result, GameResult::Won(Color::White));
+    }
+    #[test]
+    fn actions_as_comments() {
+        let mut game = Game::new();
+        game.offer_draw(Color::White).unwrap();
+        game.make_move(MoveData::from_san(&game.board, "e4").unwrap());
+        game.decline_draw(Color::Black).unwrap();
+        game.make_move(MoveData::from_san(&game.board, "e5").unwrap());
+        game.resign(Color::White).unwrap();
+        let text = Pgn::new(game).to_string();
+        assert!(text.replace('\n', " ").ends_with(
+            "{White offers a draw} 1. e4 {Black declines the draw} 1... e5 {White resigns} 0-1 "
+        ));
+        assert_eq!(Pgn::parse(&text).unwrap().game.moves.len(), 2);
+    }
+    #[test]
+    fn long_games_wrap() {
+        let mut game = Game::new();
+        for _ in 0..10 {
+            for san in ["Nf3", "Nf6", "Ng1", "Ng8"].iter() {
+                game.make_move(MoveData::from_san(&game.board, san).unwrap());
+            }
+        }
+        let text = Pgn::new(game).to_string();
+        assert!(text.lines().all(|l| l.len() <= LINE_WIDTH));
+        assert_eq!(Pgn::parse(&text).unwrap().game.moves.len(), 40);
+    }
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Pgn::parse("1. e4 e4").err(),
+            Some(PgnError::IllegalMove {
+                ply: 1,
+                san: "e4".to_string()
+            })
+        );
+        assert!(matches!(
+            Pgn::parse("[Event]\n1. e4"),
+            Err(PgnError::BadTag(_))
+        ));
+        assert!(matches!(
+            Pgn::parse("[FEN \"nonsense\"]\n1. e4"),
+            Err(PgnError::BadFen(_))
+        ));
+        assert_eq!(
+            Pgn::parse("1. e4 {never closed").err(),
+            Some(PgnError::Unterminated("comment"))
+        );
+        assert_eq!(
+            Pgn::parse("1. e4 (1. d4").err(),
+            Some(PgnError::Unterminated("variation"))
+        );
+    }
+    #[test]
+    fn comment_commands() {
+        let text = "1. e4 {[%clk 0:04:59.9] [%eval 0.31]} e5 {[%eval #-3,20] [%emt 0:00:02]} \
+                    (1... c5 {[%clk 1:00:00]}) 2. Nf3 {[%clk one]} *";
+        let pgn = Pgn::parse(text).unwrap();
+        assert_eq!(
+            pgn.commands,
+            vec![
+                (1, CommentCommand::Clock(Duration::from_millis(299_900))),
+                (1, CommentCommand::Eval(Score::Centipawns(31))),
+                (2, CommentCommand::Eval(Score::Mate(-3))),
+                (
+                    2,
+                    CommentCommand::Other {
+                        name: "emt".to_string(),
+                        value: "0:00:02".to_string()
+                    }
+                ),
+                (
+                    3,
+                    CommentCommand::Other {
+                        name: "clk".to_string(),
+                        value: "one".to_string()
+                    }
+                ),
+            ]
+        );
+        let written = pgn.to_string();
+        assert!(written.contains("1. e4 {[%clk 0:04:59.9] [%eval 0.31]} 1... e5 {[%eval #-3]"));
+        assert_eq!(Pgn::parse(&written).unwrap().commands.len(), 5);
+    }
+    #[test]
+    fn reader() {
+        let text = "[Event \"One\"]\n[Result \"*\"]\n\n1. e4 e5 *\n\n\
+                    [Event \"Two\"]\n\n1. e4 e5\n2. Ke3 *\n\
+                    [Site \"Not an event\"]\n\n1. d4 *\n\n\
+                    [Event \"Three\"]\n[White \"Bj\u{f6}rn\"]\n1. d4 {a\nlong comment} d5 *\n\
+                    [Event \"Four\"]\n\n1. c4 {never closed\n";
+        let mut input = text.as_bytes().to_vec();
+        //A Latin-1 name in the fourth game
+        let at = input.windows(3).position(|w| w == b"Bj\xc3").unwrap();
+        input.splice(at + 2..at + 4, vec![0xf6]);
+        let results: Vec<Result<Pgn, ImportError>> =
+            PgnReader::new(std::io::Cursor::new(input)).collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().tag("Event"), Some("One"));
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&ImportError {
+                game: 2,
+                line: 9,
+                error: PgnError::IllegalMove {
+                    ply: 2,
+                    san: "Ke3".to_string()
+                }
+            })
+        );
+        //The game after the broken one had no Event tag and was skipped with it
+        let three = results[2].as_ref().unwrap();
+        assert_eq!(three.tag("Event"), Some("Three"));
+        assert_eq!(three.tag("White"), Some("Bj\u{fffd}rn"));
+        assert_eq!(three.game.moves.len(), 2);
+        assert_eq!(
+            results[3].as_ref().err().map(|e| (e.game, e.line)),
+            Some((4, 20))
+        );
+    }
+    #[test]
+    fn reader_comment_lines() {
+        //Lines in a comment that start like tags do not start another game
+        let text = "[Event \"One\"]\n\n1. e4 {a long comment that wraps\n[%clk 0:01:00]} e5 *\n\n\
+                    [Event \"Two\"]\n\n1. d4 ; not a {comment\nd5 *\n";
+        let results: Vec<Pgn> = PgnReader::new(text.as_bytes())
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].game.moves.len(), 2);
+        assert_eq!(results[1].tag("Event"), Some("Two"));
+        assert_eq!(results[1].game.moves.len(), 2);
+
+        //A comment never closed ends at the next game's Event tag
+        let text = "[Event \"One\"]\n\n1. e4 {oops never closed\n\n\
+                    [Event \"Two\"]\n\n1. d4 d5 *\n\n\
+                    [Event \"Three\"]\n\n1. c4 *\n";
+        let results: Vec<Result<Pgn, ImportError>> = PgnReader::new(text.as_bytes()).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[0].as_ref().err(),
+            Some(&ImportError {
+                game: 1,
+                line: 3,
+                error: PgnError::Unterminated("comment")
+            })
+        );
+        assert_eq!(results[1].as_ref().unwrap().tag("Event"), Some("Two"));
+        assert_eq!(results[1].as_ref().unwrap().game.moves.len(), 2);
+        assert_eq!(results[2].as_ref().unwrap().tag("Event"), Some("Three"));
+    }
+    #[test]
+    fn strict_validation() {
+        let text = "[Event \"Fine\"]\n[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n\
+                    [Event \"No mate mark\"]\n\n1. f3 e5 2. g4 Qh4 0-1\n\n\
+                    [Event \"Wrong result\"]\n[Result \"1-0\"]\n\n1. f3 e5 2. g4 Qh4# 1-0\n\n\
+                    [Event \"Illegal\"]\n\n1. e4 e5 2. e5 *\n";
+        let report = validate_pgn(text.as_bytes());
+        assert_eq!((report.games, report.moves), (4, 4));
+        let errors: Vec<(usize, usize)> = report.errors.iter().map(|e| (e.game, e.line)).collect();
+        assert_eq!(errors, vec![(2, 8), (3, 13), (4, 17)]);
+        assert_eq!(
+            report.errors[0].error,
+            PgnError::NonCanonical {
+                ply: 3,
+                san: "Qh4".to_string(),
+                expected: "Qh4#".to_string()
+            }
+        );
+        //Lenient reading accepts the missing mark
+        assert_eq!(
+            PgnReader::new(text.as_bytes())
+                .filter(|r| r.is_ok())
+                .count(),
+            3
+        );
+    }
+}
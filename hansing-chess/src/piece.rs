@@ -1,6 +1,7 @@
 use crate::color::Color;
 use crate::title::Title;
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub color: Color,
     pub title: Title,
@@ -0,0 +1,431 @@
+use crate::board::Board;
+use crate::movedata::MoveData;
+use crate::movegen::generate_action_space;
+use crate::zobrist::hash_board;
+use std::collections::HashMap;
+
+//A move of the side solving the problem and every answer to it
+#[derive(PartialEq, Clone, Debug)]
+pub struct Solution {
+    pub key: MoveData,
+    pub defences: Vec<Defence>, //Empty when the move mates
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Defence {
+    pub reply: MoveData,
+    pub continuations: Vec<Solution>, //Every move still mating in time
+}
+
+impl Solution {
+    //Moves of the longest line, counting both sides
+    pub fn plies(&self) -> usize {
+        1 + self
+            .defences
+            .iter()
+            .map(|d| {
+                1 + d
+                    .continuations
+                    .iter()
+                    .map(Solution::plies)
+                    .min()
+                    .unwrap_or(0)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+//Why a try fails, the way a problem's tries are listed with their refutations
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Refutation {
+    Defence(MoveData), //A reply after which the goal is not reached in time
+    Stalemate,         //The try leaves the other side without a move
+    Mate,              //In a selfmate, the try mates the other side instead
+}
+
+//Legal moves with the positions they lead to, checks first, then captures
+fn ordered_moves(board: &Board) -> Vec<(MoveData, Board)> {
+    let mut moves: Vec<(MoveData, Board, bool)> = generate_action_space(board.clone())
+        .into_iter()
+        .map(|m| {
+            let capture = board.captured_piece(&m).is_some();
+            let mut after = board.clone();
+            after.make_move(m);
+            (m, after, capture)
+        })
+        .collect();
+    moves.sort_by_key(|(_, after, capture)| (!after.in_check, !capture));
+    moves.into_iter().map(|(m, after, _)| (m, after)).collect()
+}
+
+fn is_checkmate(board: &Board) -> bool {
+    board.in_check && generate_action_space(board.clone()).is_empty()
+}
+
+//The position with the other side to move, as set play is solved. None when the side to move
+//is in check and cannot pass.
+pub fn pass(board: &Board) -> Option<Board> {
+    if board.in_check {
+        return None;
+    }
+    let mut passed = board.clone();
+    passed.en_passant = None;
+    passed.next_turn();
+    Some(passed)
+}
+
+//Depth limited AND/OR search proving that the side to move mates in at most a number of moves,
+//or for selfmates that it forces the other side to mate it
+#[derive(Default)]
+pub struct MateSolver {
+    proven: HashMap<(u64, u32), bool>, //By position and moves left
+    nodes: u64,
+    selfmate: bool,
+}
+
+impl MateSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn selfmate() -> Self {
+        Self {
+            selfmate: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    //Whether the side to move reaches the goal in at most this many moves whatever the defence
+    pub fn is_mate_in(&mut self, board: &Board, moves: u32) -> bool {
+        if moves == 0 {
+            return false;
+        }
+        let key = (hash_board(board), moves);
+        if let Some(&proven) = self.proven.get(&key) {
+            return proven;
+        }
+        self.nodes += 1;
+        let selfmate = self.selfmate;
+        let proven = ordered_moves(board)
+            .iter()
+            //Only a check can mate with the last move
+            .filter(|(_, after)| selfmate || moves > 1 || after.in_check)
+            .any(|(_, after)| self.defeats_all(after, moves - 1));
+        self.proven.insert(key, proven);
+        proven
+    }
+
+    //Whether every defence in the position runs into the goal with the moves left
+    fn defeats_all(&mut self, board: &Board, moves: u32) -> bool {
+        let defences = ordered_moves(board);
+        if defences.is_empty() {
+            //Mate by the solving side, which a selfmate must not give, or stalemate
+            return board.in_check && !self.selfmate;
+        }
+        defences
+            .iter()
+            .all(|(_, after)| self.answered(after, moves))
+    }
+
+    //Whether the position after a defence is still solved in time
+    fn answered(&mut self, after: &Board, moves: u32) -> bool {
+        if self.selfmate && is_checkmate(after) {
+            return true;
+        }
+        self.is_mate_in(after, moves)
+    }
+
+    //Every key move reaching the goal in at most this many moves, with the full tree below it.
+    //For selfmates the defences that mate have no continuations.
+    pub fn solve(&mut self, board: &Board, moves: u32) -> Vec<Solution> {
+        let mut solutions = Vec::new();
+        if moves == 0 {
+            return solutions;
+        }
+        for (key, after) in ordered_moves(board) {
+            if self.defeats_all(&after, moves - 1) {
+                solutions.push(self.tree(key, &after, moves - 1));
+            }
+        }
+        solutions
+    }
+
+    fn tree(&mut self, key: MoveData, after: &Board, moves: u32) -> Solution {
+        let mut defences = Vec::new();
+        for (reply, position) in ordered_moves(after) {
+            let continuations = self.solve(&position, moves);
+            defences.push(Defence {
+                reply,
+                continuations,
+            });
+        }
+        Solution { key, defences }
+    }
+
+    //Set play: every move of the side not to move, as if it moved first, with the continuations
+    //solving in time from there. Empty continuations mean no prepared answer.
+    pub fn set_play(&mut self, board: &Board, moves: u32) -> Vec<Defence> {
+        let passed = match pass(board) {
+            Some(passed) => passed,
+            None => return Vec::new(),
+        };
+        let mut defences = Vec::new();
+        for (reply, position) in ordered_moves(&passed) {
+            let continuations = if self.selfmate && is_checkmate(&position) {
+                Vec::new()
+            } else {
+                self.solve(&position, moves)
+            };
+            defences.push(Defence {
+                reply,
+                continuations,
+            });
+        }
+        defences
+    }
+
+    //What stops the move from reaching the goal in time, None if it solves the problem
+    pub fn refutation(&mut self, board: &Board, key: MoveData, moves: u32) -> Option<Refutation> {
+        let mut after = board.clone();
+        after.make_move(key);
+        let defences = ordered_moves(&after);
+        if defences.is_empty() {
+            return match (after.in_check, self.selfmate) {
+                (false, _) => Some(Refutation::Stalemate),
+                (true, true) => Some(Refutation::Mate),
+                (true, false) => None,
+            };
+        }
+        defences
+            .into_iter()
+            .find(|(_, position)| !self.answered(position, moves.saturating_sub(1)))
+            .map(|(reply, _)| Refutation::Defence(reply))
+    }
+}
+
+//All solutions of a selfmate in n, where the side to move forces the other side to mate it
+pub fn solve_selfmate(board: &Board, moves: u32) -> Vec<Solution> {
+    MateSolver::selfmate().solve(board, moves)
+}
+
+//Every line of exactly this many half moves, both sides cooperating, after which the side not
+//moving last is mated. A helpmate in 2 is four half moves with Black to move, in 2.5 five with
+//White to move. Set play is the same with the position passed and one half move less.
+pub fn solve_helpmate(board: &Board, plies: u32) -> Vec<Vec<MoveData>> {
+    let mut solutions = Vec::new();
+    let mut helpmate = Helpmate::default();
+    helpmate.lines(board, plies, &mut Vec::new(), &mut solutions);
+    solutions
+}
+
+#[derive(Default)]
+struct Helpmate {
+    possible: HashMap<(u64, u32), bool>, //Whether a line of the length exists, by position
+}
+
+impl Helpmate {
+    fn exists(&mut self, board: &Board, plies: u32) -> bool {
+        let key = (hash_board(board), plies);
+        if let Some(&possible) = self.possible.get(&key) {
+            return possible;
+        }
+        let possible = ordered_moves(board).iter().any(|(_, after)| match plies {
+            0 => false,
+            1 => is_checkmate(after),
+            _ => self.exists(after, plies - 1),
+        });
+        self.possible.insert(key, possible);
+        possible
+    }
+
+    fn lines(
+        &mut self,
+        board: &Board,
+        plies: u32,
+        line: &mut Vec<MoveData>,
+        solutions: &mut Vec<Vec<MoveData>>,
+    ) {
+        if plies == 0 || !self.exists(board, plies) {
+            return;
+        }
+        for (m, after) in ordered_moves(board) {
+            line.push(m);
+            if plies == 1 {
+                if is_checkmate(&after) {
+                    solutions.push(line.clone());
+                }
+            } else {
+                self.lines(&after, plies - 1, line, solutions);
+            }
+            line.pop();
+        }
+    }
+}
+
+//Solutions the composer did not intend. Intended solutions are SAN moves separated by spaces,
+//move numbers allowed, and cover every line starting with them, so for direct mates and
+//selfmates the key move alone is enough.
+pub fn cooks(board: &Board, solutions: &[Vec<MoveData>], intended: &[&str]) -> Vec<Vec<MoveData>> {
+    let intended: Vec<Vec<&str>> = intended
+        .iter()
+        .map(|line| {
+            line.split_whitespace()
+                .filter(|token| !token.ends_with('.'))
+                .collect()
+        })
+        .collect();
+    solutions
+        .iter()
+        .filter(|line| {
+            let mut position = board.clone();
+            let mut san = Vec::new();
+            for &m in line.iter() {
+                san.push(m.to_san(&position));
+                position.make_move(m);
+            }
+            !intended
+                .iter()
+                .any(|i| i.len() <= san.len() && i.iter().zip(san.iter()).all(|(a, b)| a == b))
+        })
+        .cloned()
+        .collect()
+}
+
+//All solutions of a mate in n, a sound problem has exactly one
+pub fn solve_mate(board: &Board, moves: u32) -> Vec<Solution> {
+    MateSolver::new().solve(board, moves)
+}
+
+//The only key move of a mate in n, None when there is no solution or more than one
+pub fn unique_key(board: &Board, moves: u32) -> Option<MoveData> {
+    match solve_mate(board, moves).as_slice() {
+        [solution] => Some(solution.key),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test_problem {
+    use super::*;
+    fn uci(solutions: &[Solution]) -> Vec<String> {
+        let mut keys: Vec<String> = solutions.iter().map(|s| s.key.to_uci()).collect();
+        keys.sort();
+        keys
+    }
+    fn san(board: &Board, line: &[MoveData]) -> String {
+        let mut position = board.clone();
+        let mut moves = Vec::new();
+        for &m in line {
+            moves.push(m.to_san(&position));
+            position.make_move(m);
+        }
+        moves.join(" ")
+    }
+    #[test]
+    fn mate_in_one() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let solutions = solve_mate(&board, 1);
+        assert_eq!(uci(&solutions), vec!["a1a8"]);
+        assert!(solutions[0].defences.is_empty());
+        assert_eq!(solutions[0].plies(), 1);
+        assert_eq!(
+            MateSolver::new().refutation(&board, solutions[0].key, 1),
+            None
+        );
+        //Mating is the wrong goal for a selfmate
+        assert_eq!(
+            MateSolver::selfmate().refutation(&board, solutions[0].key, 1),
+            Some(Refutation::Mate)
+        );
+        //A longer limit still finds the short mate
+        assert!(MateSolver::new().is_mate_in(&board, 3));
+        assert!(!MateSolver::new().is_mate_in(&board, 0));
+    }
+    #[test]
+    fn mate_in_two() {
+        let board = Board::from_fen("k7/8/2K5/8/8/8/8/1R6 w - - 0 1").unwrap();
+        let mut solver = MateSolver::new();
+        assert!(!solver.is_mate_in(&board, 1));
+        assert!(solver.is_mate_in(&board, 2));
+        let key = unique_key(&board, 2).unwrap();
+        assert_eq!(key.to_uci(), "c6c7");
+        //The whole tree: Ka7 is forced and Ra1 mates
+        let solution = &solver.solve(&board, 2)[0];
+        assert_eq!(solution.defences.len(), 1);
+        assert_eq!(solution.defences[0].reply.to_uci(), "a8a7");
+        assert_eq!(uci(&solution.defences[0].continuations), vec!["b1a1"]);
+        assert_eq!(solution.plies(), 3);
+
+        assert_eq!(solver.refutation(&board, key, 2), None);
+        let try_move = MoveData::from_uci(&board, "c6b6").unwrap();
+        let mut after = board.clone();
+        after.make_move(try_move);
+        let a8b8 = MoveData::from_uci(&after, "a8b8").unwrap();
+        assert_eq!(
+            solver.refutation(&board, try_move, 2),
+            Some(Refutation::Defence(a8b8))
+        );
+    }
+    #[test]
+    fn cooks_and_failures() {
+        //Two keys make the problem unsound
+        let board = Board::from_fen("7k/8/5K2/8/8/8/8/R7 w - - 0 1").unwrap();
+        assert_eq!(uci(&solve_mate(&board, 2)), vec!["f6f7", "f6g6"]);
+        assert_eq!(unique_key(&board, 2), None);
+        //Stalemate is no mate
+        let board = Board::from_fen("k7/8/2K5/8/8/8/8/1R6 w - - 0 1").unwrap();
+        let stalemate = MoveData::from_uci(&board, "b1b7").unwrap();
+        assert_eq!(
+            MateSolver::new().refutation(&board, stalemate, 2),
+            Some(Refutation::Stalemate)
+        );
+        assert!(!uci(&solve_mate(&board, 2)).contains(&"b1b7".to_string()));
+        //Defended back rank
+        let board = Board::from_fen("r5k1/5ppp/8/8/8/8/5PPP/1R2R1K1 w - - 0 1").unwrap();
+        assert!(solve_mate(&board, 2).is_empty());
+    }
+    #[test]
+    fn selfmate() {
+        //Nc7+ leaves only Nxc7, which uncovers the bishop on White's king
+        let board = Board::from_fen("kr6/pb6/4N3/3n4/8/8/7P/6BK w - - 0 1").unwrap();
+        let solutions = solve_selfmate(&board, 1);
+        assert_eq!(uci(&solutions), vec!["e6c7"]);
+        assert_eq!(solutions[0].defences.len(), 1);
+        assert_eq!(solutions[0].defences[0].reply.to_uci(), "d5c7");
+        assert!(solutions[0].defences[0].continuations.is_empty());
+        assert!(solve_mate(&board, 1).is_empty());
+        //Set play: the pawn moves are answered the same way
+        let mut prepared: Vec<String> = MateSolver::selfmate()
+            .set_play(&board, 1)
+            .iter()
+            .filter(|d| !d.continuations.is_empty())
+            .map(|d| d.reply.to_uci())
+            .collect();
+        prepared.sort();
+        assert_eq!(prepared, vec!["a7a5", "a7a6"]);
+    }
+    #[test]
+    fn helpmate() {
+        let board = Board::from_fen("7k/8/6K1/8/8/8/8/R7 b - - 0 1").unwrap();
+        let lines = solve_helpmate(&board, 2);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(san(&board, &lines[0]), "Kg8 Ra8#");
+        //Given two moves there are many ways, all but the intended ones are cooks
+        let lines = solve_helpmate(&board, 4);
+        assert_eq!(lines.len(), 13);
+        assert!(lines.iter().any(|l| san(&board, l) == "Kg8 Kh6 Kh8 Ra8#"));
+        let intended = ["1... Kg8 2. Kh6 Kh8 3. Ra8#", "Kg8 Kf6"];
+        assert_eq!(cooks(&board, &lines, &intended).len(), 11);
+        assert!(cooks(&board, &lines, &["Kg8"]).is_empty());
+        //Set play, White moving first
+        let passed = pass(&board).unwrap();
+        assert_eq!(solve_helpmate(&passed, 3).len(), 11);
+        let check = Board::from_fen("7k/8/6K1/8/8/8/8/7R b - - 0 1").unwrap();
+        assert!(pass(&check).is_none());
+    }
+}
@@ -0,0 +1,562 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movedata::MoveData;
+use crate::movegen::generate_action_space;
+use crate::moverules::{BISHOP_MOVES, KING_MOVES, KNIGHT_MOVES, QUEEN_MOVES, ROOK_MOVES};
+use crate::piece::Piece;
+use crate::square::Square;
+use crate::title::Title;
+use std::fmt;
+
+//What can be put back on the square a move captured on
+const UNCAPTURES: [Title; 5] = [
+    Title::Pawn,
+    Title::Knight,
+    Title::Bishop,
+    Title::Rook,
+    Title::Queen,
+];
+
+//A position the board can have been reached from, and the move played there
+#[derive(Clone, Debug)]
+pub struct Predecessor {
+    pub board: Board,
+    pub last_move: MoveData,
+    pub uncaptured: Option<Title>, //The piece the move took, put back
+}
+
+//One way of taking back the move that put a piece on its square
+struct Candidate {
+    from: Square,
+    piece: Piece, //As it stood before the move, a pawn for promotions
+    uncapture: Option<(Square, Title)>, //Where the taken piece goes back
+    rook: Option<(Square, Square)>, //The rook of a castling move, from and to
+    en_passant: Option<Square>, //Set before an en passant capture
+}
+
+impl Candidate {
+    fn new(from: Square, piece: Piece, uncapture: Option<(Square, Title)>) -> Self {
+        Self {
+            from,
+            piece,
+            uncapture,
+            rook: None,
+            en_passant: None,
+        }
+    }
+}
+
+fn start_rank(color: Color) -> i32 {
+    7 - color.seventh_rank()
+}
+
+fn back_rank(color: Color) -> i32 {
+    if color == Color::White {
+        0
+    } else {
+        7
+    }
+}
+
+fn pawn(color: Color, s: Square) -> Piece {
+    let mut p = Piece::new(color, Title::Pawn);
+    p.has_moved = s.rank() != start_rank(color);
+    p
+}
+
+//Empty squares a piece can have come from without capturing, promoting or castling: back
+//along its moves, or one or two squares back for a pawn. Takes the emptiness of a square
+//rather than a board, so positions stored another way, like the tablebase's, unmove the same.
+pub fn quiet_origins(
+    title: Title,
+    color: Color,
+    to: Square,
+    empty: impl Fn(Square) -> bool,
+) -> Vec<Square> {
+    let mut squares = Vec::new();
+    if title == Title::Pawn {
+        let back = -color.forward();
+        if to.rank() == start_rank(color) {
+            return squares;
+        }
+        if let Some(from) = to.offset(back, 0).filter(|&s| empty(s)) {
+            if from.rank() != back_rank(color) {
+                squares.push(from);
+            }
+            if to.rank() == start_rank(color) - 2 * back {
+                if let Some(from) = from.offset(back, 0).filter(|&s| empty(s)) {
+                    squares.push(from);
+                }
+            }
+        }
+        return squares;
+    }
+    let rules: &[(i32, i32, bool)] = match title {
+        Title::Knight => &KNIGHT_MOVES,
+        Title::Bishop => &BISHOP_MOVES,
+        Title::Rook => &ROOK_MOVES,
+        Title::Queen => &QUEEN_MOVES,
+        _ => &KING_MOVES,
+    };
+    for &(rank_offset, file_offset, slides) in rules.iter() {
+        let mut s = to;
+        while let Some(next) = s.offset(rank_offset, file_offset) {
+            if !empty(next) {
+                break;
+            }
+            squares.push(next);
+            if !slides {
+                break;
+            }
+            s = next;
+        }
+    }
+    squares
+}
+
+fn candidates(board: &Board, to: Square, piece: Piece) -> Vec<Candidate> {
+    let color = piece.color;
+    let forward = color.forward();
+    let empty = |s: &Square| !board.pieces.contains_key(s);
+    let mut found = Vec::new();
+    //A pawn can be put back on the square unless it is on a back rank
+    let uncaptures = |s: Square| {
+        UNCAPTURES
+            .iter()
+            .filter(move |&&t| t != Title::Pawn || (s.rank() != 0 && s.rank() != 7))
+            .map(move |&t| Some((s, t)))
+    };
+
+    if piece.title == Title::Pawn {
+        if to.rank() == start_rank(color) {
+            return found;
+        }
+        for from in quiet_origins(Title::Pawn, color, to, |s| empty(&s)) {
+            found.push(Candidate::new(from, pawn(color, from), None));
+        }
+        for &file_offset in [-1, 1].iter() {
+            let from = match to.offset(-forward, file_offset).filter(empty) {
+                Some(from) if from.rank() != back_rank(color) => from,
+                _ => continue,
+            };
+            for uncapture in uncaptures(to) {
+                found.push(Candidate::new(from, pawn(color, from), uncapture));
+            }
+            //En passant, the pawn taken stood beside the one taking it
+            let beside = to.offset(-forward, 0).filter(empty);
+            let passed = to.offset(forward, 0).filter(empty);
+            if let (Some(beside), Some(_)) = (beside, passed) {
+                if to.rank() == start_rank(color.inverse()) - forward {
+                    found.push(Candidate {
+                        from,
+                        piece: pawn(color, from),
+                        uncapture: Some((beside, Title::Pawn)),
+                        rook: None,
+                        en_passant: Some(to),
+                    });
+                }
+            }
+        }
+        return found;
+    }
+
+    //Kings and rooks that can still castle have never moved
+    if matches!(piece.title, Title::King | Title::Rook) && !piece.has_moved {
+        return found;
+    }
+    let mut moved = piece;
+    moved.has_moved = true;
+    for from in quiet_origins(piece.title, color, to, |s| empty(&s)) {
+        found.push(Candidate::new(from, moved, None));
+        for uncapture in uncaptures(to) {
+            found.push(Candidate::new(from, moved, uncapture));
+        }
+    }
+    //Promotions, the pawn stood on the seventh rank
+    if piece.title != Title::King && to.rank() == back_rank(color.inverse()) {
+        for &file_offset in [-1, 0, 1].iter() {
+            let from = match to.offset(-forward, file_offset).filter(empty) {
+                Some(from) => from,
+                None => continue,
+            };
+            if file_offset == 0 {
+                found.push(Candidate::new(from, pawn(color, from), None));
+            } else {
+                for uncapture in uncaptures(to) {
+                    found.push(Candidate::new(from, pawn(color, from), uncapture));
+                }
+            }
+        }
+    }
+    //Castling, the king and rook go back home unmoved
+    let rank = back_rank(color);
+    if piece.title == Title::King && to.rank() == rank && (to.file() == 6 || to.file() == 2) {
+        let (rook_from, rook_to) = if to.file() == 6 { (7, 5) } else { (0, 3) };
+        let rook_from = Square::new(rank, rook_from).unwrap();
+        let rook_to = Square::new(rank, rook_to).unwrap();
+        let king_from = Square::new(rank, 4).unwrap();
+        let rook_in_place = board
+            .pieces
+            .get(&rook_to)
+            .is_some_and(|p| p.color == color && p.title == Title::Rook);
+        if rook_in_place && empty(&king_from) && empty(&rook_from) {
+            found.push(Candidate {
+                from: king_from,
+                piece: Piece::new(color, Title::King),
+                uncapture: None,
+                rook: Some((rook_from, rook_to)),
+                en_passant: None,
+            });
+        }
+    }
+    found
+}
+
+//The position before the candidate move, if it is legal and the move really leads to the board
+fn predecessor(board: &Board, to: Square, candidate: Candidate) -> Option<Predecessor> {
+    let color = candidate.piece.color;
+    let mut before = board.clone();
+    before.pieces.remove(&to);
+    before.pieces.insert(candidate.from, candidate.piece);
+    if let Some((s, title)) = candidate.uncapture {
+        let mut taken = Piece::new(color.inverse(), title);
+        taken.has_moved = title != Title::Pawn || s.rank() != start_rank(color.inverse());
+        before.pieces.insert(s, taken);
+    }
+    if let Some((rook_from, rook_to)) = candidate.rook {
+        before.pieces.remove(&rook_to);
+        before
+            .pieces
+            .insert(rook_from, Piece::new(color, Title::Rook));
+    }
+    before.to_act = color;
+    before.en_passant = candidate.en_passant;
+    before.halfmove_clock = board.halfmove_clock.saturating_sub(1);
+    if color == Color::Black {
+        before.fullmove_number = board.fullmove_number.saturating_sub(1).max(1);
+    }
+    before.update_status();
+    //The side waiting cannot be in check
+    let waiting = color.inverse();
+    if before
+        .check_if_threatend(before.king_pos[waiting.index()], waiting)
+        .0
+    {
+        return None;
+    }
+
+    let placement = board.placement_fen();
+    let last_move = generate_action_space(before.clone())
+        .into_iter()
+        .find(|m| {
+            if m.from != candidate.from || m.to != to {
+                return false;
+            }
+            let mut after = before.clone();
+            after.make_move(*m);
+            //A missing en passant square or a clock of zero may just be unknown, but anything
+            //else must match
+            if board.en_passant.is_none() {
+                after.en_passant = None;
+            }
+            after.placement_fen() == placement
+                && (board.halfmove_clock == 0 || after.halfmove_clock == board.halfmove_clock)
+        })?;
+    Some(Predecessor {
+        board: before,
+        last_move,
+        uncaptured: candidate.uncapture.map(|(_, title)| title),
+    })
+}
+
+//Every legal position the board can have been reached from in one move, with uncaptures,
+//unpromotions, en passant and castling taken back. Empty if the side not to move is in check.
+pub fn unmoves(board: &Board) -> Vec<Predecessor> {
+    let mover = board.to_act.inverse();
+    if board
+        .check_if_threatend(board.king_pos[mover.index()], mover)
+        .0
+    {
+        return Vec::new();
+    }
+    let mut pieces: Vec<(Square, Piece)> = board
+        .pieces
+        .iter()
+        .filter(|(_, p)| p.color == mover)
+        .map(|(&s, &p)| (s, p))
+        .collect();
+    pieces.sort_by_key(|(s, _)| s.index());
+    let mut predecessors = Vec::new();
+    for (to, piece) in pieces {
+        for candidate in candidates(board, to, piece) {
+            if let Some(predecessor) = predecessor(board, to, candidate) {
+                predecessors.push(predecessor);
+            }
+        }
+    }
+    predecessors
+}
+
+//Reasons a position cannot have arisen from the standard start
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Illegality {
+    KingCount(Color),
+    TooManyPieces(Color),
+    TooManyPawns(Color),
+    PawnOnBackRank,
+    TooManyPromotions(Color), //More extra pieces than pawns gone
+    TooFewCaptures(Color),    //The pawns needed more captures than the other side lost pieces
+    OpponentInCheck,          //The side not to move is in check
+    ImpossibleCheck,          //No move can have given the check
+    NoPredecessor,            //No legal position leads here
+}
+
+impl fmt::Display for Illegality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Illegality::KingCount(c) => write!(f, "{} does not have exactly one king", c),
+            Illegality::TooManyPieces(c) => write!(f, "{} has more than 16 pieces", c),
+            Illegality::TooManyPawns(c) => write!(f, "{} has more than 8 pawns", c),
+            Illegality::PawnOnBackRank => write!(f, "pawn on the first or last rank"),
+            Illegality::TooManyPromotions(c) => {
+                write!(f, "{} has more promoted pieces than missing pawns", c)
+            }
+            Illegality::TooFewCaptures(c) => write!(
+                f,
+                "{}'s pawns need more captures than there are pieces missing",
+                c
+            ),
+            Illegality::OpponentInCheck => write!(f, "the side not to move is in check"),
+            Illegality::ImpossibleCheck => write!(f, "no move can have given the check"),
+            Illegality::NoPredecessor => write!(f, "no legal move leads to the position"),
+        }
+    }
+}
+
+//Fewest pawn captures that spread pawns from one per file onto the given files
+fn pawn_captures_needed(mut files: Vec<i32>) -> usize {
+    files.sort_unstable();
+    let n = files.len();
+    //cost[i][j]: the first i pawns from the first j start files
+    let mut cost = vec![vec![usize::MAX; 9]; n + 1];
+    cost[0] = vec![0; 9];
+    for i in 1..=n {
+        for j in i..=8 {
+            let take = cost[i - 1][j - 1]
+                .saturating_add((files[i - 1] - (j as i32 - 1)).unsigned_abs() as usize);
+            cost[i][j] = cost[i][j - 1].min(take);
+        }
+    }
+    cost[n][8]
+}
+
+//Promoted pieces a side must have, beyond the ones it starts with. Two bishops on squares of
+//one color count one as promoted.
+fn promoted_pieces(board: &Board, color: Color) -> usize {
+    let count = |title: Title, light: Option<bool>| {
+        board
+            .pieces
+            .iter()
+            .filter(|(s, p)| {
+                p.color == color
+                    && p.title == title
+                    && light.is_none_or(|l| l == ((s.rank() + s.file()) % 2 == 1))
+            })
+            .count()
+    };
+    count(Title::Queen, None).saturating_sub(1)
+        + count(Title::Rook, None).saturating_sub(2)
+        + count(Title::Knight, None).saturating_sub(2)
+        + count(Title::Bishop, Some(true)).saturating_sub(1)
+        + count(Title::Bishop, Some(false)).saturating_sub(1)
+}
+
+//Everything found wrong with the position, empty when nothing rules out that it arose from the
+//standard start. The checks are necessary conditions, a position passing them can still be
+//unreachable.
+pub fn illegalities(board: &Board) -> Vec<Illegality> {
+    let mut found = Vec::new();
+    for &color in [Color::White, Color::Black].iter() {
+        let pieces: Vec<(&Square, &Piece)> = board
+            .pieces
+            .iter()
+            .filter(|(_, p)| p.color == color)
+            .collect();
+        let count = |title: Title| pieces.iter().filter(|(_, p)| p.title == title).count();
+        if count(Title::King) != 1 {
+            found.push(Illegality::KingCount(color));
+        }
+        if pieces.len() > 16 {
+            found.push(Illegality::TooManyPieces(color));
+        }
+        let pawns = count(Title::Pawn);
+        if pawns > 8 {
+            found.push(Illegality::TooManyPawns(color));
+            continue;
+        }
+        if pawns + promoted_pieces(board, color) > 8 {
+            found.push(Illegality::TooManyPromotions(color));
+        }
+        let files: Vec<i32> = pieces
+            .iter()
+            .filter(|(_, p)| p.title == Title::Pawn)
+            .map(|(s, _)| s.file())
+            .collect();
+        let lost = 16
+            - board
+                .pieces
+                .values()
+                .filter(|p| p.color != color)
+                .count()
+                .min(16);
+        if pawn_captures_needed(files) > lost {
+            found.push(Illegality::TooFewCaptures(color));
+        }
+    }
+    if board
+        .pieces
+        .iter()
+        .any(|(s, p)| p.title == Title::Pawn && (s.rank() == 0 || s.rank() == 7))
+    {
+        found.push(Illegality::PawnOnBackRank);
+    }
+    //The rest needs one king of each color
+    if found.iter().any(|i| matches!(i, Illegality::KingCount(_))) {
+        return found;
+    }
+    let waiting = board.to_act.inverse();
+    if board
+        .check_if_threatend(board.king_pos[waiting.index()], waiting)
+        .0
+    {
+        found.push(Illegality::OpponentInCheck);
+        return found;
+    }
+    if board.placement_fen() != Board::new_standard().placement_fen() && unmoves(board).is_empty() {
+        let checkers = board.attackers(board.king_pos[board.to_act.index()]);
+        let in_check = checkers.iter().any(|s| board.pieces[s].color == waiting);
+        found.push(if in_check {
+            Illegality::ImpossibleCheck
+        } else {
+            Illegality::NoPredecessor
+        });
+    }
+    found
+}
+
+#[cfg(test)]
+mod test_retro {
+    use super::*;
+    use crate::movedata::MoveType;
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+    fn last_moves(predecessors: &[Predecessor]) -> Vec<String> {
+        let mut moves: Vec<String> = predecessors.iter().map(|p| p.last_move.to_uci()).collect();
+        moves.sort();
+        moves.dedup();
+        moves
+    }
+    #[test]
+    fn simple_unmoves() {
+        //The en passant square leaves only the double step
+        let after_e4 = board("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let predecessors = unmoves(&after_e4);
+        assert_eq!(predecessors.len(), 1);
+        assert_eq!(
+            predecessors[0].board.placement_fen(),
+            Board::new_standard().placement_fen()
+        );
+        //Without it the pawn can have come from e3, or the knights moved
+        let after_e4 = board("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        let moves = last_moves(&unmoves(&after_e4));
+        for m in ["b1c3", "g1f3", "e1e2"].iter() {
+            assert!(!moves.contains(&m.to_string()), "{}", m);
+        }
+        for m in ["a3b1", "c3b1", "e2e4", "e3e4", "f3g1", "h3g1"].iter() {
+            assert!(moves.contains(&m.to_string()), "{}", m);
+        }
+        //A halfmove clock rules out the pawn move
+        let quiet = board("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 3 1");
+        assert!(!last_moves(&unmoves(&quiet))
+            .iter()
+            .any(|m| m.ends_with("e4")));
+        //The side not to move in check has no predecessor
+        assert!(unmoves(&board("4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1")).is_empty());
+    }
+    #[test]
+    fn special_unmoves() {
+        //Queen on the last rank: a promotion with or without a capture
+        let promoted = unmoves(&board("3Q4/8/8/8/8/8/8/k3K3 b - - 0 1"));
+        let moves = last_moves(&promoted);
+        assert!(moves.contains(&"d7d8q".to_string()));
+        assert!(moves.contains(&"c7d8q".to_string()));
+        assert!(promoted
+            .iter()
+            .any(|p| p.last_move.to_uci() == "e7d8q" && p.uncaptured == Some(Title::Rook)));
+        assert!(!promoted
+            .iter()
+            .any(|p| p.last_move.to_uci() == "e7d8q" && p.uncaptured == Some(Title::Pawn)));
+        //A pawn on the sixth can have taken en passant
+        let passed = unmoves(&board("4k3/8/3P4/8/8/8/8/4K3 b - - 0 1"));
+        let en_passant = passed
+            .iter()
+            .find(|p| {
+                p.last_move.to_uci() == "e5d6"
+                    && matches!(p.last_move.move_type, MoveType::EnPassant(_))
+            })
+            .unwrap();
+        assert_eq!(
+            en_passant.board.placement_fen(),
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6"
+        );
+        //King and rook side by side can have castled
+        let castled = unmoves(&board("4k3/8/8/8/8/8/8/5RK1 b - - 0 1"));
+        let castling = castled
+            .iter()
+            .find(|p| matches!(p.last_move.move_type, MoveType::Casteling(_, _)))
+            .unwrap();
+        assert!(castling.board.can_castle(Color::White, true));
+        assert_eq!(castling.board.fullmove_number, 1);
+    }
+    #[test]
+    fn legality() {
+        assert!(illegalities(&Board::new_standard()).is_empty());
+        assert!(illegalities(&board(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+        ))
+        .is_empty());
+        //Two pawns on the e-file and no black piece taken
+        assert_eq!(
+            illegalities(&board(
+                "rnbqkbnr/pppppppp/8/8/8/4P3/PPP1PPPP/RNBQKBNR b KQkq - 0 1"
+            )),
+            vec![Illegality::TooFewCaptures(Color::White)]
+        );
+        let found = illegalities(&board("4k3/8/8/8/8/8/PPPPPPPP/PPPPK3 w - - 0 1"));
+        assert!(found.contains(&Illegality::TooManyPawns(Color::White)));
+        assert!(found.contains(&Illegality::PawnOnBackRank));
+        assert_eq!(
+            illegalities(&board("4k3/8/8/8/8/8/8/8 w - - 0 1")),
+            vec![Illegality::KingCount(Color::White)]
+        );
+        //Three queens and all eight pawns
+        assert_eq!(
+            illegalities(&board("4k3/8/8/8/8/8/PPPPPPPP/QQQ1K3 w - - 0 1")),
+            vec![Illegality::TooManyPromotions(Color::White)]
+        );
+        assert_eq!(
+            illegalities(&board("4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1")),
+            vec![Illegality::OpponentInCheck]
+        );
+        //Double check by two rooks that no single move can have given
+        assert_eq!(
+            illegalities(&board("R3k3/8/8/8/8/8/8/4RK2 b - - 0 1")),
+            vec![Illegality::ImpossibleCheck]
+        );
+        assert_eq!(
+            Illegality::ImpossibleCheck.to_string(),
+            "no move can have given the check"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movedata::{MoveData, MoveType};
+use crate::movegen::generate_action_space;
+use crate::piece::Piece;
+use crate::square::FILE_SIGN;
+use crate::title::Title;
+
+impl MoveData {
+    //Standard algebraic notation, e.g. Nbd7, exd5, e8=Q+ or O-O-O#
+    pub fn to_san(&self, board: &Board) -> String {
+        let mut san = self.san_without_check(board, &generate_action_space(board.clone()));
+        let mut after = board.clone();
+        after.make_move(*self);
+        if after.in_check {
+            if generate_action_space(after).is_empty() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san
+    }
+
+    fn san_without_check(&self, board: &Board, legal: &[MoveData]) -> String {
+        if let MoveType::Casteling(_, _) = self.move_type {
+            return if self.to.file() > self.from.file() {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            };
+        }
+        let title = match board.pieces.get(&self.from) {
+            Some(p) => p.title,
+            None => return self.to_uci(),
+        };
+        let capture = board.captured_piece(self).is_some();
+        let mut san = String::new();
+        if title == Title::Pawn {
+            if capture {
+                san.push(FILE_SIGN[self.from.file() as usize]);
+                san.push('x');
+            }
+            san.push_str(&self.to.to_string());
+            if let MoveType::Promotion(promote_to) = self.move_type {
+                san.push('=');
+                san.push(title_sign(promote_to.unwrap_or(Title::Queen)));
+            }
+            return san;
+        }
+
+        san.push(title_sign(title));
+        //Other pieces of the same kind that can reach the square
+        let rivals: Vec<&MoveData> = legal
+            .iter()
+            .filter(|m| {
+                m.to == self.to
+                    && m.from != self.from
+                    && board.pieces.get(&m.from).map(|p| p.title) == Some(title)
+            })
+            .collect();
+        if !rivals.is_empty() {
+            if rivals.iter().all(|m| m.from.file() != self.from.file()) {
+                san.push(FILE_SIGN[self.from.file() as usize]);
+            } else if rivals.iter().all(|m| m.from.rank() != self.from.rank()) {
+                san.push_str(&(self.from.rank() + 1).to_string());
+            } else {
+                san.push_str(&self.from.to_string());
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&self.to.to_string());
+        san
+    }
+
+    //The legal move matching the SAN, check marks and annotations like ! and ? are optional
+    pub fn from_san(board: &Board, note: &str) -> Option<Self> {
+        let wanted = normalize(note);
+        if wanted.is_empty() {
+            return None;
+        }
+        let legal = generate_action_space(board.clone());
+        let exact = legal
+            .iter()
+            .find(|m| normalize(&m.san_without_check(board, &legal)) == wanted);
+        if let Some(m) = exact {
+            return Some(*m);
+        }
+        //Some writers over-disambiguate, e.g. Ng1f3, so also accept the full from square
+        legal.iter().copied().find(|m| {
+            let piece = board.pieces[&m.from];
+            piece.title != Title::Pawn
+                && !matches!(m.move_type, MoveType::Casteling(_, _))
+                && wanted
+                    == format!(
+                        "{}{}{}{}",
+                        title_sign(piece.title),
+                        m.from,
+                        if board.captured_piece(m).is_some() {
+                            "x"
+                        } else {
+                            ""
+                        },
+                        m.to
+                    )
+        })
+    }
+}
+
+fn title_sign(title: Title) -> char {
+    Piece::new(Color::White, title).to_char()
+}
+
+//Drops check marks, annotations and the promotion '=', and accepts zeros for castling
+fn normalize(note: &str) -> String {
+    note.trim()
+        .replace("0-0-0", "O-O-O")
+        .replace("0-0", "O-O")
+        .chars()
+        .filter(|c| !matches!(c, '+' | '#' | '!' | '?' | '='))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_san {
+    use super::*;
+    fn play(board: &mut Board, notes: &[&str]) -> Vec<String> {
+        let mut sans = Vec::new();
+        for note in notes {
+            let m = MoveData::from_san(board, note).unwrap();
+            sans.push(m.to_san(board));
+            board.make_move(m);
+        }
+        sans
+    }
+    #[test]
+    fn opening_moves() {
+        let mut board = Board::new_standard();
+        let sans = play(
+            &mut board,
+            &["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"],
+        );
+        assert_eq!(
+            sans,
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"]
+        );
+    }
+    #[test]
+    fn disambiguation() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        let m = MoveData::from_san(&board, "Rad1").unwrap();
+        assert_eq!(m.from.to_string(), "a1");
+        assert_eq!(m.to_san(&board), "Rad1");
+        board = Board::from_fen("4k3/8/8/N7/8/8/8/N3K3 w - - 0 1").unwrap();
+        let m = MoveData::from_san(&board, "N1b3").unwrap();
+        assert_eq!(m.from.to_string(), "a1");
+        assert_eq!(MoveData::from_san(&board, "Nb3"), None);
+        assert_eq!(MoveData::from_san(&board, "Na1b3"), Some(m));
+        board = Board::from_fen("8/7k/8/8/Q2Q4/8/8/Q3K3 w - - 0 1").unwrap();
+        let m = MoveData::from_san(&board, "Qa4d1").unwrap();
+        assert_eq!(m.to_san(&board), "Qa4d1");
+    }
+    #[test]
+    fn promotion_check_and_mate() {
+        let board = Board::from_fen("7k/P7/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let m = MoveData::from_san(&board, "a8=Q#").unwrap();
+        assert_eq!(m.to_san(&board), "a8=Q#");
+        let under = MoveData::from_san(&board, "a8R").unwrap();
+        assert_eq!(under.to_san(&board), "a8=R#");
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            MoveData::from_san(&board, "Ra8").unwrap().to_san(&board),
+            "Ra8+"
+        );
+    }
+    #[test]
+    fn castling_and_en_passant() {
+        let board = Board::from_fen("r3k3/8/8/3pP3/8/8/8/4K3 w q d6 0 1").unwrap();
+        let m = MoveData::from_san(&board, "exd6").unwrap();
+        assert_eq!(
+            m.move_type,
+            MoveType::EnPassant(m.to.offset(-1, 0).unwrap())
+        );
+        assert_eq!(m.to_san(&board), "exd6");
+        let mut board = board;
+        board.to_act = Color::Black;
+        board.en_passant = None;
+        board.update_status();
+        assert_eq!(
+            MoveData::from_san(&board, "0-0-0").unwrap().to_san(&board),
+            "O-O-O"
+        );
+        assert_eq!(MoveData::from_san(&board, "O-O"), None);
+    }
+    #[test]
+    fn rejects_nonsense() {
+        let board = Board::new_standard();
+        assert_eq!(MoveData::from_san(&board, "e5"), None);
+        assert_eq!(MoveData::from_san(&board, "Ke2"), None);
+        assert_eq!(MoveData::from_san(&board, ""), None);
+        assert_eq!(
+            MoveData::from_san(&board, "Nf3!?").unwrap().to_uci(),
+            "g1f3"
+        );
+    }
+}
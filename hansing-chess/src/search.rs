@@ -0,0 +1,525 @@
+use crate::board::Board;
+use crate::eval::evaluate;
+use crate::movedata::{MoveData, MoveType};
+use crate::movegen::generate_action_space;
+use crate::movepick::{Heuristics, MovePicker};
+use crate::see::see;
+use crate::tt::{Bound, TranspositionTable, TtEntry};
+use crate::zobrist::hash_board;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub const MATE: i32 = 30000;
+pub const MAX_DEPTH: i32 = 64;
+//Scores beyond this are mates, stored relative to the node in the transposition table
+const MATE_BOUND: i32 = MATE - 1000;
+//Guards against endless check/evasion sequences in quiescence
+const MAX_QUIESCENCE_PLY: i32 = 16;
+
+//Searches captures and promotions until the position is quiet, captures losing material by SEE are pruned
+pub fn quiescence(board: &Board, alpha: i32, beta: i32) -> i32 {
+    qsearch(board, alpha, beta, 0)
+}
+
+fn qsearch(board: &Board, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+    let moves = generate_action_space(board.clone());
+    if moves.is_empty() {
+        return if board.in_check { -MATE + ply } else { 0 };
+    }
+    if ply >= MAX_QUIESCENCE_PLY {
+        return evaluate(board);
+    }
+
+    if board.in_check {
+        //Every evasion has to be considered, standing pat is not an option
+        for m in moves {
+            let mut next = board.clone();
+            next.make_move(m);
+            let score = -qsearch(&next, -beta, -alpha, ply + 1);
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        return alpha;
+    }
+
+    let stand_pat = evaluate(board);
+    if stand_pat >= beta {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+
+    let mut noisy: Vec<(i32, MoveData)> = moves
+        .into_iter()
+        .filter(|m| is_noisy(board, m))
+        .map(|m| (see(board, &m), m))
+        .filter(|(exchange, _)| *exchange >= 0)
+        .collect();
+    noisy.sort_by_key(|(exchange, _)| -exchange);
+
+    for (_, m) in noisy {
+        let mut next = board.clone();
+        next.make_move(m);
+        let score = -qsearch(&next, -beta, -alpha, ply + 1);
+        if score >= beta {
+            return score;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchLimits {
+    pub depth: i32,
+    pub nodes: Option<u64>,
+    pub multi_pv: usize,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: i32) -> Self {
+        Self {
+            depth,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            depth: MAX_DEPTH,
+            nodes: None,
+            multi_pv: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<MoveData>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<MoveData>,
+    pub score: i32,
+    pub depth: i32,
+    pub pv: Vec<MoveData>,
+    pub nodes: u64,
+    pub lines: Vec<PvLine>, //All MultiPV lines, the first is the same as score and pv
+}
+
+//Full moves until mate for mate scores, negative when being mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score > MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score < -MATE_BOUND {
+        Some(-(MATE + score + 1) / 2)
+    } else {
+        None
+    }
+}
+
+//Iterative deepening alpha-beta search, keeping its table and move ordering between searches
+pub struct Searcher {
+    pub tt: Arc<TranspositionTable>,
+    pub heuristics: Heuristics,
+    stop: Arc<AtomicBool>,
+    nodes: u64,
+    root_best: Option<MoveData>,
+    root_excluded: Vec<MoveData>, //Moves already reported as earlier MultiPV lines
+    node_limit: Option<u64>,
+    abortable: bool,
+}
+
+impl Searcher {
+    pub fn new(hash_mb: usize) -> Self {
+        Self::with_table(
+            Arc::new(TranspositionTable::new(hash_mb)),
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    //Searcher using a table and stop flag that may be shared with other threads
+    pub fn with_table(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            tt,
+            heuristics: Heuristics::new(),
+            stop,
+            nodes: 0,
+            root_best: None,
+            root_excluded: Vec::new(),
+            node_limit: None,
+            abortable: false,
+        }
+    }
+
+    //Setting the flag aborts the search, the first iteration is always completed so there is a move
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    fn stopped(&self) -> bool {
+        self.abortable
+            && (self.stop.load(Ordering::Relaxed)
+                || matches!(self.node_limit, Some(limit) if self.nodes >= limit))
+    }
+
+    pub fn search(&mut self, board: &Board, depth: i32) -> SearchResult {
+        self.search_with(board, &SearchLimits::depth(depth), &mut |_| ())
+    }
+
+    //Reports the result of every completed iteration before going deeper
+    pub fn search_with(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        report: &mut dyn FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.nodes = 0;
+        self.node_limit = limits.nodes;
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            pv: Vec::new(),
+            nodes: 0,
+            lines: Vec::new(),
+        };
+        for d in 1..=limits.depth.max(1) {
+            self.abortable = d > 1;
+            self.root_excluded.clear();
+            let mut lines = Vec::new();
+            let mut root_score = 0;
+            for _ in 0..limits.multi_pv.max(1) {
+                self.root_best = None;
+                let score = self.alpha_beta(board, d, -MATE, MATE, 0, None);
+                if self.stopped() {
+                    break;
+                }
+                if lines.is_empty() {
+                    root_score = score;
+                }
+                match self.root_best {
+                    Some(best) => {
+                        lines.push(PvLine {
+                            score,
+                            pv: self.principal_variation(board, Some(best), d),
+                        });
+                        self.root_excluded.push(best);
+                    }
+                    None => break,
+                }
+            }
+            if self.stopped() {
+                result.nodes = self.nodes;
+                break;
+            }
+            result = SearchResult {
+                best_move: lines.first().map(|l| l.pv[0]),
+                score: root_score,
+                depth: d,
+                pv: lines.first().map(|l| l.pv.clone()).unwrap_or_default(),
+                nodes: self.nodes,
+                lines,
+            };
+            report(&result);
+        }
+        self.root_excluded.clear();
+        result
+    }
+
+    fn alpha_beta(
+        &mut self,
+        board: &Board,
+        depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: i32,
+        previous: Option<MoveData>,
+    ) -> i32 {
+        if depth <= 0 {
+            return qsearch(board, alpha, beta, ply);
+        }
+        self.nodes += 1;
+        if self.stopped() {
+            return 0;
+        }
+
+        let key = hash_board(board);
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(key) {
+            hash_move = entry.best_move;
+            if ply > 0 && entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => (),
+                }
+            }
+        }
+
+        let moves = generate_action_space(board.clone());
+        if moves.is_empty() {
+            return if board.in_check { -MATE + ply } else { 0 };
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = -MATE;
+        let mut best_move = None;
+        let picker = MovePicker::new(
+            board,
+            moves,
+            hash_move,
+            &self.heuristics,
+            ply as usize,
+            previous,
+        );
+        for m in picker {
+            if ply == 0 && self.root_excluded.contains(&m) {
+                continue;
+            }
+            let mut next = board.clone();
+            next.make_move(m);
+            let score = -self.alpha_beta(&next, depth - 1, -beta, -alpha, ply + 1, Some(m));
+            if self.stopped() {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if !is_noisy(board, &m) {
+                    self.heuristics.add_killer(ply as usize, m);
+                    self.heuristics.add_history(board.to_act, &m, depth);
+                    self.heuristics.set_countermove(previous, m);
+                }
+                break;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        //Root results with excluded moves are not the true value of the position
+        if ply > 0 || self.root_excluded.is_empty() {
+            self.tt.store(TtEntry {
+                key,
+                depth,
+                score: score_to_tt(best_score, ply),
+                bound,
+                best_move,
+            });
+        }
+        if ply == 0 {
+            self.root_best = best_move;
+        }
+        best_score
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    //Follows best moves stored in the table, stopping at anything no longer legal
+    fn principal_variation(
+        &self,
+        board: &Board,
+        first: Option<MoveData>,
+        depth: i32,
+    ) -> Vec<MoveData> {
+        let mut pv = Vec::new();
+        let mut board = board.clone();
+        let mut next = first;
+        let mut seen = Vec::new();
+        while let Some(m) = next {
+            if pv.len() as i32 >= depth || !generate_action_space(board.clone()).contains(&m) {
+                break;
+            }
+            seen.push(hash_board(&board));
+            board.make_move(m);
+            pv.push(m);
+            let key = hash_board(&board);
+            if seen.contains(&key) {
+                break;
+            }
+            next = self.tt.probe(key).and_then(|e| e.best_move);
+        }
+        pv
+    }
+}
+
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score > MATE_BOUND {
+        score + ply
+    } else if score < -MATE_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score > MATE_BOUND {
+        score - ply
+    } else if score < -MATE_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
+
+//Captures and promotions
+pub fn is_noisy(board: &Board, move_data: &MoveData) -> bool {
+    match move_data.move_type {
+        MoveType::Promotion(_) => true,
+        _ => board.captured_piece(move_data).is_some(),
+    }
+}
+
+#[cfg(test)]
+mod test_search {
+    use super::*;
+    use crate::color::Color;
+    use crate::testutil::{place, sq};
+    use crate::title::Title;
+    #[test]
+    fn standard_is_quiet() {
+        let board = Board::new_standard();
+        assert_eq!(quiescence(&board, -MATE, MATE), 0);
+    }
+    #[test]
+    fn wins_hanging_piece() {
+        let mut board = Board::new_empty();
+        place(&mut board, "a1", Color::White, Title::King);
+        place(&mut board, "h8", Color::Black, Title::King);
+        place(&mut board, "d1", Color::White, Title::Rook);
+        place(&mut board, "d5", Color::Black, Title::Knight);
+        board.update_status();
+        assert_eq!(quiescence(&board, -MATE, MATE), Title::Rook.value());
+    }
+    #[test]
+    fn avoids_defended_piece() {
+        let mut board = Board::new_empty();
+        place(&mut board, "a1", Color::White, Title::King);
+        place(&mut board, "h8", Color::Black, Title::King);
+        place(&mut board, "d1", Color::White, Title::Queen);
+        place(&mut board, "d5", Color::Black, Title::Pawn);
+        place(&mut board, "e6", Color::Black, Title::Pawn);
+        board.update_status();
+        //Taking the pawn loses the queen, so white stands pat
+        assert_eq!(
+            quiescence(&board, -MATE, MATE),
+            Title::Queen.value() - 2 * Title::Pawn.value()
+        );
+    }
+    #[test]
+    fn finds_mate_in_one() {
+        let mut board = Board::new_empty();
+        place(&mut board, "g1", Color::White, Title::King);
+        place(&mut board, "f2", Color::White, Title::Pawn);
+        place(&mut board, "g2", Color::White, Title::Pawn);
+        place(&mut board, "h2", Color::White, Title::Pawn);
+        place(&mut board, "g8", Color::Black, Title::King);
+        place(&mut board, "a8", Color::Black, Title::Rook);
+        board.to_act = Color::Black;
+        board.update_status();
+        let result = Searcher::new(1).search(&board, 2);
+        assert_eq!(
+            result.best_move,
+            Some(MoveData::new_standard(sq("a8"), sq("a1")))
+        );
+        assert_eq!(result.score, MATE - 1);
+        assert_eq!(result.pv[0], result.best_move.unwrap());
+        //Mate found by quiescence at the horizon is still one ply away
+        assert_eq!(Searcher::new(1).search(&board, 1).score, MATE - 1);
+    }
+    #[test]
+    fn avoids_losing_queen() {
+        let mut board = Board::new_empty();
+        place(&mut board, "a1", Color::White, Title::King);
+        place(&mut board, "h8", Color::Black, Title::King);
+        place(&mut board, "d4", Color::White, Title::Queen);
+        place(&mut board, "c6", Color::Black, Title::Knight);
+        place(&mut board, "b7", Color::Black, Title::Pawn);
+        board.update_status();
+        let mut searcher = Searcher::new(1);
+        let result = searcher.search(&board, 2);
+        let m = result.best_move.unwrap();
+        //Queen is attacked by the knight and must not take the protected one
+        assert_eq!(m.from, sq("d4"));
+        assert_ne!(m.to, sq("c6"));
+        assert!(result.score > 0);
+        assert_eq!(result.depth, 2);
+        assert!(result.nodes > 0);
+    }
+    #[test]
+    fn multi_pv() {
+        let board = Board::new_standard();
+        let mut searcher = Searcher::new(1);
+        let limits = SearchLimits {
+            depth: 2,
+            multi_pv: 3,
+            ..SearchLimits::default()
+        };
+        let mut reported = Vec::new();
+        let result = searcher.search_with(&board, &limits, &mut |r| reported.push(r.depth));
+        assert_eq!(reported, vec![1, 2]);
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].pv, result.pv);
+        assert!(result.lines[0].score >= result.lines[1].score);
+        assert!(result.lines[1].score >= result.lines[2].score);
+        assert_ne!(result.lines[0].pv[0], result.lines[1].pv[0]);
+        assert_ne!(result.lines[1].pv[0], result.lines[2].pv[0]);
+    }
+    #[test]
+    fn node_limit() {
+        let board = Board::new_standard();
+        let mut searcher = Searcher::new(1);
+        let limits = SearchLimits {
+            nodes: Some(50),
+            ..SearchLimits::default()
+        };
+        let result = searcher.search_with(&board, &limits, &mut |_| ());
+        assert!(result.best_move.is_some());
+        assert!(result.depth < MAX_DEPTH);
+    }
+    #[test]
+    fn mate_distance() {
+        assert_eq!(mate_in(MATE - 1), Some(1));
+        assert_eq!(mate_in(MATE - 3), Some(2));
+        assert_eq!(mate_in(-MATE + 2), Some(-1));
+        assert_eq!(mate_in(150), None);
+    }
+    #[test]
+    fn stop_keeps_first_iteration() {
+        let board = Board::new_standard();
+        let mut searcher = Searcher::new(1);
+        searcher.stop_flag().store(true, Ordering::Relaxed);
+        let result = searcher.search(&board, 5);
+        assert_eq!(result.depth, 1);
+        assert!(result.best_move.is_some());
+    }
+    #[test]
+    fn mated_in_check() {
+        let mut board = Board::new_empty();
+        place(&mut board, "h1", Color::White, Title::King);
+        place(&mut board, "g2", Color::Black, Title::Queen);
+        place(&mut board, "e3", Color::Black, Title::Knight);
+        place(&mut board, "a8", Color::Black, Title::King);
+        board.update_status();
+        assert!(board.in_check);
+        assert_eq!(quiescence(&board, -MATE, MATE), -MATE);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movedata::{MoveData, MoveType};
+use crate::square::Square;
+use crate::title::Title;
+
+//Static exchange evaluation: the material balance for the moving side if both sides keep
+//recapturing on the target square with their least valuable attacker. Pins are ignored.
+pub fn see(board: &Board, move_data: &MoveData) -> i32 {
+    let moving = match board.pieces.get(&move_data.from) {
+        Some(p) => *p,
+        None => return 0,
+    };
+    let mut removed = vec![move_data.from];
+    let mut gain = vec![0];
+    let mut on_square = moving.title.value();
+    match move_data.move_type {
+        MoveType::Casteling(_, _) => return 0,
+        MoveType::EnPassant(capture) => {
+            removed.push(capture);
+            gain[0] = Title::Pawn.value();
+        }
+        MoveType::Promotion(promote_to) => {
+            let promotion = promote_to.unwrap_or(Title::Queen).value();
+            if let Some(captured) = board.pieces.get(&move_data.to) {
+                gain[0] = captured.title.value();
+            }
+            gain[0] += promotion - Title::Pawn.value();
+            on_square = promotion;
+        }
+        _ => {
+            if let Some(captured) = board.pieces.get(&move_data.to) {
+                gain[0] = captured.title.value();
+            }
+        }
+    }
+
+    let mut side = moving.color.inverse();
+    while let Some(attacker) = least_valuable_attacker(board, move_data.to, side, &removed) {
+        let d = gain.len();
+        gain.push(on_square - gain[d - 1]);
+        //Neither side can gain by continuing
+        if (-gain[d - 1]).max(gain[d]) < 0 {
+            break;
+        }
+        removed.push(attacker);
+        on_square = board.pieces[&attacker].title.value();
+        side = side.inverse();
+    }
+    while gain.len() > 1 {
+        let last = gain.pop().unwrap();
+        let d = gain.len() - 1;
+        gain[d] = -(-gain[d]).max(last);
+    }
+    gain[0]
+}
+
+fn least_valuable_attacker(
+    board: &Board,
+    to: Square,
+    side: Color,
+    removed: &[Square],
+) -> Option<Square> {
+    board
+        .attackers_through(to, removed)
+        .into_iter()
+        .filter(|s| s != &to && board.pieces[s].color == side)
+        .min_by_key(|s| board.pieces[s].title.value())
+}
+
+#[cfg(test)]
+mod test_see {
+    use super::*;
+    use crate::testutil::{place, sq};
+    #[test]
+    fn undefended_capture() {
+        let mut board = Board::new_empty();
+        place(&mut board, "e1", Color::White, Title::Rook);
+        place(&mut board, "e5", Color::Black, Title::Pawn);
+        let m = MoveData::new_standard(sq("e1"), sq("e5"));
+        assert_eq!(see(&board, &m), Title::Pawn.value());
+    }
+    #[test]
+    fn defended_capture() {
+        let mut board = Board::new_empty();
+        place(&mut board, "e1", Color::White, Title::Rook);
+        place(&mut board, "e5", Color::Black, Title::Pawn);
+        place(&mut board, "d6", Color::Black, Title::Pawn);
+        let m = MoveData::new_standard(sq("e1"), sq("e5"));
+        assert_eq!(see(&board, &m), Title::Pawn.value() - Title::Rook.value());
+    }
+    #[test]
+    fn xray_recapture() {
+        let mut board = Board::new_empty();
+        place(&mut board, "e1", Color::White, Title::Rook);
+        place(&mut board, "e2", Color::White, Title::Rook);
+        place(&mut board, "e5", Color::Black, Title::Knight);
+        place(&mut board, "e8", Color::Black, Title::Rook);
+        let m = MoveData::new_standard(sq("e2"), sq("e5"));
+        //RxN RxR RxR, white ends a knight up
+        assert_eq!(see(&board, &m), Title::Knight.value());
+        board.pieces.remove(&sq("e1"));
+        assert_eq!(see(&board, &m), Title::Knight.value() - Title::Rook.value());
+    }
+    #[test]
+    fn xray_behind_queen() {
+        let mut board = Board::new_empty();
+        place(&mut board, "b2", Color::White, Title::Bishop);
+        place(&mut board, "c3", Color::White, Title::Queen);
+        place(&mut board, "e5", Color::Black, Title::Pawn);
+        place(&mut board, "f6", Color::Black, Title::Bishop);
+        let m = MoveData::new_standard(sq("c3"), sq("e5"));
+        //Attack through d4 is open; QxP BxQ BxB
+        assert_eq!(
+            see(&board, &m),
+            Title::Pawn.value() - Title::Queen.value() + Title::Bishop.value()
+        );
+    }
+    #[test]
+    fn quiet_move_to_attacked_square() {
+        let mut board = Board::new_empty();
+        place(&mut board, "d1", Color::White, Title::Queen);
+        place(&mut board, "e6", Color::Black, Title::Pawn);
+        let m = MoveData::new_standard(sq("d1"), sq("d5"));
+        assert_eq!(see(&board, &m), -Title::Queen.value());
+        let safe = MoveData::new_standard(sq("d1"), sq("d4"));
+        assert_eq!(see(&board, &safe), 0);
+    }
+    #[test]
+    fn en_passant() {
+        let mut board = Board::new_empty();
+        place(&mut board, "e5", Color::White, Title::Pawn);
+        place(&mut board, "d5", Color::Black, Title::Pawn);
+        let m = MoveData::new_en_passant(sq("e5"), sq("d6"), sq("d5"));
+        assert_eq!(see(&board, &m), Title::Pawn.value());
+    }
+}
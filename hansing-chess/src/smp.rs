@@ -0,0 +1,107 @@
+use crate::board::Board;
+use crate::search::{SearchLimits, SearchResult, Searcher, MAX_DEPTH};
+use crate::tt::TranspositionTable;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+//Lazy SMP: every thread searches the same position with its own board and heuristics, sharing
+//only the transposition table. Helpers alternate between the target depth and one ply deeper,
+//and are stopped as soon as the main thread finishes or the stop flag is set.
+pub fn search_parallel(
+    board: &Board,
+    limits: &SearchLimits,
+    threads: usize,
+    tt: &Arc<TranspositionTable>,
+    stop: &Arc<AtomicBool>,
+    report: &mut dyn FnMut(&SearchResult),
+) -> SearchResult {
+    let helper_stop = Arc::new(AtomicBool::new(false));
+    let helpers: Vec<thread::JoinHandle<SearchResult>> = (1..threads.max(1))
+        .map(|i| {
+            let board = board.clone();
+            let mut searcher = Searcher::with_table(tt.clone(), helper_stop.clone());
+            let helper_depth = (limits.depth + (i % 2) as i32).min(MAX_DEPTH);
+            thread::spawn(move || searcher.search(&board, helper_depth))
+        })
+        .collect();
+
+    let mut main = Searcher::with_table(tt.clone(), stop.clone());
+    let main_result = main.search_with(board, limits, report);
+    helper_stop.store(true, Ordering::Relaxed);
+
+    let mut results = vec![main_result];
+    for h in helpers {
+        if let Ok(r) = h.join() {
+            results.push(r);
+        }
+    }
+    combine(results)
+}
+
+//Deepest completed iteration wins, the main thread (first) is preferred on equal depth
+fn combine(results: Vec<SearchResult>) -> SearchResult {
+    let nodes = results.iter().map(|r| r.nodes).sum();
+    let mut best = results[0].clone();
+    for r in results.into_iter().skip(1) {
+        if r.depth > best.depth && r.best_move.is_some() {
+            best = r;
+        }
+    }
+    best.nodes = nodes;
+    best
+}
+
+#[cfg(test)]
+mod test_smp {
+    use super::*;
+    use crate::game::Game;
+    use crate::movedata::MoveData;
+    use crate::movegen::generate_action_space;
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    #[test]
+    fn thread_safety() {
+        assert_send::<Board>();
+        assert_send::<Game>();
+        assert_send::<Searcher>();
+        assert_sync::<TranspositionTable>();
+    }
+    #[test]
+    fn parallel_search_finds_legal_move() {
+        let board = Board::new_standard();
+        let tt = Arc::new(TranspositionTable::new(1));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut reports = 0;
+        let result = search_parallel(&board, &SearchLimits::depth(2), 3, &tt, &stop, &mut |_| {
+            reports += 1
+        });
+        assert_eq!(reports, 2);
+        let legal: Vec<MoveData> = generate_action_space(board.clone());
+        assert!(legal.contains(&result.best_move.unwrap()));
+        assert!(result.depth >= 2);
+        assert!(!result.pv.is_empty());
+    }
+    #[test]
+    fn combine_prefers_deeper() {
+        let m = generate_action_space(Board::new_standard())[0];
+        let shallow = SearchResult {
+            best_move: Some(m),
+            score: 10,
+            depth: 2,
+            pv: vec![m],
+            nodes: 5,
+            lines: Vec::new(),
+        };
+        let deep = SearchResult {
+            depth: 3,
+            score: 20,
+            nodes: 7,
+            ..shallow.clone()
+        };
+        let combined = combine(vec![shallow, deep]);
+        assert_eq!(combined.depth, 3);
+        assert_eq!(combined.score, 20);
+        assert_eq!(combined.nodes, 12);
+    }
+}
@@ -28,6 +28,27 @@ impl Square {
     pub fn file(&self) -> i32 {
         self.1
     }
+    //0 for a1 through 63 for h8
+    pub fn index(&self) -> usize {
+        (self.0 * 8 + self.1) as usize
+    }
+    pub fn from_index(index: usize) -> Option<Self> {
+        if index < 64 {
+            Self::new(index as i32 / 8, index as i32 % 8)
+        } else {
+            None
+        }
+    }
+    pub fn from_notation(note: &str) -> Option<Self> {
+        let mut chars = note.chars();
+        let file_sign = chars.next()?;
+        let file = FILE_SIGN.iter().position(|&c| c == file_sign)?;
+        let rank = chars.next()?.to_digit(10)? as i32 - 1;
+        if chars.next().is_some() {
+            return None;
+        }
+        Self::new(rank, file as i32)
+    }
 }
 
 pub const FILE_SIGN: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
@@ -38,6 +59,23 @@ impl fmt::Display for Square {
     }
 }
 
+//Written in algebraic notation, e.g. "e4"
+#[cfg(feature = "serde")]
+impl serde::Serialize for Square {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Square {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let note = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Self::from_notation(&note)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid square '{}'", note)))
+    }
+}
+
 #[cfg(test)]
 mod test_square {
     use super::*;
@@ -66,4 +104,23 @@ mod test_square {
         assert_eq!(s1, Some(Square::new(6, 6).unwrap()));
         assert_eq!(s2, None);
     }
+    #[test]
+    fn index() {
+        assert_eq!(Square::new(0, 0).unwrap().index(), 0);
+        assert_eq!(Square::new(7, 7).unwrap().index(), 63);
+        for i in 0..64 {
+            assert_eq!(Square::from_index(i).unwrap().index(), i);
+        }
+        assert_eq!(Square::from_index(64), None);
+    }
+    #[test]
+    fn from_notation() {
+        assert_eq!(Square::from_notation("e4"), Square::new(3, 4));
+        assert_eq!(Square::from_notation("a1"), Square::new(0, 0));
+        assert_eq!(Square::from_notation("h8"), Square::new(7, 7));
+        assert_eq!(Square::from_notation("i1"), None);
+        assert_eq!(Square::from_notation("a9"), None);
+        assert_eq!(Square::from_notation("e44"), None);
+        assert_eq!(Square::from_notation(""), None);
+    }
 }
@@ -0,0 +1,1093 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movedata::MoveData;
+use crate::movegen::generate_action_space;
+use crate::moverules::{
+    BISHOP_MOVES, KING_MOVES, KNIGHT_MOVES, PROMOTION_TITLES, QUEEN_MOVES, ROOK_MOVES,
+};
+use crate::retro::quiet_origins;
+use crate::square::Square;
+use crate::title::Title;
+use crate::zobrist::can_capture_en_passant;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+//Both kings included, five pieces already make tables of hundreds of millions of positions
+pub const MAX_PIECES: usize = 5;
+
+const MAGIC: &[u8; 4] = b"HTB1";
+const INVALID: u8 = 255; //Illegal positions and ones stored under a symmetric twin
+const MAX_PLIES: usize = 253; //Values are plies to mate plus one, so they fit below INVALID
+
+//Where the white king is moved by symmetry, a1-d1-d4 without pawns and files a-d with them
+const PAWNLESS_KING_SQUARES: [u8; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+const PAWN_KING_SQUARES: [u8; 32] = [
+    0, 1, 2, 3, 8, 9, 10, 11, 16, 17, 18, 19, 24, 25, 26, 27, 32, 33, 34, 35, 40, 41, 42, 43, 48,
+    49, 50, 51, 56, 57, 58, 59,
+];
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    BadMaterial(String),
+    PawnsOnBothSides(String), //En passant would be possible, which the tables do not hold
+    TooDeep(String),          //A mate too long to store
+    MissingTable(String),     //A table needed to play out captures and promotions
+    Io(io::Error),
+    Corrupt(String), //The file the error was found in
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TablebaseError::BadMaterial(m) => write!(f, "invalid material '{}'", m),
+            TablebaseError::PawnsOnBothSides(m) => {
+                write!(
+                    f,
+                    "{} has pawns on both sides, en passant is not supported",
+                    m
+                )
+            }
+            TablebaseError::TooDeep(m) => {
+                write!(f, "{} has mates longer than {} plies", m, MAX_PLIES)
+            }
+            TablebaseError::MissingTable(m) => write!(f, "missing table {}", m),
+            TablebaseError::Io(e) => write!(f, "{}", e),
+            TablebaseError::Corrupt(file) => write!(f, "corrupt table file {}", file),
+        }
+    }
+}
+
+impl std::error::Error for TablebaseError {}
+
+impl From<io::Error> for TablebaseError {
+    fn from(e: io::Error) -> Self {
+        TablebaseError::Io(e)
+    }
+}
+
+//Result with perfect play for the side to move, counted in plies to mate
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Outcome {
+    Win(u32),
+    Draw,
+    Loss(u32),
+}
+
+impl Outcome {
+    //Distance to mate in moves, negative when the side to move gets mated
+    pub fn dtm(&self) -> Option<i32> {
+        match *self {
+            Outcome::Win(plies) => Some((plies as i32 + 1) / 2),
+            Outcome::Draw => None,
+            Outcome::Loss(plies) => Some(-(plies as i32) / 2),
+        }
+    }
+    fn from_value(value: u8) -> Option<Self> {
+        match value {
+            INVALID => None,
+            0 => Some(Outcome::Draw),
+            v if v % 2 == 0 => Some(Outcome::Win(v as u32 - 1)),
+            v => Some(Outcome::Loss(v as u32 - 1)),
+        }
+    }
+    //Ordered from the point of view of the side to move, higher is better
+    fn rank(&self) -> i64 {
+        match *self {
+            Outcome::Win(plies) => 1000 - plies as i64,
+            Outcome::Draw => 0,
+            Outcome::Loss(plies) => -1000 + plies as i64,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.dtm() {
+            Some(n) if n > 0 => write!(f, "mate in {}", n),
+            Some(0) => write!(f, "checkmated"),
+            Some(n) => write!(f, "mated in {}", -n),
+            None => write!(f, "draw"),
+        }
+    }
+}
+
+fn sign(title: Title) -> char {
+    match title {
+        Title::Pawn => 'P',
+        Title::Knight => 'N',
+        Title::Bishop => 'B',
+        Title::Rook => 'R',
+        Title::Queen => 'Q',
+        Title::King => 'K',
+    }
+}
+
+//The pieces of a table in index order: white king, black king, then white and black pieces,
+//strongest first
+#[derive(PartialEq, Clone, Debug)]
+struct Material(Vec<(Color, Title)>);
+
+impl Material {
+    //Written like KRKP, White's pieces first
+    fn parse(name: &str) -> Result<Self, TablebaseError> {
+        let bad = || TablebaseError::BadMaterial(name.to_string());
+        let black_at = name.get(1..).and_then(|n| n.find('K')).ok_or_else(bad)? + 1;
+        if !name.starts_with('K') {
+            return Err(bad());
+        }
+        let mut pieces = Vec::new();
+        for (i, c) in name.chars().enumerate() {
+            let color = if i < black_at {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let title = match c {
+                'K' if i == 0 || i == black_at => Title::King,
+                'Q' => Title::Queen,
+                'R' => Title::Rook,
+                'B' => Title::Bishop,
+                'N' => Title::Knight,
+                'P' => Title::Pawn,
+                _ => return Err(bad()),
+            };
+            pieces.push((color, title));
+        }
+        if pieces.len() > MAX_PIECES {
+            return Err(bad());
+        }
+        let pawns = |color: Color| pieces.contains(&(color, Title::Pawn));
+        if pawns(Color::White) && pawns(Color::Black) {
+            return Err(TablebaseError::PawnsOnBothSides(name.to_string()));
+        }
+        Ok(Self::sorted(pieces))
+    }
+    fn sorted(mut pieces: Vec<(Color, Title)>) -> Self {
+        pieces.sort_by_key(|&(c, t)| (t != Title::King, c.index(), -t.value()));
+        Material(pieces)
+    }
+    fn name(&self) -> String {
+        let side = |color: Color| -> String {
+            self.0
+                .iter()
+                .filter(|(c, _)| *c == color)
+                .map(|&(_, t)| sign(t))
+                .collect()
+        };
+        format!("{}{}", side(Color::White), side(Color::Black))
+    }
+    fn has_pawns(&self) -> bool {
+        self.0.iter().any(|&(_, t)| t == Title::Pawn)
+    }
+    //Tables are stored with the stronger side as White
+    fn needs_flip(&self) -> bool {
+        let strength = |color: Color| -> (usize, Vec<i32>) {
+            let values: Vec<i32> = self
+                .0
+                .iter()
+                .filter(|(c, _)| *c == color)
+                .map(|&(_, t)| t.value())
+                .collect();
+            (values.len(), values)
+        };
+        strength(Color::Black) > strength(Color::White)
+    }
+    fn king_squares(&self) -> &'static [u8] {
+        if self.has_pawns() {
+            &PAWN_KING_SQUARES
+        } else {
+            &PAWNLESS_KING_SQUARES
+        }
+    }
+    fn size(&self) -> usize {
+        2 * self.king_squares().len() * 64usize.pow(self.0.len() as u32 - 1)
+    }
+    //Tables reached by a capture, a promotion or both
+    fn children(&self) -> Vec<Material> {
+        let mut children = Vec::new();
+        let mut add = |pieces: Vec<(Color, Title)>| {
+            let mut child = Material::sorted(pieces);
+            if child.needs_flip() {
+                child = Material::sorted(child.0.iter().map(|&(c, t)| (c.inverse(), t)).collect());
+            }
+            if !children.contains(&child) {
+                children.push(child);
+            }
+        };
+        for (i, &(color, title)) in self.0.iter().enumerate() {
+            if title == Title::King {
+                continue;
+            }
+            let mut captured = self.0.clone();
+            captured.remove(i);
+            add(captured);
+            if title != Title::Pawn {
+                continue;
+            }
+            for &promote_to in PROMOTION_TITLES.iter() {
+                let mut promoted = self.0.clone();
+                promoted[i] = (color, promote_to);
+                add(promoted.clone());
+                for (j, &(c, t)) in self.0.iter().enumerate() {
+                    if c != color && t != Title::King {
+                        let mut both = promoted.clone();
+                        both.remove(j);
+                        add(both);
+                    }
+                }
+            }
+        }
+        children
+    }
+}
+
+//A position of a table, squares 0 for a1 to 63 for h8 in material order
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct Pos {
+    squares: [u8; MAX_PIECES],
+    white_to_move: bool,
+}
+
+fn rank_file(s: u8) -> (i32, i32) {
+    ((s / 8) as i32, (s % 8) as i32)
+}
+
+fn offset(s: u8, (rank_step, file_step): (i32, i32)) -> Option<u8> {
+    let (rank, file) = rank_file(s);
+    let (rank, file) = (rank + rank_step, file + file_step);
+    if (0..8).contains(&rank) && (0..8).contains(&file) {
+        Some((rank * 8 + file) as u8)
+    } else {
+        None
+    }
+}
+
+//Mirror the files, mirror the ranks, then swap ranks and files, by the bits of symmetry
+fn transform(s: u8, symmetry: u8) -> u8 {
+    let (mut rank, mut file) = rank_file(s);
+    if symmetry & 1 != 0 {
+        file = 7 - file;
+    }
+    if symmetry & 2 != 0 {
+        rank = 7 - rank;
+    }
+    if symmetry & 4 != 0 {
+        std::mem::swap(&mut rank, &mut file);
+    }
+    (rank * 8 + file) as u8
+}
+
+//Squares a non-pawn piece reaches from a square, stopping at the first occupied one
+fn piece_targets(title: Title, from: u8, occupied: u64, out: &mut Vec<u8>) {
+    let rules: &[(i32, i32, bool)] = match title {
+        Title::Knight => &KNIGHT_MOVES,
+        Title::Bishop => &BISHOP_MOVES,
+        Title::Rook => &ROOK_MOVES,
+        Title::Queen => &QUEEN_MOVES,
+        _ => &KING_MOVES,
+    };
+    for &(rank_offset, file_offset, slides) in rules.iter() {
+        let mut s = from;
+        while let Some(next) = offset(s, (rank_offset, file_offset)) {
+            out.push(next);
+            if !slides || occupied & 1 << next != 0 {
+                break;
+            }
+            s = next;
+        }
+    }
+}
+
+fn attacks(title: Title, color: Color, from: u8, target: u8, occupied: u64) -> bool {
+    let ((from_rank, from_file), (to_rank, to_file)) = (rank_file(from), rank_file(target));
+    let (rank_step, file_step) = (to_rank - from_rank, to_file - from_file);
+    let straight = rank_step == 0 || file_step == 0;
+    let diagonal = rank_step.abs() == file_step.abs();
+    let slides = match title {
+        Title::Pawn => {
+            let forward = if color == Color::White { 1 } else { -1 };
+            return rank_step == forward && file_step.abs() == 1;
+        }
+        Title::Knight => return rank_step.abs() * file_step.abs() == 2,
+        Title::King => return rank_step.abs().max(file_step.abs()) == 1,
+        Title::Bishop => diagonal,
+        Title::Rook => straight,
+        Title::Queen => straight || diagonal,
+    };
+    if !slides || from == target {
+        return false;
+    }
+    //Nothing may stand in between
+    let step = (rank_step.signum(), file_step.signum());
+    let mut s = offset(from, step).unwrap();
+    while s != target {
+        if occupied & 1 << s != 0 {
+            return false;
+        }
+        s = offset(s, step).unwrap();
+    }
+    true
+}
+
+//A legal move, either staying in the table or leaving it by a capture or promotion
+enum Child {
+    Stay(Pos),
+    Exit(Vec<(Color, Title, u8)>),
+}
+
+struct Mover<'a> {
+    material: &'a Material,
+}
+
+impl<'a> Mover<'a> {
+    fn occupied(&self, pos: &Pos) -> u64 {
+        pos.squares[..self.material.0.len()]
+            .iter()
+            .fold(0, |o, &s| o | 1 << s)
+    }
+    fn attacked(&self, pos: &Pos, target: u8, by: Color) -> bool {
+        let occupied = self.occupied(pos);
+        self.material
+            .0
+            .iter()
+            .zip(pos.squares.iter())
+            .any(|(&(color, title), &from)| {
+                color == by && attacks(title, color, from, target, occupied)
+            })
+    }
+    fn to_move(pos: &Pos) -> Color {
+        if pos.white_to_move {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+    //Distinct squares, no pawns on the first or last rank and the side not to move not in check
+    fn legal(&self, pos: &Pos) -> bool {
+        let n = self.material.0.len();
+        for i in 0..n {
+            let (rank, _) = rank_file(pos.squares[i]);
+            if self.material.0[i].1 == Title::Pawn && (rank == 0 || rank == 7) {
+                return false;
+            }
+            if pos.squares[..i].contains(&pos.squares[i]) {
+                return false;
+            }
+        }
+        let waiting = Self::to_move(pos).inverse();
+        !self.attacked(pos, pos.squares[waiting.index()], waiting.inverse())
+    }
+    fn in_check(&self, pos: &Pos) -> bool {
+        let color = Self::to_move(pos);
+        self.attacked(pos, pos.squares[color.index()], color.inverse())
+    }
+
+    //The same position under the symmetry that puts the white king on the fewest squares,
+    //ties broken by the lowest squares
+    fn index(&self, pos: &Pos) -> usize {
+        let n = self.material.0.len();
+        let king_squares = self.material.king_squares();
+        let symmetries: &[u8] = if self.material.has_pawns() {
+            &[0, 1]
+        } else {
+            &[0, 1, 2, 3, 4, 5, 6, 7]
+        };
+        let mut best: Option<[u8; MAX_PIECES]> = None;
+        for &symmetry in symmetries {
+            let mut squares = [0; MAX_PIECES];
+            for (square, &s) in squares.iter_mut().zip(pos.squares[..n].iter()) {
+                *square = transform(s, symmetry);
+            }
+            if !king_squares.contains(&squares[0]) {
+                continue;
+            }
+            //Identical pieces in square order so they have one index between them
+            let mut i = 2;
+            while i < n {
+                let mut j = i;
+                while j < n && self.material.0[j] == self.material.0[i] {
+                    j += 1;
+                }
+                squares[i..j].sort_unstable();
+                i = j;
+            }
+            if best.is_none_or(|b| squares[..n] < b[..n]) {
+                best = Some(squares);
+            }
+        }
+        let squares = best.unwrap();
+        let king = king_squares.iter().position(|&s| s == squares[0]).unwrap();
+        let mut index = if pos.white_to_move { 0 } else { 1 };
+        index = index * king_squares.len() + king;
+        for &s in squares[1..n].iter() {
+            index = index * 64 + s as usize;
+        }
+        index
+    }
+    fn position(&self, mut index: usize) -> Pos {
+        let n = self.material.0.len();
+        let king_squares = self.material.king_squares();
+        let mut squares = [0; MAX_PIECES];
+        for i in (1..n).rev() {
+            squares[i] = (index % 64) as u8;
+            index /= 64;
+        }
+        squares[0] = king_squares[index % king_squares.len()];
+        Pos {
+            squares,
+            white_to_move: index / king_squares.len() == 0,
+        }
+    }
+
+    fn moves(&self, pos: &Pos) -> Vec<Child> {
+        let n = self.material.0.len();
+        let color = Self::to_move(pos);
+        let occupied = self.occupied(pos);
+        let occupant = |s: u8| pos.squares[..n].iter().position(|&o| o == s);
+        let mut moves = Vec::new();
+        let mut targets = Vec::with_capacity(28);
+        for i in 0..n {
+            let (c, title) = self.material.0[i];
+            if c != color {
+                continue;
+            }
+            let from = pos.squares[i];
+            //Target square, captured piece and promotion
+            let mut candidates: Vec<(u8, Option<usize>, Option<Title>)> = Vec::new();
+            if title == Title::Pawn {
+                let forward = if color == Color::White { 1 } else { -1 };
+                let last_rank = if color == Color::White { 7 } else { 0 };
+                let mut pawn_targets = Vec::new();
+                if let Some(to) = offset(from, (forward, 0)).filter(|&s| occupant(s).is_none()) {
+                    pawn_targets.push((to, None));
+                    let start_rank = if color == Color::White { 1 } else { 6 };
+                    if rank_file(from).0 == start_rank {
+                        if let Some(to) =
+                            offset(to, (forward, 0)).filter(|&s| occupant(s).is_none())
+                        {
+                            pawn_targets.push((to, None));
+                        }
+                    }
+                }
+                for &f in [1, -1].iter() {
+                    if let Some(to) = offset(from, (forward, f)) {
+                        match occupant(to) {
+                            Some(j) if self.material.0[j].0 != color => {
+                                pawn_targets.push((to, Some(j)))
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                for (to, capture) in pawn_targets {
+                    if rank_file(to).0 == last_rank {
+                        for &t in PROMOTION_TITLES.iter() {
+                            candidates.push((to, capture, Some(t)));
+                        }
+                    } else {
+                        candidates.push((to, capture, None));
+                    }
+                }
+            } else {
+                targets.clear();
+                piece_targets(title, from, occupied, &mut targets);
+                for &to in targets.iter() {
+                    match occupant(to) {
+                        None => candidates.push((to, None, None)),
+                        Some(j) if self.material.0[j].0 != color => {
+                            candidates.push((to, Some(j), None))
+                        }
+                        Some(_) => (),
+                    }
+                }
+            }
+            for (to, capture, promotion) in candidates {
+                if capture.is_some_and(|j| self.material.0[j].1 == Title::King) {
+                    continue;
+                }
+                let mut after = *pos;
+                after.squares[i] = to;
+                after.white_to_move = !pos.white_to_move;
+                if capture.is_none() && promotion.is_none() {
+                    //Own king safety, the position after the move has the mover waiting
+                    if self.legal(&after) {
+                        moves.push(Child::Stay(after));
+                    }
+                    continue;
+                }
+                //Take the captured piece off before testing for check
+                let mut pieces = Vec::with_capacity(n);
+                for j in 0..n {
+                    if Some(j) == capture {
+                        continue;
+                    }
+                    let (c, t) = self.material.0[j];
+                    let t = if j == i { promotion.unwrap_or(t) } else { t };
+                    pieces.push((c, t, after.squares[j]));
+                }
+                let (child, child_pos) = from_pieces(&pieces, after.white_to_move);
+                if (Mover { material: &child }).legal(&child_pos) {
+                    moves.push(Child::Exit(pieces));
+                }
+            }
+        }
+        moves
+    }
+
+    //Positions of this table from which the side that just moved could have reached pos
+    //without capturing or promoting, walked back the same way retro unmoves a board
+    fn unmoves(&self, pos: &Pos) -> Vec<usize> {
+        let n = self.material.0.len();
+        let mover = Self::to_move(pos).inverse();
+        let occupied = self.occupied(pos);
+        let mut preds = Vec::new();
+        for i in 0..n {
+            let (c, title) = self.material.0[i];
+            if c != mover {
+                continue;
+            }
+            let to = Square::from_index(pos.squares[i] as usize).unwrap();
+            let empty = |s: Square| occupied & 1 << s.index() == 0;
+            for from in quiet_origins(title, mover, to, empty) {
+                let mut pred = *pos;
+                pred.squares[i] = from.index() as u8;
+                pred.white_to_move = !pos.white_to_move;
+                if self.legal(&pred) {
+                    let index = self.index(&pred);
+                    if !preds.contains(&index) {
+                        preds.push(index);
+                    }
+                }
+            }
+        }
+        preds
+    }
+}
+
+//The table and position for a list of pieces, with the colors swapped if the table is stored
+//the other way around
+fn from_pieces(pieces: &[(Color, Title, u8)], white_to_move: bool) -> (Material, Pos) {
+    let mut pieces = pieces.to_vec();
+    pieces.sort_by_key(|&(c, t, _)| (t != Title::King, c.index(), -t.value()));
+    let material = Material(pieces.iter().map(|&(c, t, _)| (c, t)).collect());
+    if material.needs_flip() {
+        let flipped: Vec<(Color, Title, u8)> = pieces
+            .iter()
+            .map(|&(c, t, s)| (c.inverse(), t, s ^ 56))
+            .collect();
+        return from_pieces(&flipped, !white_to_move);
+    }
+    let mut squares = [0; MAX_PIECES];
+    for (i, &(_, _, s)) in pieces.iter().enumerate() {
+        squares[i] = s;
+    }
+    (
+        material,
+        Pos {
+            squares,
+            white_to_move,
+        },
+    )
+}
+
+//Distance to mate tables for every position of a set of pieces
+#[derive(Clone, Debug, Default)]
+pub struct Tablebase {
+    tables: HashMap<String, Vec<u8>>, //By material, one value per index
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Names of the tables held, like KQK
+    pub fn tables(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.tables.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    //Generates the table for the material, written like KRKP, and every table it depends on.
+    //Pawns may only be on one side, as en passant is not part of the positions.
+    pub fn generate(&mut self, material: &str) -> Result<(), TablebaseError> {
+        let mut material = Material::parse(material)?;
+        if material.needs_flip() {
+            material =
+                Material::sorted(material.0.iter().map(|&(c, t)| (c.inverse(), t)).collect());
+        }
+        self.generate_material(&material)
+    }
+
+    fn generate_material(&mut self, material: &Material) -> Result<(), TablebaseError> {
+        if self.tables.contains_key(&material.name()) {
+            return Ok(());
+        }
+        for child in material.children() {
+            self.generate_material(&child)?;
+        }
+        let values = self.solve(material, MAX_PLIES)?;
+        self.tables.insert(material.name(), values);
+        Ok(())
+    }
+
+    //Retrograde analysis: mates are found first, then every position one ply further away
+    //from them by walking moves backwards. Fails rather than leave a result past max_plies
+    //looking like a draw.
+    fn solve(&self, material: &Material, max_plies: usize) -> Result<Vec<u8>, TablebaseError> {
+        let mover = Mover { material };
+        let size = material.size();
+        let mut values = vec![0; size];
+        let mut remaining = vec![0u8; size]; //Moves within the table not yet known to lose
+        let mut escapes = vec![false; size]; //Draws or wins by capturing or promoting
+        let mut longest_exit = vec![0u8; size]; //Plies of the slowest loss by capturing or promoting
+        let mut plies: Vec<Vec<u32>> = vec![Vec::new()];
+        let push = |plies: &mut Vec<Vec<u32>>, ply: usize, index: usize| {
+            if plies.len() <= ply {
+                plies.resize(ply + 1, Vec::new());
+            }
+            plies[ply].push(index as u32);
+        };
+
+        for index in 0..size {
+            let pos = mover.position(index);
+            if !mover.legal(&pos) || mover.index(&pos) != index {
+                values[index] = INVALID;
+                continue;
+            }
+            let moves = mover.moves(&pos);
+            if moves.is_empty() {
+                if mover.in_check(&pos) {
+                    push(&mut plies, 0, index);
+                }
+                continue;
+            }
+            let mut children = Vec::new();
+            let mut fastest_win: Option<u32> = None;
+            for m in moves {
+                let pieces = match m {
+                    Child::Stay(child_pos) => {
+                        let child_index = mover.index(&child_pos);
+                        if !children.contains(&child_index) {
+                            children.push(child_index);
+                        }
+                        continue;
+                    }
+                    Child::Exit(pieces) => pieces,
+                };
+                let (child, child_pos) = from_pieces(&pieces, !pos.white_to_move);
+                let table = &self.tables[&child.name()];
+                let outcome =
+                    Outcome::from_value(table[Mover { material: &child }.index(&child_pos)]);
+                match outcome {
+                    Some(Outcome::Loss(p)) => {
+                        fastest_win = Some(fastest_win.map_or(p + 1, |w| w.min(p + 1)))
+                    }
+                    Some(Outcome::Win(p)) => {
+                        //Too long to store is caught with the other results past max_plies
+                        let exit = (p + 1).min(u8::MAX as u32) as u8;
+                        longest_exit[index] = longest_exit[index].max(exit)
+                    }
+                    _ => escapes[index] = true,
+                }
+            }
+            remaining[index] = children.len() as u8;
+            if let Some(p) = fastest_win {
+                escapes[index] = true;
+                push(&mut plies, p as usize, index);
+            } else if children.is_empty() && !escapes[index] {
+                push(&mut plies, longest_exit[index] as usize, index);
+            }
+        }
+
+        let mut ply = 0;
+        while ply < plies.len() && ply <= max_plies {
+            for index in std::mem::take(&mut plies[ply]) {
+                let index = index as usize;
+                if values[index] != 0 {
+                    continue;
+                }
+                values[index] = ply as u8 + 1;
+                for pred in mover.unmoves(&mover.position(index)) {
+                    if values[pred] != 0 {
+                        continue;
+                    }
+                    if ply % 2 == 0 {
+                        //Moving here mates, so the position before is won
+                        push(&mut plies, ply + 1, pred);
+                        continue;
+                    }
+                    remaining[pred] -= 1;
+                    if remaining[pred] == 0 && !escapes[pred] {
+                        let loss = (ply + 1).max(longest_exit[pred] as usize);
+                        push(&mut plies, loss, pred);
+                    }
+                }
+            }
+            ply += 1;
+        }
+        let unsolved = plies
+            .iter()
+            .skip(ply)
+            .flatten()
+            .any(|&index| values[index as usize] == 0);
+        if unsolved {
+            return Err(TablebaseError::TooDeep(material.name()));
+        }
+        Ok(values)
+    }
+
+    fn probe_pieces(&self, pieces: &[(Color, Title, u8)], white_to_move: bool) -> Option<Outcome> {
+        let (material, pos) = from_pieces(pieces, white_to_move);
+        let table = self.tables.get(&material.name())?;
+        let mover = Mover {
+            material: &material,
+        };
+        if !mover.legal(&pos) {
+            return None;
+        }
+        Outcome::from_value(table[mover.index(&pos)])
+    }
+
+    //None without a table for the material, or with castling or en passant still possible
+    pub fn probe(&self, board: &Board) -> Option<Outcome> {
+        if board.pieces.len() > MAX_PIECES || can_capture_en_passant(board) {
+            return None;
+        }
+        let castling = [Color::White, Color::Black]
+            .iter()
+            .any(|&c| board.can_castle(c, true) || board.can_castle(c, false));
+        if castling {
+            return None;
+        }
+        let pieces: Vec<(Color, Title, u8)> = board
+            .pieces
+            .iter()
+            .map(|(s, p)| (p.color, p.title, s.index() as u8))
+            .collect();
+        self.probe_pieces(&pieces, board.to_act == Color::White)
+    }
+
+    //The move keeping the best outcome, the fastest mate or the slowest loss
+    pub fn best_move(&self, board: &Board) -> Option<(MoveData, Outcome)> {
+        self.probe(board)?;
+        let mut best: Option<(MoveData, Outcome)> = None;
+        for m in generate_action_space(board.clone()) {
+            let mut after = board.clone();
+            after.make_move(m);
+            //Positions where en passant can be taken are not in the tables. With pawns on one
+            //side only they do not come up, but such a move is left out rather than losing
+            //the answer for every other move.
+            if can_capture_en_passant(&after) {
+                continue;
+            }
+            let outcome = match self.probe(&after)? {
+                Outcome::Win(p) => Outcome::Loss(p + 1),
+                Outcome::Draw => Outcome::Draw,
+                Outcome::Loss(p) => Outcome::Win(p + 1),
+            };
+            if best.is_none_or(|(_, b)| outcome.rank() > b.rank()) {
+                best = Some((m, outcome));
+            }
+        }
+        best
+    }
+
+    //One file per table: magic, material name, number of values and the values
+    pub fn save(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        fs::create_dir_all(&dir)?;
+        for (name, values) in self.tables.iter() {
+            let mut bytes = Vec::with_capacity(values.len() + 16);
+            bytes.extend_from_slice(MAGIC);
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(values);
+            fs::write(dir.as_ref().join(format!("{}.htb", name)), bytes)?;
+        }
+        Ok(())
+    }
+
+    //Loads every table file in the directory
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, TablebaseError> {
+        let mut tablebase = Self::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "htb") {
+                continue;
+            }
+            let corrupt = || TablebaseError::Corrupt(path.display().to_string());
+            let bytes = fs::read(&path)?;
+            if bytes.get(..4) != Some(&MAGIC[..]) {
+                return Err(corrupt());
+            }
+            let name_len = *bytes.get(4).ok_or_else(corrupt)? as usize;
+            let name = bytes
+                .get(5..5 + name_len)
+                .and_then(|n| std::str::from_utf8(n).ok())
+                .ok_or_else(corrupt)?;
+            let material = Material::parse(name).map_err(|_| corrupt())?;
+            let mut count = [0; 4];
+            count.copy_from_slice(bytes.get(5 + name_len..9 + name_len).ok_or_else(corrupt)?);
+            let values = &bytes[9 + name_len..];
+            if u32::from_le_bytes(count) as usize != values.len() || values.len() != material.size()
+            {
+                return Err(corrupt());
+            }
+            tablebase.tables.insert(material.name(), values.to_vec());
+        }
+        for name in tablebase.tables() {
+            let material = Material::parse(name).unwrap();
+            for child in material.children() {
+                if !tablebase.tables.contains_key(&child.name()) {
+                    return Err(TablebaseError::MissingTable(child.name()));
+                }
+            }
+        }
+        Ok(tablebase)
+    }
+}
+
+#[cfg(test)]
+mod test_tablebase {
+    use super::*;
+    use crate::piece::Piece;
+    use crate::square::Square;
+    use std::sync::OnceLock;
+    //King and pawn against king, with the queen, rook and minor piece tables it promotes into
+    fn kpk() -> &'static Tablebase {
+        static TABLEBASE: OnceLock<Tablebase> = OnceLock::new();
+        TABLEBASE.get_or_init(|| {
+            let mut tablebase = Tablebase::new();
+            tablebase.generate("KPK").unwrap();
+            tablebase
+        })
+    }
+    fn probe(tablebase: &Tablebase, fen: &str) -> Option<Outcome> {
+        tablebase.probe(&Board::from_fen(fen).unwrap())
+    }
+    fn longest_win(tablebase: &Tablebase, name: &str) -> Option<Outcome> {
+        tablebase.tables[name]
+            .iter()
+            .filter_map(|&v| Outcome::from_value(v))
+            .filter(|o| matches!(o, Outcome::Win(_)))
+            .max_by_key(|o| -o.rank())
+    }
+    #[test]
+    fn material() {
+        assert_eq!(Material::parse("KRKP").unwrap().name(), "KRKP");
+        assert_eq!(Material::parse("KPKR").unwrap().name(), "KPKR");
+        assert!(Material::parse("KPKR").unwrap().needs_flip());
+        assert!(Material::parse("KQ").is_err());
+        assert!(Material::parse("KQKX").is_err());
+        assert!(Material::parse("KQRKBN").is_err());
+        let children: Vec<String> = Material::parse("KPK")
+            .unwrap()
+            .children()
+            .iter()
+            .map(Material::name)
+            .collect();
+        assert_eq!(children, vec!["KK", "KQK", "KNK", "KRK", "KBK"]);
+        assert!(matches!(
+            Tablebase::new().generate("KPKP"),
+            Err(TablebaseError::PawnsOnBothSides(_))
+        ));
+    }
+    //The boards after each move, as sorted piece lists
+    fn children(material: &Material, pos: &Pos) -> Vec<Vec<(Color, Title, u8)>> {
+        let mut found: Vec<Vec<(Color, Title, u8)>> = Mover { material }
+            .moves(pos)
+            .into_iter()
+            .map(|child| match child {
+                Child::Stay(after) => material
+                    .0
+                    .iter()
+                    .zip(after.squares.iter())
+                    .map(|(&(c, t), &s)| (c, t, s))
+                    .collect(),
+                Child::Exit(pieces) => pieces,
+            })
+            .collect();
+        for pieces in found.iter_mut() {
+            pieces.sort_by_key(|&(_, _, s)| s);
+        }
+        found.sort_by_key(|pieces| format!("{:?}", pieces));
+        found
+    }
+    fn board_children(board: &Board) -> Vec<Vec<(Color, Title, u8)>> {
+        let mut found: Vec<Vec<(Color, Title, u8)>> = generate_action_space(board.clone())
+            .into_iter()
+            .map(|m| {
+                let mut after = board.clone();
+                after.make_move(m);
+                let mut pieces: Vec<(Color, Title, u8)> = after
+                    .pieces
+                    .iter()
+                    .map(|(s, p)| (p.color, p.title, s.index() as u8))
+                    .collect();
+                pieces.sort_by_key(|&(_, _, s)| s);
+                pieces
+            })
+            .collect();
+        found.sort_by_key(|pieces| format!("{:?}", pieces));
+        found
+    }
+    #[test]
+    fn moves_match_movegen() {
+        //Every position of the smallest table and a sample of the others, movegen is too slow
+        //in debug builds to go through them all
+        for &(name, step) in [
+            ("KK", 1),
+            ("KPK", 53),
+            ("KKP", 53),
+            ("KQK", 17),
+            ("KBKN", 4001),
+        ]
+        .iter()
+        {
+            let material = Material::parse(name).unwrap();
+            let mover = Mover {
+                material: &material,
+            };
+            let mut checked = 0;
+            for index in (0..material.size()).step_by(step) {
+                let pos = mover.position(index);
+                if !mover.legal(&pos) || mover.index(&pos) != index {
+                    continue;
+                }
+                let mut board = Board::new_empty();
+                for (&(color, title), &s) in material.0.iter().zip(pos.squares.iter()) {
+                    let square = Square::from_index(s as usize).unwrap();
+                    let mut piece = Piece::new(color, title);
+                    //No castling, and double steps from the start rank
+                    piece.has_moved =
+                        title != Title::Pawn || square.rank() != 7 - color.seventh_rank();
+                    board.pieces.insert(square, piece);
+                }
+                board.to_act = Mover::to_move(&pos);
+                board.update_status();
+                assert_eq!(
+                    children(&material, &pos),
+                    board_children(&board),
+                    "{}",
+                    board.to_fen()
+                );
+                checked += 1;
+            }
+            assert!(checked > 0);
+        }
+    }
+    #[test]
+    fn too_deep() {
+        let mut tablebase = Tablebase::new();
+        tablebase.generate("KK").unwrap();
+        let material = Material::parse("KQK").unwrap();
+        assert!(matches!(
+            tablebase.solve(&material, 10),
+            Err(TablebaseError::TooDeep(_))
+        ));
+        assert!(tablebase.solve(&material, MAX_PLIES).is_ok());
+    }
+    #[test]
+    fn queen_and_rook_mates() {
+        let mut tablebase = Tablebase::new();
+        tablebase.tables = kpk().tables.clone();
+        tablebase
+            .tables
+            .retain(|name, _| name == "KK" || name == "KQK");
+        //Stored with the rook as White's
+        tablebase.generate("KKR").unwrap();
+        assert_eq!(tablebase.tables(), vec!["KK", "KQK", "KRK"]);
+        //The longest mates are known to take 10 and 16 moves
+        assert_eq!(longest_win(&tablebase, "KQK"), Some(Outcome::Win(19)));
+        assert_eq!(longest_win(&tablebase, "KRK"), Some(Outcome::Win(31)));
+
+        assert_eq!(
+            probe(&tablebase, "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"),
+            Some(Outcome::Loss(0))
+        );
+        assert_eq!(
+            probe(&tablebase, "k7/8/1Q6/8/8/8/8/K7 b - - 0 1"),
+            Some(Outcome::Draw)
+        );
+        let board = Board::from_fen("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap();
+        let (m, outcome) = tablebase.best_move(&board).unwrap();
+        assert_eq!(outcome, Outcome::Win(1));
+        assert_eq!(outcome.to_string(), "mate in 1");
+        let mut after = board.clone();
+        after.make_move(m);
+        assert_eq!(tablebase.probe(&after), Some(Outcome::Loss(0)));
+        //Black's rook is found in the same table
+        let outcome = probe(&tablebase, "8/8/8/4k3/8/8/r7/4K3 b - - 0 1").unwrap();
+        assert!(matches!(outcome, Outcome::Win(_)));
+        assert_eq!(
+            outcome.dtm(),
+            Some((outcome.rank() - 1000).abs() as i32 / 2 + 1)
+        );
+        //Not in the tablebase
+        assert_eq!(probe(&tablebase, "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), None);
+        assert_eq!(probe(&tablebase, "4k3/8/8/8/8/8/8/RB2K3 w - - 0 1"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        tablebase.save(dir.path()).unwrap();
+        let loaded = Tablebase::load(dir.path()).unwrap();
+        assert_eq!(loaded.tables, tablebase.tables);
+        fs::remove_file(dir.path().join("KK.htb")).unwrap();
+        assert!(matches!(
+            Tablebase::load(dir.path()),
+            Err(TablebaseError::MissingTable(_))
+        ));
+        fs::write(dir.path().join("KK.htb"), b"HTB1").unwrap();
+        assert!(matches!(
+            Tablebase::load(dir.path()),
+            Err(TablebaseError::Corrupt(_))
+        ));
+    }
+    #[test]
+    fn pawn_endings() {
+        let tablebase = kpk();
+        //King on the sixth in front of its pawn wins, whoever moves
+        for fen in [
+            "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+            "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+        ]
+        .iter()
+        {
+            let outcome = probe(tablebase, fen).unwrap();
+            assert_eq!(
+                outcome.dtm().unwrap().signum(),
+                if fen.contains(" w ") { 1 } else { -1 }
+            );
+        }
+        //The same with the colors swapped
+        assert!(matches!(
+            probe(tablebase, "8/8/8/8/4p3/4k3/8/4K3 w - - 0 1"),
+            Some(Outcome::Loss(_))
+        ));
+        //Rook pawns and stalemate
+        assert_eq!(
+            probe(tablebase, "k7/8/K7/P7/8/8/8/8 w - - 0 1"),
+            Some(Outcome::Draw)
+        );
+        assert_eq!(
+            probe(tablebase, "4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"),
+            Some(Outcome::Draw)
+        );
+        //Promotion goes through the queen table
+        let board = Board::from_fen("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        let (m, outcome) = tablebase.best_move(&board).unwrap();
+        let plies = match outcome {
+            Outcome::Win(p) => p,
+            _ => panic!("{}", outcome),
+        };
+        let mut after = board.clone();
+        after.make_move(m);
+        assert_eq!(tablebase.probe(&after), Some(Outcome::Loss(plies - 1)));
+        let mut queen = board.clone();
+        queen.make_move(MoveData::from_uci(&board, "e7e8q").unwrap());
+        assert!(matches!(tablebase.probe(&queen), Some(Outcome::Loss(_))));
+    }
+}
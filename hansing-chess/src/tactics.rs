@@ -0,0 +1,331 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::movedata::MoveData;
+use crate::piece::Piece;
+use crate::square::Square;
+use crate::title::Title;
+use std::fmt;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Motif {
+    //One piece attacking several that cannot all be saved
+    Fork {
+        attacker: Square,
+        targets: Vec<Square>,
+    },
+    //The pinned piece cannot leave the line without exposing the target, the king when absolute
+    Pin {
+        pinner: Square,
+        pinned: Square,
+        target: Square,
+        absolute: bool,
+    },
+    //A valuable piece attacked with a lesser one behind it on the line
+    Skewer {
+        attacker: Square,
+        front: Square,
+        behind: Square,
+    },
+    //A piece moved off a line and uncovered an attack by the piece behind it
+    DiscoveredAttack {
+        moved: Square,
+        attacker: Square,
+        target: Square,
+    },
+    DoubleCheck {
+        king: Square,
+        checkers: Vec<Square>,
+    },
+    //The only defender of several attacked pieces
+    Overloaded {
+        defender: Square,
+        defended: Vec<Square>,
+    },
+    //Attacked and not defended
+    Hanging {
+        square: Square,
+    },
+}
+
+fn squares(list: &[Square]) -> String {
+    list.iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Motif {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Motif::Fork { attacker, targets } => {
+                write!(f, "fork: {} attacks {}", attacker, squares(targets))
+            }
+            Motif::Pin {
+                pinner,
+                pinned,
+                target,
+                absolute,
+            } => write!(
+                f,
+                "{}pin: {} pins {} to {}",
+                if *absolute { "absolute " } else { "" },
+                pinner,
+                pinned,
+                target
+            ),
+            Motif::Skewer {
+                attacker,
+                front,
+                behind,
+            } => write!(
+                f,
+                "skewer: {} attacks {} and {} behind it",
+                attacker, front, behind
+            ),
+            Motif::DiscoveredAttack {
+                moved,
+                attacker,
+                target,
+            } => write!(
+                f,
+                "discovered attack: {} uncovers {} on {}",
+                moved, attacker, target
+            ),
+            Motif::DoubleCheck { king, checkers } => {
+                write!(f, "double check: {} by {}", king, squares(checkers))
+            }
+            Motif::Overloaded { defender, defended } => {
+                write!(f, "overloaded: {} defends {}", defender, squares(defended))
+            }
+            Motif::Hanging { square } => write!(f, "hanging: {}", square),
+        }
+    }
+}
+
+//Pieces of a color attacking the square, in square order
+fn attackers_by(board: &Board, s: Square, color: Color) -> Vec<Square> {
+    let mut attackers: Vec<Square> = board
+        .attackers(s)
+        .into_iter()
+        .filter(|a| board.pieces[a].color == color)
+        .collect();
+    attackers.sort_by_key(|a| a.index());
+    attackers
+}
+
+//Every motif on the board, for both sides
+pub fn motifs(board: &Board) -> Vec<Motif> {
+    let mut pieces: Vec<(Square, Piece)> = board.pieces.iter().map(|(&s, &p)| (s, p)).collect();
+    pieces.sort_by_key(|(s, _)| s.index());
+    let mut found = Vec::new();
+
+    let king = board.king_pos[board.to_act.index()];
+    let checkers = attackers_by(board, king, board.to_act.inverse());
+    if checkers.len() > 1 {
+        found.push(Motif::DoubleCheck { king, checkers });
+    }
+
+    //Pins and skewers: an attacked piece with another behind it that the attacker only
+    //reaches through it, as the board's x-ray attacks find it
+    for &(s, p) in pieces.iter() {
+        for &(front, front_piece) in pieces.iter() {
+            if front_piece.color == p.color || !board.attackers(front).contains(&s) {
+                continue;
+            }
+            for &(behind, behind_piece) in pieces.iter() {
+                if behind_piece.color == p.color
+                    || board.attackers(behind).contains(&s)
+                    || !board.attackers_through(behind, &[front]).contains(&s)
+                {
+                    continue;
+                }
+                let (front_value, behind_value) =
+                    (front_piece.title.value(), behind_piece.title.value());
+                if behind_value > front_value {
+                    found.push(Motif::Pin {
+                        pinner: s,
+                        pinned: front,
+                        target: behind,
+                        absolute: behind_piece.title == Title::King,
+                    });
+                } else if front_value > behind_value {
+                    found.push(Motif::Skewer {
+                        attacker: s,
+                        front,
+                        behind,
+                    });
+                }
+            }
+        }
+    }
+
+    //Forks: attacked kings, pieces worth more than the attacker and undefended pieces count
+    for &(s, p) in pieces.iter() {
+        let targets: Vec<Square> = pieces
+            .iter()
+            .filter(|(t, target)| {
+                target.color != p.color
+                    && board.attackers(*t).contains(&s)
+                    && (target.title == Title::King
+                        || target.title.value() > p.title.value()
+                        || attackers_by(board, *t, target.color).is_empty())
+            })
+            .map(|(t, _)| *t)
+            .collect();
+        if targets.len() > 1 {
+            found.push(Motif::Fork {
+                attacker: s,
+                targets,
+            });
+        }
+    }
+
+    for &(s, p) in pieces.iter() {
+        if p.title == Title::King {
+            continue;
+        }
+        let attacked = !attackers_by(board, s, p.color.inverse()).is_empty();
+        if attacked && attackers_by(board, s, p.color).is_empty() {
+            found.push(Motif::Hanging { square: s });
+        }
+    }
+
+    //Overloaded defenders: the only defender of two or more attacked pieces
+    for &(d, p) in pieces.iter() {
+        let defended: Vec<Square> = pieces
+            .iter()
+            .filter(|(t, target)| {
+                *t != d
+                    && target.color == p.color
+                    && target.title != Title::King
+                    && !attackers_by(board, *t, p.color.inverse()).is_empty()
+                    && attackers_by(board, *t, p.color) == [d]
+            })
+            .map(|(t, _)| *t)
+            .collect();
+        if defended.len() > 1 {
+            found.push(Motif::Overloaded {
+                defender: d,
+                defended,
+            });
+        }
+    }
+    found
+}
+
+//The motifs a move creates: the ones on the board afterwards that were not there before, and
+//attacks it uncovers by moving off a line
+pub fn move_motifs(board: &Board, move_data: MoveData) -> Vec<Motif> {
+    let mut after = board.clone();
+    after.make_move(move_data);
+    let before = motifs(board);
+    let mut found: Vec<Motif> = motifs(&after)
+        .into_iter()
+        .filter(|m| !before.contains(m))
+        .collect();
+
+    let mover = board.to_act;
+    let mut targets: Vec<Square> = after
+        .pieces
+        .iter()
+        .filter(|(_, p)| p.color != mover)
+        .map(|(&s, _)| s)
+        .collect();
+    targets.sort_by_key(|s| s.index());
+    for target in targets {
+        for attacker in attackers_by(&after, target, mover) {
+            if attacker != move_data.to
+                && !board.attackers(target).contains(&attacker)
+                && board
+                    .attackers_through(target, &[move_data.from])
+                    .contains(&attacker)
+            {
+                found.push(Motif::DiscoveredAttack {
+                    moved: move_data.to,
+                    attacker,
+                    target,
+                });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test_tactics {
+    use super::*;
+    use crate::testutil::{sq, sqs};
+
+    #[test]
+    fn forks_and_hanging() {
+        let board = Board::from_fen("r3k3/2N5/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        let found = motifs(&board);
+        assert!(found.contains(&Motif::Fork {
+            attacker: sq("c7"),
+            targets: sqs(&["a8", "e8"]),
+        }));
+        assert!(found.contains(&Motif::Hanging { square: sq("a8") }));
+        assert!(!found.contains(&Motif::Hanging { square: sq("c7") }));
+    }
+
+    #[test]
+    fn pins_and_skewers() {
+        let board = Board::from_fen("4k3/4n3/8/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+        assert!(motifs(&board).contains(&Motif::Pin {
+            pinner: sq("e1"),
+            pinned: sq("e7"),
+            target: sq("e8"),
+            absolute: true,
+        }));
+
+        let board = Board::from_fen("4k3/3q4/2n5/1B6/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(motifs(&board).contains(&Motif::Pin {
+            pinner: sq("b5"),
+            pinned: sq("c6"),
+            target: sq("d7"),
+            absolute: false,
+        }));
+
+        let board = Board::from_fen("8/8/8/8/R3k2q/8/8/K7 b - - 0 1").unwrap();
+        let found = motifs(&board);
+        assert!(found.contains(&Motif::Skewer {
+            attacker: sq("a4"),
+            front: sq("e4"),
+            behind: sq("h4"),
+        }));
+        assert!(!found.iter().any(|m| matches!(m, Motif::Pin { .. })));
+    }
+
+    #[test]
+    fn overloaded_defender() {
+        let board = Board::from_fen("3qk3/8/8/b2n2N1/8/5B2/8/R5K1 w - - 0 1").unwrap();
+        let found = motifs(&board);
+        assert!(found.contains(&Motif::Overloaded {
+            defender: sq("d8"),
+            defended: sqs(&["a5", "d5"]),
+        }));
+        assert!(found.contains(&Motif::Hanging { square: sq("g5") }));
+        assert!(!found.contains(&Motif::Hanging { square: sq("d5") }));
+    }
+
+    #[test]
+    fn discovered_double_check() {
+        let board = Board::from_fen("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1").unwrap();
+        let m = MoveData::from_uci(&board, "e4d6").unwrap();
+        let found = move_motifs(&board, m);
+        assert!(found.contains(&Motif::DoubleCheck {
+            king: sq("e8"),
+            checkers: sqs(&["e1", "d6"]),
+        }));
+        assert!(found.contains(&Motif::DiscoveredAttack {
+            moved: sq("d6"),
+            attacker: sq("e1"),
+            target: sq("e8"),
+        }));
+        assert_eq!(
+            found[0].to_string(),
+            "double check: e8 by e1, d6".to_string()
+        );
+        assert!(motifs(&board).is_empty());
+    }
+}
@@ -0,0 +1,21 @@
+//Shorthands for building positions in tests
+use crate::board::Board;
+use crate::color::Color;
+use crate::piece::Piece;
+use crate::square::Square;
+use crate::title::Title;
+
+pub fn sq(s: &str) -> Square {
+    Square::from_notation(s).unwrap()
+}
+
+pub fn sqs(list: &[&str]) -> Vec<Square> {
+    list.iter().map(|s| sq(s)).collect()
+}
+
+//Placed as moved, so pawns have no double step and kings and rooks cannot castle
+pub fn place(board: &mut Board, s: &str, color: Color, title: Title) {
+    let mut p = Piece::new(color, title);
+    p.has_moved = true;
+    board.pieces.insert(sq(s), p);
+}
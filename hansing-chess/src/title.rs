@@ -1,4 +1,5 @@
 #[derive(PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Title {
     Pawn,
     Knight,
@@ -8,6 +9,30 @@ pub enum Title {
     King,
 }
 
+impl Title {
+    pub fn index(&self) -> usize {
+        match self {
+            Title::Pawn => 0,
+            Title::Knight => 1,
+            Title::Bishop => 2,
+            Title::Rook => 3,
+            Title::Queen => 4,
+            Title::King => 5,
+        }
+    }
+    //Centipawn values, the king is valued high enough to never be traded
+    pub fn value(&self) -> i32 {
+        match self {
+            Title::Pawn => 100,
+            Title::Knight => 320,
+            Title::Bishop => 330,
+            Title::Rook => 500,
+            Title::Queen => 900,
+            Title::King => 20000,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_title {
     use super::*;
@@ -15,4 +40,10 @@ mod test_title {
     fn eq() {
         assert_eq!(Title::Bishop, Title::Bishop)
     }
+    #[test]
+    fn value() {
+        assert!(Title::Pawn.value() < Title::Knight.value());
+        assert!(Title::Rook.value() < Title::Queen.value());
+        assert!(Title::Queen.value() < Title::King.value());
+    }
 }
@@ -0,0 +1,111 @@
+use crate::movedata::MoveData;
+use std::mem::size_of;
+use std::sync::Mutex;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Bound {
+    Exact,
+    Lower, //Score is at least this (beta cutoff)
+    Upper, //Score is at most this (failed low)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TtEntry {
+    pub key: u64,
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<MoveData>,
+}
+
+//Shared between search threads, each slot is locked on its own so threads rarely wait on each other
+pub struct TranspositionTable {
+    entries: Vec<Mutex<Option<TtEntry>>>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let wanted = (size_mb.max(1) << 20) / size_of::<Mutex<Option<TtEntry>>>();
+        //Round down to a power of two so the index is a mask
+        let mut len = 1;
+        while len * 2 <= wanted {
+            len *= 2;
+        }
+        Self {
+            entries: (0..len).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        match *self.entries[self.index(key)].lock().unwrap() {
+            Some(entry) if entry.key == key => Some(entry),
+            _ => None,
+        }
+    }
+
+    //Keeps the deeper entry when both are for the same position
+    pub fn store(&self, entry: TtEntry) {
+        let mut slot = self.entries[self.index(entry.key)].lock().unwrap();
+        if let Some(old) = *slot {
+            if old.key == entry.key && old.depth > entry.depth {
+                return;
+            }
+        }
+        *slot = Some(entry);
+    }
+
+    pub fn clear(&self) {
+        for e in self.entries.iter() {
+            *e.lock().unwrap() = None;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test_tt {
+    use super::*;
+    fn entry(key: u64, depth: i32, score: i32) -> TtEntry {
+        TtEntry {
+            key,
+            depth,
+            score,
+            bound: Bound::Exact,
+            best_move: None,
+        }
+    }
+    #[test]
+    fn size_is_power_of_two() {
+        let tt = TranspositionTable::new(1);
+        assert!(tt.len().is_power_of_two());
+        assert!(tt.len() * size_of::<Mutex<Option<TtEntry>>>() <= 1 << 20);
+    }
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        tt.store(entry(12345, 3, 50));
+        assert_eq!(tt.probe(12345).unwrap().score, 50);
+        assert!(tt.probe(54321).is_none());
+        //Shallower result for the same position is ignored
+        tt.store(entry(12345, 1, 10));
+        assert_eq!(tt.probe(12345).unwrap().score, 50);
+        //Colliding position replaces
+        let other = 12345 + tt.len() as u64;
+        tt.store(entry(other, 1, 10));
+        assert!(tt.probe(12345).is_none());
+        assert_eq!(tt.probe(other).unwrap().score, 10);
+        tt.clear();
+        assert!(tt.probe(other).is_none());
+    }
+}
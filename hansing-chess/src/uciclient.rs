@@ -0,0 +1,579 @@
+use crate::board::Board;
+use crate::fen::STANDARD_FEN;
+use crate::game::Game;
+use crate::movedata::MoveData;
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+//How long an engine gets to answer stop or quit before it is given up on
+const GRACE: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum UciError {
+    Spawn(io::Error),
+    Io(io::Error),
+    Timeout(&'static str), //What was being waited for
+    Crashed(Option<i32>),  //Exit code if there was one
+    Protocol(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciError::Spawn(e) => write!(f, "could not start engine: {}", e),
+            UciError::Io(e) => write!(f, "engine communication failed: {}", e),
+            UciError::Timeout(waiting) => write!(f, "engine timed out waiting for {}", waiting),
+            UciError::Crashed(Some(code)) => write!(f, "engine exited with code {}", code),
+            UciError::Crashed(None) => write!(f, "engine was killed"),
+            UciError::Protocol(line) => write!(f, "unexpected engine output '{}'", line),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32), //Full moves, negative when the engine is getting mated
+}
+
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Info {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<Score>,
+    pub lowerbound: bool,
+    pub upperbound: bool,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time: Option<Duration>,
+    pub hashfull: Option<u32>,
+    pub pv: Vec<MoveData>,
+    pub string: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct BestMove {
+    pub best_move: Option<MoveData>, //None when the engine answers 0000 or (none)
+    pub ponder: Option<MoveData>,
+    pub infos: Vec<Info>,
+}
+
+//Limits for go, unset fields are left out of the command
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Go {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+}
+
+impl Go {
+    pub fn depth(depth: u32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Self::default()
+        }
+    }
+    pub fn infinite() -> Self {
+        Self {
+            infinite: true,
+            ..Self::default()
+        }
+    }
+    pub fn movetime(movetime: Duration) -> Self {
+        Self {
+            movetime: Some(movetime),
+            ..Self::default()
+        }
+    }
+
+    pub fn to_command(&self) -> String {
+        let mut command = "go".to_string();
+        let ms = |d: &Duration| d.as_millis();
+        let mut add = |name: &str, value: Option<String>| {
+            if let Some(v) = value {
+                command.push_str(&format!(" {} {}", name, v));
+            }
+        };
+        add("depth", self.depth.map(|v| v.to_string()));
+        add("nodes", self.nodes.map(|v| v.to_string()));
+        add(
+            "movetime",
+            self.movetime.as_ref().map(|v| ms(v).to_string()),
+        );
+        add("wtime", self.wtime.as_ref().map(|v| ms(v).to_string()));
+        add("btime", self.btime.as_ref().map(|v| ms(v).to_string()));
+        add("winc", self.winc.as_ref().map(|v| ms(v).to_string()));
+        add("binc", self.binc.as_ref().map(|v| ms(v).to_string()));
+        add("movestogo", self.movestogo.map(|v| v.to_string()));
+        if self.infinite {
+            command.push_str(" infinite");
+        }
+        command
+    }
+}
+
+//The position command for a game, from startpos when possible
+pub fn position_command(game: &Game) -> String {
+    let start_fen = game.start.to_fen();
+    let mut command = if start_fen == STANDARD_FEN {
+        "position startpos".to_string()
+    } else {
+        format!("position fen {}", start_fen)
+    };
+    if !game.moves.is_empty() {
+        command.push_str(" moves");
+        for m in game.moves.iter() {
+            command.push(' ');
+            command.push_str(&m.to_uci());
+        }
+    }
+    command
+}
+
+//Parses an info line, pv moves are checked against the position and cut at the first illegal one
+pub fn parse_info(line: &str, board: &Board) -> Info {
+    let mut info = Info::default();
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let number = |i: usize| tokens.get(i).and_then(|t| t.parse::<i64>().ok());
+    let mut i = 1;
+    while i < tokens.len() {
+        match tokens[i] {
+            "depth" => info.depth = number(i + 1).map(|v| v as u32),
+            "seldepth" => info.seldepth = number(i + 1).map(|v| v as u32),
+            "multipv" => info.multipv = number(i + 1).map(|v| v as u32),
+            "nodes" => info.nodes = number(i + 1).map(|v| v as u64),
+            "nps" => info.nps = number(i + 1).map(|v| v as u64),
+            "hashfull" => info.hashfull = number(i + 1).map(|v| v as u32),
+            "time" => info.time = number(i + 1).map(|v| Duration::from_millis(v as u64)),
+            "score" => {
+                info.score = match tokens.get(i + 1) {
+                    Some(&"cp") => number(i + 2).map(|v| Score::Centipawns(v as i32)),
+                    Some(&"mate") => number(i + 2).map(|v| Score::Mate(v as i32)),
+                    _ => None,
+                };
+                i += 3;
+                continue;
+            }
+            "lowerbound" | "upperbound" => {
+                info.lowerbound |= tokens[i] == "lowerbound";
+                info.upperbound |= tokens[i] == "upperbound";
+                i += 1;
+                continue;
+            }
+            "pv" => {
+                let mut board = board.clone();
+                for note in tokens[i + 1..].iter() {
+                    match MoveData::from_uci(&board, note) {
+                        Some(m) => {
+                            board.make_move(m);
+                            info.pv.push(m);
+                        }
+                        None => break,
+                    }
+                }
+                break;
+            }
+            "string" => {
+                info.string = Some(tokens[i + 1..].join(" "));
+                break;
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    info
+}
+
+//A running external engine. The process is killed when this is dropped.
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    position: Board,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub options: Vec<String>, //Raw option lines from the handshake
+}
+
+impl UciEngine {
+    //Starts the engine and waits for uciok
+    pub fn spawn(program: &str, args: &[&str], timeout: Duration) -> Result<Self, UciError> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(UciError::Spawn)?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(l) => {
+                        if sender.send(l).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut engine = Self {
+            child,
+            stdin,
+            lines,
+            position: Board::new_standard(),
+            name: None,
+            author: None,
+            options: Vec::new(),
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = engine.read_line(deadline, "uciok")?;
+            if line == "uciok" {
+                break;
+            } else if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = Some(name.to_string());
+            } else if let Some(author) = line.strip_prefix("id author ") {
+                engine.author = Some(author.to_string());
+            } else if line.starts_with("option ") {
+                engine.options.push(line);
+            }
+        }
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), UciError> {
+        let written = writeln!(self.stdin, "{}", command).and_then(|_| self.stdin.flush());
+        match written {
+            Ok(()) => Ok(()),
+            //A broken pipe means the engine is gone
+            Err(_) if self.exit_code().is_some() => {
+                Err(UciError::Crashed(self.exit_code().unwrap()))
+            }
+            Err(e) => Err(UciError::Io(e)),
+        }
+    }
+
+    fn read_line(&mut self, deadline: Instant, waiting: &'static str) -> Result<String, UciError> {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match self.lines.recv_timeout(left) {
+            Ok(line) => Ok(line.trim().to_string()),
+            Err(RecvTimeoutError::Timeout) => Err(UciError::Timeout(waiting)),
+            Err(RecvTimeoutError::Disconnected) => {
+                //Output closed, give the process a moment to finish exiting
+                let code = self.wait_for_exit(GRACE);
+                Err(UciError::Crashed(code.unwrap_or(None)))
+            }
+        }
+    }
+
+    //Some(exit code) once the process has exited
+    fn exit_code(&mut self) -> Option<Option<i32>> {
+        match self.child.try_wait() {
+            Ok(Some(status)) => Some(status.code()),
+            _ => None,
+        }
+    }
+
+    fn wait_for_exit(&mut self, timeout: Duration) -> Option<Option<i32>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(code) = self.exit_code() {
+                return Some(code);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    pub fn is_ready(&mut self, timeout: Duration) -> Result<(), UciError> {
+        self.send("isready")?;
+        let deadline = Instant::now() + timeout;
+        while self.read_line(deadline, "readyok")? != "readyok" {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), UciError> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    pub fn new_game(&mut self, timeout: Duration) -> Result<(), UciError> {
+        self.send("ucinewgame")?;
+        self.is_ready(timeout)
+    }
+
+    pub fn set_position(&mut self, game: &Game) -> Result<(), UciError> {
+        self.position = game.board.clone();
+        self.send(&position_command(game))
+    }
+
+    //Searches the last set position. If the engine has not answered within the timeout it is
+    //sent stop and gets a short grace period before this gives up.
+    pub fn go(&mut self, go: &Go, timeout: Duration) -> Result<BestMove, UciError> {
+        self.go_with(go, timeout, &mut |_| ())
+    }
+
+    pub fn go_with(
+        &mut self,
+        go: &Go,
+        timeout: Duration,
+        on_info: &mut dyn FnMut(&Info),
+    ) -> Result<BestMove, UciError> {
+        self.send(&go.to_command())?;
+        let mut deadline = Instant::now() + timeout;
+        let mut stopped = false;
+        let mut infos = Vec::new();
+        loop {
+            let line = match self.read_line(deadline, "bestmove") {
+                Ok(line) => line,
+                Err(UciError::Timeout(_)) if !stopped => {
+                    self.send("stop")?;
+                    stopped = true;
+                    deadline = Instant::now() + GRACE;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if line.starts_with("info") {
+                let info = parse_info(&line, &self.position);
+                on_info(&info);
+                infos.push(info);
+            } else if line.starts_with("bestmove") {
+                let mut tokens = line.split_whitespace().skip(1);
+                let best_move = match tokens.next() {
+                    Some("0000") | Some("(none)") => None,
+                    Some(note) => Some(
+                        MoveData::from_uci(&self.position, note)
+                            .ok_or_else(|| UciError::Protocol(line.clone()))?,
+                    ),
+                    None => return Err(UciError::Protocol(line)),
+                };
+                let ponder = match (best_move, tokens.next(), tokens.next()) {
+                    (Some(m), Some("ponder"), Some(note)) => {
+                        let mut after = self.position.clone();
+                        after.make_move(m);
+                        MoveData::from_uci(&after, note)
+                    }
+                    _ => None,
+                };
+                return Ok(BestMove {
+                    best_move,
+                    ponder,
+                    infos,
+                });
+            }
+        }
+    }
+
+    //Asks the engine to quit, killing it if it does not exit in time
+    pub fn quit(mut self) -> Option<i32> {
+        self.send("quit").ok();
+        match self.wait_for_exit(GRACE) {
+            Some(code) => code,
+            None => {
+                self.child.kill().ok();
+                self.child.wait().ok().and_then(|s| s.code())
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if self.exit_code().is_none() {
+            self.child.kill().ok();
+            self.child.wait().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_uciclient {
+    use super::*;
+    use crate::square::Square;
+
+    #[test]
+    fn info_parsing() {
+        let board = Board::new_standard();
+        let info = parse_info(
+            "info depth 12 seldepth 18 multipv 2 score cp -35 upperbound nodes 12345 nps 100000 time 123 hashfull 10 pv e2e4 e7e5 g1f3",
+            &board,
+        );
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.seldepth, Some(18));
+        assert_eq!(info.multipv, Some(2));
+        assert_eq!(info.score, Some(Score::Centipawns(-35)));
+        assert!(info.upperbound);
+        assert_eq!(info.nodes, Some(12345));
+        assert_eq!(info.time, Some(Duration::from_millis(123)));
+        assert_eq!(info.hashfull, Some(10));
+        assert_eq!(info.pv.len(), 3);
+        assert_eq!(info.pv[2].to_uci(), "g1f3");
+
+        let info = parse_info("info score mate -3 pv e2e4 e2e4", &board);
+        assert_eq!(info.score, Some(Score::Mate(-3)));
+        assert_eq!(info.pv.len(), 1);
+
+        let info = parse_info("info string hello there", &board);
+        assert_eq!(info.string, Some("hello there".to_string()));
+    }
+
+    #[test]
+    fn go_command() {
+        assert_eq!(Go::depth(5).to_command(), "go depth 5");
+        let go = Go {
+            wtime: Some(Duration::from_secs(60)),
+            btime: Some(Duration::from_secs(50)),
+            winc: Some(Duration::from_millis(500)),
+            infinite: false,
+            ..Go::default()
+        };
+        assert_eq!(go.to_command(), "go wtime 60000 btime 50000 winc 500");
+    }
+
+    #[test]
+    fn position() {
+        let mut game = Game::new();
+        assert_eq!(position_command(&game), "position startpos");
+        game.make_move_from_notation("e2 e4".to_string());
+        game.make_move_from_notation("e7 e5".to_string());
+        assert_eq!(position_command(&game), "position startpos moves e2e4 e7e5");
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let mut game = Game::from_board(Board::from_fen(fen).unwrap());
+        let castle = game
+            .find_move(
+                Square::from_notation("e1").unwrap(),
+                Square::from_notation("g1").unwrap(),
+            )
+            .unwrap();
+        game.make_move(castle);
+        assert_eq!(
+            position_command(&game),
+            format!("position fen {} moves e1g1", fen)
+        );
+    }
+
+    #[cfg(unix)]
+    mod mock {
+        use super::*;
+        use std::fs;
+        use std::path::{Path, PathBuf};
+
+        //Writes a shell script standing in for an engine, go is answered by the given lines
+        fn mock_engine(dir: &Path, on_go: &str) -> PathBuf {
+            let path = dir.join("engine.sh");
+            let script = format!(
+                r#"while read line; do
+  case "$line" in
+    uci) echo "id name Mock Engine"; echo "id author Tester";
+         echo "option name Hash type spin default 1 min 1 max 16"; echo "uciok";;
+    isready) echo "readyok";;
+    go*) {};;
+    stop) echo "bestmove e2e4";;
+    quit) exit 0;;
+  esac
+done
+"#,
+                on_go
+            );
+            fs::write(&path, script).unwrap();
+            path
+        }
+
+        fn spawn(path: &Path) -> UciEngine {
+            UciEngine::spawn("sh", &[path.to_str().unwrap()], Duration::from_secs(5)).unwrap()
+        }
+
+        #[test]
+        fn handshake_and_search() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = mock_engine(
+                dir.path(),
+                r#"echo "info depth 1 score cp 13 nodes 20 pv e2e4 e7e5"; echo "bestmove e2e4 ponder e7e5""#,
+            );
+            let mut engine = spawn(&path);
+            assert_eq!(engine.name, Some("Mock Engine".to_string()));
+            assert_eq!(engine.author, Some("Tester".to_string()));
+            assert_eq!(engine.options.len(), 1);
+            engine.new_game(Duration::from_secs(5)).unwrap();
+            engine.set_position(&Game::new()).unwrap();
+            let mut seen = 0;
+            let result = engine
+                .go_with(&Go::depth(1), Duration::from_secs(5), &mut |_| seen += 1)
+                .unwrap();
+            assert_eq!(seen, 1);
+            assert_eq!(result.best_move.unwrap().to_uci(), "e2e4");
+            assert_eq!(result.ponder.unwrap().to_uci(), "e7e5");
+            assert_eq!(result.infos[0].score, Some(Score::Centipawns(13)));
+            assert_eq!(engine.quit(), Some(0));
+        }
+
+        #[test]
+        fn timeout_sends_stop() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = mock_engine(dir.path(), ":");
+            let mut engine = spawn(&path);
+            engine.set_position(&Game::new()).unwrap();
+            let result = engine
+                .go(&Go::infinite(), Duration::from_millis(100))
+                .unwrap();
+            assert_eq!(result.best_move.unwrap().to_uci(), "e2e4");
+        }
+
+        #[test]
+        fn crash() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = mock_engine(dir.path(), "exit 3");
+            let mut engine = spawn(&path);
+            engine.set_position(&Game::new()).unwrap();
+            match engine.go(&Go::depth(1), Duration::from_secs(5)) {
+                Err(UciError::Crashed(Some(3))) => (),
+                other => panic!("expected crash, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn illegal_best_move() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = mock_engine(dir.path(), r#"echo "bestmove e2e5""#);
+            let mut engine = spawn(&path);
+            engine.set_position(&Game::new()).unwrap();
+            match engine.go(&Go::depth(1), Duration::from_secs(5)) {
+                Err(UciError::Protocol(_)) => (),
+                other => panic!("expected protocol error, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn missing_program() {
+            match UciEngine::spawn("/nonexistent/engine", &[], Duration::from_secs(1)) {
+                Err(UciError::Spawn(_)) => (),
+                _ => panic!("expected spawn error"),
+            }
+        }
+    }
+}
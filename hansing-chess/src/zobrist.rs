@@ -0,0 +1,145 @@
+use crate::board::Board;
+use crate::color::Color;
+use crate::title::Title;
+
+//Keys for each piece on each square, then castling rights, en passant files and side to move
+const PIECE_KEYS: usize = 2 * 6 * 64;
+const CASTLING_KEYS: usize = PIECE_KEYS;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 4;
+const SIDE_KEY: usize = EN_PASSANT_KEYS + 8;
+const KEY_COUNT: usize = SIDE_KEY + 1;
+
+const fn generate_keys() -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        //xorshift64*
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        keys[i] = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        i += 1;
+    }
+    keys
+}
+
+static KEYS: [u64; KEY_COUNT] = generate_keys();
+
+//Hash of the position, equal for positions that are the same for move generation purposes
+pub fn hash_board(board: &Board) -> u64 {
+    let mut hash = 0;
+    for (s, p) in board.pieces.iter() {
+        hash ^= KEYS[(p.color.index() * 6 + p.title.index()) * 64 + s.index()];
+    }
+    for (i, &(c, king_side)) in [
+        (Color::White, true),
+        (Color::White, false),
+        (Color::Black, true),
+        (Color::Black, false),
+    ]
+    .iter()
+    .enumerate()
+    {
+        if board.can_castle(c, king_side) {
+            hash ^= KEYS[CASTLING_KEYS + i];
+        }
+    }
+    if let Some(ep) = board.en_passant {
+        if can_capture_en_passant(board) {
+            hash ^= KEYS[EN_PASSANT_KEYS + ep.file() as usize];
+        }
+    }
+    if board.to_act == Color::Black {
+        hash ^= KEYS[SIDE_KEY];
+    }
+    hash
+}
+
+//Only count en passant if a pawn is actually in place to take it
+pub(crate) fn can_capture_en_passant(board: &Board) -> bool {
+    let ep = match board.en_passant {
+        Some(ep) => ep,
+        None => return false,
+    };
+    [1, -1].iter().any(|&file_offset| {
+        match ep
+            .offset(-board.to_act.forward(), file_offset)
+            .and_then(|s| board.pieces.get(&s))
+        {
+            Some(p) => p.color == board.to_act && p.title == Title::Pawn,
+            None => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test_zobrist {
+    use super::*;
+    use crate::movedata::MoveData;
+    use crate::square::Square;
+    fn standard(from: &str, to: &str) -> MoveData {
+        MoveData::new_standard(
+            Square::from_notation(from).unwrap(),
+            Square::from_notation(to).unwrap(),
+        )
+    }
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys = KEYS.to_vec();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), KEY_COUNT);
+    }
+    #[test]
+    fn transposition() {
+        let mut a = Board::new_standard();
+        let mut b = Board::new_standard();
+        for m in [
+            standard("g1", "f3"),
+            standard("g8", "f6"),
+            standard("b1", "c3"),
+        ]
+        .iter()
+        {
+            a.make_move(*m);
+        }
+        for m in [
+            standard("b1", "c3"),
+            standard("g8", "f6"),
+            standard("g1", "f3"),
+        ]
+        .iter()
+        {
+            b.make_move(*m);
+        }
+        assert_eq!(hash_board(&a), hash_board(&b));
+        assert_ne!(hash_board(&a), hash_board(&Board::new_standard()));
+    }
+    #[test]
+    fn side_to_move_and_castling() {
+        let mut a = Board::new_standard();
+        let b = Board::new_standard();
+        a.to_act = Color::Black;
+        assert_ne!(hash_board(&a), hash_board(&b));
+
+        //Rook out and back loses castling rights
+        let mut c = Board::new_standard();
+        for m in [
+            standard("g1", "f3"),
+            standard("g8", "f6"),
+            standard("h1", "g1"),
+            standard("f6", "g8"),
+            standard("g1", "h1"),
+            standard("g8", "f6"),
+        ]
+        .iter()
+        {
+            c.make_move(*m);
+        }
+        let mut d = Board::new_standard();
+        d.make_move(standard("g1", "f3"));
+        d.make_move(standard("g8", "f6"));
+        assert_ne!(hash_board(&c), hash_board(&d));
+    }
+}
@@ -0,0 +1,815 @@
+use hansing_chess::board::Board;
+use hansing_chess::bookbuilder::{BookBuilder, BookOptions};
+use hansing_chess::color::Color;
+use hansing_chess::eco::classify;
+use hansing_chess::epd::{parse_suite, run_suite};
+use hansing_chess::eval::evaluate;
+use hansing_chess::game::{Game, GameResult};
+use hansing_chess::movedata::MoveData;
+use hansing_chess::pgn::{result_string, Pgn};
+use hansing_chess::problem::{
+    cooks, pass, solve_helpmate, solve_mate, solve_selfmate, Defence, MateSolver, Solution,
+};
+use hansing_chess::search::{mate_in, SearchLimits, Searcher};
+use hansing_chess::square::Square;
+use hansing_chess::tablebase::Tablebase;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::time::Duration;
+
+const HASH_MB: usize = 16;
+const MAX_GO_DEPTH: i32 = 12;
+const MAX_SOLVE_MOVES: u32 = 5;
+
+const HELP: &str = "commands:
+  move <SAN|UCI>      play a move, e.g. move Nf3 or move g1f3
+  undo / redo         take back or replay a move
+  fen                 show the position as FEN
+  load-fen <fen>      start a new game from a position
+  pgn [save|load <file>]
+                      show, save or load the game as PGN
+  moves [square]      list legal moves, optionally from one square
+  flip                turn the board around
+  eval                static evaluation from White's point of view
+  opening             name the opening of the game
+  go <depth>          let the engine search and play a move
+  epd <file> [depth <n>|time <ms>]
+                      run a test suite, to depth 4 unless told otherwise
+  book <pgn> <out> [plies <n>] [min <n>] [elo <n>] [player <name>]...
+                      build an opening book, Polyglot when out ends in .bin
+  solve [help|self] <n> [set] [intended <line>, ...]
+                      find every solution of a mate, helpmate or selfmate in n,
+                      helpmates also in half moves like 2.5, set adds set play,
+                      intended lists the cooks, solutions not starting with a line
+  tablebase <dir> [generate <material>...]
+                      probe the endgame tables in dir, or generate tables like KRK
+  new                 start a new game
+  result              show the result of the game
+  board               show the board
+  quit";
+
+//hansing-cli [script]: without a script commands are read interactively from stdin
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1) {
+        Some(path) => {
+            let script = match fs::read_to_string(path) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("could not read {}: {}", path, e);
+                    process::exit(2);
+                }
+            };
+            let mut cli = Cli::new(false);
+            if let Err((line, e)) = run_batch(&mut cli, &script, &mut io::stdout()) {
+                eprintln!("{}:{}: {}", path, line, e);
+                process::exit(1);
+            }
+        }
+        None => interactive(),
+    }
+}
+
+fn interactive() {
+    let mut cli = Cli::new(true);
+    println!("{}", cli.render());
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+        match cli.execute(&line) {
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => break,
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+//Runs every line of a script, echoing the commands. Stops at the first error with its line number.
+fn run_batch(cli: &mut Cli, script: &str, out: &mut dyn Write) -> Result<(), (usize, String)> {
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        writeln!(out, "> {}", line).ok();
+        match cli.execute(line) {
+            Ok(Some(output)) => {
+                if !output.is_empty() {
+                    writeln!(out, "{}", output).ok();
+                }
+            }
+            Ok(None) => break,
+            Err(e) => return Err((i + 1, e)),
+        }
+    }
+    Ok(())
+}
+
+struct Cli {
+    pgn: Pgn,
+    searcher: Searcher,
+    flipped: bool,
+    show_board: bool, //Print the board after every change, off in batch mode
+}
+
+impl Cli {
+    fn new(show_board: bool) -> Self {
+        Self {
+            pgn: Pgn::new(Game::new()),
+            searcher: Searcher::new(HASH_MB),
+            flipped: false,
+            show_board,
+        }
+    }
+
+    fn game(&self) -> &Game {
+        &self.pgn.game
+    }
+
+    //Output of the command, None on quit
+    fn execute(&mut self, line: &str) -> Result<Option<String>, String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((c, a)) => (*c, a),
+            None => return Ok(Some(String::new())),
+        };
+        let output = match command {
+            "move" | "m" => {
+                let note = args.first().ok_or("move needs a move, e.g. move e4")?;
+                self.play(note)?
+            }
+            "undo" => {
+                if self.game().moves.is_empty() {
+                    return Err("nothing to undo".to_string());
+                }
+                self.pgn.game.undo_move();
+                self.changed(String::new())
+            }
+            "redo" => {
+                if !self.pgn.game.redo_move() {
+                    return Err("nothing to redo".to_string());
+                }
+                self.changed(String::new())
+            }
+            "fen" => self.game().board.to_fen(),
+            "load-fen" => {
+                let board = Board::from_fen(&args.join(" ")).map_err(|e| e.to_string())?;
+                self.pgn = Pgn::new(Game::from_board(board));
+                self.changed(String::new())
+            }
+            "pgn" => match (args.first(), args.get(1)) {
+                (None, _) => self.pgn.to_string().trim_end().to_string(),
+                (Some(&"save"), Some(path)) => {
+                    fs::write(path, self.pgn.to_string()).map_err(|e| e.to_string())?;
+                    format!("saved {}", path)
+                }
+                (Some(&"load"), Some(path)) => {
+                    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+                    self.pgn = Pgn::parse(&text).map_err(|e| e.to_string())?;
+                    let loaded = format!("loaded {} moves from {}", self.game().moves.len(), path);
+                    self.changed(loaded)
+                }
+                _ => return Err("usage: pgn [save|load <file>]".to_string()),
+            },
+            "moves" => self.list_moves(args.first())?,
+            "flip" => {
+                self.flipped = !self.flipped;
+                self.changed(String::new())
+            }
+            "eval" => {
+                let board = &self.game().board;
+                let score = match board.to_act {
+                    Color::White => evaluate(board),
+                    Color::Black => -evaluate(board),
+                };
+                format!("{:+.2}", score as f64 / 100.0)
+            }
+            "opening" => match classify(self.game()) {
+                Some(opening) => opening.to_string(),
+                None => "no known opening".to_string(),
+            },
+            "go" => {
+                let depth = match args.first() {
+                    Some(d) => d
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|d| (1..=MAX_GO_DEPTH).contains(d))
+                        .ok_or(format!("depth must be between 1 and {}", MAX_GO_DEPTH))?,
+                    None => 4,
+                };
+                self.go(depth)?
+            }
+            "epd" => {
+                let depth_or_time = |value: &str| {
+                    value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&v| v > 0)
+                        .ok_or(format!("'{}' is not a positive number", value))
+                };
+                let (limits, time) = match args {
+                    [_] => (SearchLimits::depth(4), None),
+                    [_, "depth", d] => (SearchLimits::depth(depth_or_time(d)? as i32), None),
+                    [_, "time", ms] => (
+                        SearchLimits::default(),
+                        Some(Duration::from_millis(depth_or_time(ms)?)),
+                    ),
+                    _ => return Err("usage: epd <file> [depth <n>|time <ms>]".to_string()),
+                };
+                let text = fs::read_to_string(args[0]).map_err(|e| e.to_string())?;
+                let suite =
+                    parse_suite(&text).map_err(|(line, e)| format!("line {}: {}", line, e))?;
+                run_suite(&suite, &mut self.searcher, &limits, time, &mut |_| ()).to_string()
+            }
+            "book" => build_book(args)?,
+            "solve" => solve(&self.game().board, args)?,
+            "tablebase" => tablebase(&self.game().board, args)?,
+            "new" => {
+                self.pgn = Pgn::new(Game::new());
+                self.searcher = Searcher::new(HASH_MB);
+                self.changed(String::new())
+            }
+            "result" => self.result(),
+            "board" => self.render(),
+            "help" => HELP.to_string(),
+            "quit" | "exit" => return Ok(None),
+            _ => return Err(format!("unknown command '{}', try help", command)),
+        };
+        Ok(Some(output))
+    }
+
+    //Adds the board to the output when it is shown after changes
+    fn changed(&self, output: String) -> String {
+        if !self.show_board {
+            return output;
+        }
+        if output.is_empty() {
+            self.render()
+        } else {
+            format!("{}\n{}", output, self.render())
+        }
+    }
+
+    fn play(&mut self, note: &str) -> Result<String, String> {
+        if self.game().result != GameResult::Ongoing {
+            return Err(format!("the game is over, {}", self.result()));
+        }
+        let board = &self.game().board;
+        let m = MoveData::from_san(board, note)
+            .or_else(|| MoveData::from_uci(board, note))
+            .ok_or(format!("illegal move '{}'", note))?;
+        let san = m.to_san(board);
+        self.pgn.game.make_move(m);
+        let mut output = san;
+        if self.game().result != GameResult::Ongoing {
+            output = format!("{}\n{}", output, self.result());
+        }
+        Ok(self.changed(output))
+    }
+
+    fn list_moves(&self, from: Option<&&str>) -> Result<String, String> {
+        let from = match from {
+            Some(s) => Some(Square::from_notation(s).ok_or(format!("no square '{}'", s))?),
+            None => None,
+        };
+        let board = &self.game().board;
+        //Sorted so scripted output does not depend on generation order
+        let mut moves: Vec<String> = self
+            .game()
+            .action_space
+            .iter()
+            .filter(|m| from.is_none() || from == Some(m.from))
+            .map(|m| m.to_san(board))
+            .collect();
+        moves.sort();
+        if moves.is_empty() {
+            Ok("no legal moves".to_string())
+        } else {
+            Ok(moves.join(" "))
+        }
+    }
+
+    fn go(&mut self, depth: i32) -> Result<String, String> {
+        if self.game().result != GameResult::Ongoing {
+            return Err(format!("the game is over, {}", self.result()));
+        }
+        let board = self.game().board.clone();
+        let result = self.searcher.search(&board, depth);
+        let best = result.best_move.ok_or("no move found")?;
+        let score = match mate_in(result.score) {
+            Some(n) => format!("mate {}", n),
+            None => format!("{:+.2}", result.score as f64 / 100.0),
+        };
+        let mut pv = Vec::new();
+        let mut line_board = board;
+        for m in result.pv.iter() {
+            pv.push(m.to_san(&line_board));
+            line_board.make_move(*m);
+        }
+        let played = self.play(&best.to_uci())?;
+        Ok(format!(
+            "depth {} score {} pv {}\n{}",
+            result.depth,
+            score,
+            pv.join(" "),
+            played
+        ))
+    }
+
+    fn result(&self) -> String {
+        let description = match self.game().result {
+            GameResult::Won(c) => format!("{} won", c),
+            GameResult::Tied => "draw".to_string(),
+            GameResult::Ongoing => "the game is not over".to_string(),
+        };
+        format!("{} ({})", result_string(self.game().result), description)
+    }
+
+    fn render(&self) -> String {
+        let board = &self.game().board;
+        let (ranks, files): (Vec<i32>, Vec<i32>) = if self.flipped {
+            ((0..8).collect(), (0..8).rev().collect())
+        } else {
+            ((0..8).rev().collect(), (0..8).collect())
+        };
+        let letters: String = files
+            .iter()
+            .map(|f| format!("{} ", (b'a' + *f as u8) as char))
+            .collect();
+        let mut out = format!("  {}\n", letters.trim_end());
+        for &rank in ranks.iter() {
+            out.push_str(&format!("{} ", rank + 1));
+            for &file in files.iter() {
+                let s = Square::new(rank, file).unwrap();
+                let c = match board.pieces.get(&s) {
+                    Some(p) => p.to_char(),
+                    None if (rank + file) % 2 == 0 => ',',
+                    None => '.',
+                };
+                out.push(c);
+                out.push(' ');
+            }
+            out.push_str(&format!("{}\n", rank + 1));
+        }
+        out.push_str(&format!("  {}\n", letters.trim_end()));
+        out.push_str(&format!("{} to play", board.to_act));
+        if board.in_check {
+            out.push_str(", in check");
+        }
+        if let Some(opening) = classify(self.game()) {
+            out.push_str(&format!("\n{}", opening));
+        }
+        out
+    }
+}
+
+//book <pgn> <out> [plies <n>] [min <n>] [elo <n>] [player <name>]...
+fn build_book(args: &[&str]) -> Result<String, String> {
+    let usage = || "usage: book <pgn> <out> [plies <n>] [min <n>] [elo <n>] [player <name>]...";
+    let (pgn, out) = match args {
+        [pgn, out, ..] => (pgn, out),
+        _ => return Err(usage().to_string()),
+    };
+    let mut options = BookOptions::default();
+    for pair in args[2..].chunks(2) {
+        let number = || pair[1].parse::<u32>().map_err(|_| usage().to_string());
+        match pair {
+            ["plies", _] => options.max_ply = number()? as usize,
+            ["min", _] => options.min_games = number()?,
+            ["elo", _] => options.min_rating = Some(number()?),
+            ["player", name] => options.players.push(name.to_string()),
+            _ => return Err(usage().to_string()),
+        }
+    }
+    let file = fs::File::open(pgn).map_err(|e| e.to_string())?;
+    let mut builder = BookBuilder::new(options);
+    let skipped = builder.add_pgn(io::BufReader::new(file)).len();
+    let written = if out.ends_with(".bin") {
+        builder.write_polyglot(out)
+    } else {
+        builder.write_text(out)
+    };
+    written.map_err(|e| e.to_string())?;
+    Ok(format!(
+        "{} positions from {} games written to {}, {} games skipped",
+        builder.positions(),
+        builder.games(),
+        out,
+        skipped
+    ))
+}
+
+fn write_tree(board: &Board, solution: &Solution, indent: usize, out: &mut Vec<String>) {
+    out.push(format!(
+        "{:indent$}{}",
+        "",
+        solution.key.to_san(board),
+        indent = indent
+    ));
+    let mut after = board.clone();
+    after.make_move(solution.key);
+    for defence in solution.defences.iter() {
+        write_defence(&after, defence, indent + 2, out);
+    }
+}
+
+fn write_defence(board: &Board, defence: &Defence, indent: usize, out: &mut Vec<String>) {
+    out.push(format!(
+        "{:indent$}{}",
+        "",
+        defence.reply.to_san(board),
+        indent = indent
+    ));
+    let mut position = board.clone();
+    position.make_move(defence.reply);
+    for continuation in defence.continuations.iter() {
+        write_tree(&position, continuation, indent + 2, out);
+    }
+}
+
+fn write_line(board: &Board, line: &[MoveData], out: &mut Vec<String>) {
+    let mut position = board.clone();
+    let mut moves = Vec::new();
+    for &m in line {
+        moves.push(m.to_san(&position));
+        position.make_move(m);
+    }
+    out.push(moves.join(" "));
+}
+
+//solve [help|self] <n> [set] [intended <line>, ...]: every solution as an indented tree, keys
+//and continuations with the defences below them, or one line per helpmate. Intended lines are
+//SAN moves separated by commas, the key alone for mates and selfmates.
+fn solve(board: &Board, args: &[&str]) -> Result<String, String> {
+    let usage = || {
+        format!(
+            "usage: solve [help|self] <n> [set] [intended <line>, ...], n up to {}",
+            MAX_SOLVE_MOVES
+        )
+    };
+    let (args, intended) = match args.iter().position(|&a| a == "intended") {
+        Some(i) => (&args[..i], Some(args[i + 1..].join(" "))),
+        None => (args, None),
+    };
+    let intended: Option<Vec<&str>> = intended.as_ref().map(|lines| {
+        lines
+            .split(',')
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect()
+    });
+    if intended.as_ref().is_some_and(|lines| lines.is_empty()) {
+        return Err(usage());
+    }
+    let (kind, args) = match args.first() {
+        Some(&kind) if kind == "help" || kind == "self" => (kind, &args[1..]),
+        _ => ("mate", args),
+    };
+    let set = match args {
+        [_] => false,
+        [_, "set"] => true,
+        _ => return Err(usage()),
+    };
+    //Half moves, so a helpmate in 2.5 is 5
+    let plies = match args[0].split_once('.') {
+        Some((n, "5")) if kind == "help" => n.parse::<u32>().map(|n| 2 * n + 1),
+        Some(_) => return Err(usage()),
+        None => args[0].parse::<u32>().map(|n| 2 * n),
+    }
+    .ok()
+    .filter(|&p| (1..=2 * MAX_SOLVE_MOVES).contains(&p))
+    .ok_or_else(usage)?;
+    let moves = plies / 2;
+
+    let mut out = Vec::new();
+    //Helpmates as whole lines, mates and selfmates by their keys
+    let lines = if kind == "help" {
+        let lines = solve_helpmate(board, plies);
+        for line in lines.iter() {
+            write_line(board, line, &mut out);
+        }
+        lines
+    } else {
+        let solutions = if kind == "self" {
+            solve_selfmate(board, moves)
+        } else {
+            solve_mate(board, moves)
+        };
+        for solution in solutions.iter() {
+            write_tree(board, solution, 0, &mut out);
+        }
+        solutions.iter().map(|s| vec![s.key]).collect()
+    };
+    let count = lines.len();
+    out.insert(
+        0,
+        match count {
+            0 => format!("no solution in {}", args[0]),
+            1 => "1 solution".to_string(),
+            n if kind == "help" => format!("{} solutions", n),
+            n => format!("{} solutions, the problem is cooked", n),
+        },
+    );
+    if let Some(intended) = intended {
+        let cooked = cooks(board, &lines, &intended);
+        if cooked.is_empty() {
+            out.push("no cooks".to_string());
+        }
+        for line in cooked.iter() {
+            let mut san = Vec::new();
+            write_line(board, line, &mut san);
+            out.push(format!("cook {}", san.join(" ")));
+        }
+    }
+
+    if set {
+        out.push("set play".to_string());
+        let passed = pass(board).ok_or("no set play with the side to move in check")?;
+        if kind == "help" {
+            for line in solve_helpmate(&passed, plies - 1).iter() {
+                write_line(&passed, line, &mut out);
+            }
+        } else {
+            let mut solver = if kind == "self" {
+                MateSolver::selfmate()
+            } else {
+                MateSolver::new()
+            };
+            for defence in solver.set_play(board, moves) {
+                if !defence.continuations.is_empty() {
+                    write_defence(&passed, &defence, 0, &mut out);
+                }
+            }
+        }
+    }
+    Ok(out.join("\n"))
+}
+
+//tablebase <dir> [generate <material>...]
+fn tablebase(board: &Board, args: &[&str]) -> Result<String, String> {
+    let dir = match args.first() {
+        Some(dir) => dir,
+        None => return Err("usage: tablebase <dir> [generate <material>...]".to_string()),
+    };
+    if args.get(1) == Some(&"generate") {
+        //Only a directory that is not there yet starts empty, a broken one is not written over
+        let mut tablebase = if std::path::Path::new(dir).exists() {
+            Tablebase::load(dir).map_err(|e| e.to_string())?
+        } else {
+            Tablebase::default()
+        };
+        for material in args[2..].iter() {
+            tablebase.generate(material).map_err(|e| e.to_string())?;
+        }
+        tablebase.save(dir).map_err(|e| e.to_string())?;
+        return Ok(format!(
+            "tables in {}: {}",
+            dir,
+            tablebase.tables().join(" ")
+        ));
+    }
+    let tablebase = Tablebase::load(dir).map_err(|e| e.to_string())?;
+    match tablebase.best_move(board) {
+        Some((m, outcome)) => Ok(format!("{} {}", m.to_san(board), outcome)),
+        None => match tablebase.probe(board) {
+            Some(outcome) => Ok(outcome.to_string()),
+            None => Err("position not in the tablebase".to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+    fn run(cli: &mut Cli, line: &str) -> String {
+        cli.execute(line).unwrap().unwrap()
+    }
+    #[test]
+    fn moves_in_both_notations() {
+        let mut cli = Cli::new(false);
+        assert_eq!(run(&mut cli, "move e4"), "e4");
+        assert_eq!(run(&mut cli, "move e7e5"), "e5");
+        assert_eq!(run(&mut cli, "move Nf3"), "Nf3");
+        assert_eq!(
+            run(&mut cli, "fen"),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        assert_eq!(
+            cli.execute("move Ke3"),
+            Err("illegal move 'Ke3'".to_string())
+        );
+        assert!(cli.execute("move").is_err());
+        assert!(cli
+            .execute("dance")
+            .unwrap_err()
+            .contains("unknown command"));
+    }
+    #[test]
+    fn undo_and_redo() {
+        let mut cli = Cli::new(false);
+        assert!(cli.execute("undo").is_err());
+        run(&mut cli, "move d4");
+        run(&mut cli, "undo");
+        assert_eq!(cli.game().moves.len(), 0);
+        run(&mut cli, "redo");
+        assert_eq!(cli.game().moves.len(), 1);
+        assert!(cli.execute("redo").is_err());
+    }
+    #[test]
+    fn move_list() {
+        let mut cli = Cli::new(false);
+        assert_eq!(run(&mut cli, "moves g1"), "Nf3 Nh3");
+        assert_eq!(run(&mut cli, "moves").split(' ').count(), 20);
+        assert!(cli.execute("moves z9").is_err());
+    }
+    #[test]
+    fn fen_eval_and_result() {
+        let mut cli = Cli::new(false);
+        run(&mut cli, "load-fen 7k/8/6K1/8/8/8/8/R7 b - - 0 1");
+        assert_eq!(run(&mut cli, "eval"), "+5.00");
+        assert!(cli.execute("load-fen nonsense").is_err());
+        assert_eq!(run(&mut cli, "opening"), "no known opening");
+        run(&mut cli, "move Kg8");
+        assert_eq!(run(&mut cli, "result"), "* (the game is not over)");
+        run(&mut cli, "load-fen 7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+        let output = run(&mut cli, "go 2");
+        assert!(output.starts_with("depth 2 score mate 1 pv Ra8#"));
+        assert!(output.ends_with("1-0 (White won)"));
+        assert!(cli
+            .execute("move Kh7")
+            .unwrap_err()
+            .starts_with("the game is over"));
+    }
+    #[test]
+    fn pgn_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("game.pgn");
+        let path = path.to_str().unwrap();
+        let mut cli = Cli::new(false);
+        run(&mut cli, "move e4");
+        run(&mut cli, "move c5");
+        assert!(run(&mut cli, "pgn").ends_with("1. e4 c5 *"));
+        run(&mut cli, &format!("pgn save {}", path));
+        run(&mut cli, "new");
+        assert_eq!(
+            run(&mut cli, &format!("pgn load {}", path)),
+            format!("loaded 2 moves from {}", path)
+        );
+        assert_eq!(cli.game().moves.len(), 2);
+        fs::remove_file(path).unwrap();
+        assert!(cli.execute(&format!("pgn load {}", path)).is_err());
+    }
+    #[test]
+    fn epd_suite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("suite.epd");
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";\n",
+        )
+        .unwrap();
+        let mut cli = Cli::new(false);
+        let report = run(&mut cli, &format!("epd {} depth 2", path));
+        assert!(report.starts_with("back rank    solved a1a8"));
+        assert!(report.ends_with("solved 1 of 1, failed 0"));
+        assert!(cli.execute(&format!("epd {} depth none", path)).is_err());
+        fs::write(path, "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Rh8;\n").unwrap();
+        assert_eq!(
+            cli.execute(&format!("epd {}", path)).unwrap_err(),
+            "line 1: illegal move 'Rh8' in bm operation"
+        );
+    }
+    #[test]
+    fn opening_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let pgn = dir.path().join("games.pgn");
+        let bin = dir.path().join("book.bin");
+        let (pgn, bin) = (pgn.to_str().unwrap(), bin.to_str().unwrap());
+        fs::write(
+            pgn,
+            "[White \"A\"]\n[Black \"B\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n",
+        )
+        .unwrap();
+        let mut cli = Cli::new(false);
+        assert_eq!(
+            run(&mut cli, &format!("book {} {} plies 2 player A", pgn, bin)),
+            format!(
+                "1 positions from 1 games written to {}, 0 games skipped",
+                bin
+            )
+        );
+        let book = hansing_chess::polyglot::Book::open(bin).unwrap();
+        assert_eq!(
+            book.best_move(&Board::new_standard()).unwrap().to_uci(),
+            "e2e4"
+        );
+        assert!(cli.execute(&format!("book {} {} plies", pgn, bin)).is_err());
+    }
+    #[test]
+    fn mate_problems() {
+        let mut cli = Cli::new(false);
+        run(&mut cli, "load-fen k7/8/2K5/8/8/8/8/1R6 w - - 0 1");
+        assert_eq!(run(&mut cli, "solve 1"), "no solution in 1");
+        assert_eq!(run(&mut cli, "solve 2"), "1 solution\nKc7\n  Ka7\n    Ra1#");
+        run(&mut cli, "load-fen 7k/8/5K2/8/8/8/8/R7 w - - 0 1");
+        assert!(run(&mut cli, "solve 2").starts_with("2 solutions, the problem is cooked\nKf7\n"));
+        assert!(cli.execute("solve 0").is_err());
+        assert!(cli.execute("solve").is_err());
+        assert!(cli.execute("solve 2.5").is_err());
+        assert!(cli.execute("solve self 2 sets").is_err());
+
+        run(&mut cli, "load-fen kr6/pb6/4N3/3n4/8/8/7P/6BK w - - 0 1");
+        let selfmate = run(&mut cli, "solve self 1 set");
+        assert!(selfmate.starts_with("1 solution\nNc7+\n  Nxc7#\nset play\n"));
+        assert!(selfmate.contains("\na6\n  Nc7+\n    Nxc7#"));
+        run(&mut cli, "load-fen 7k/8/6K1/8/8/8/8/R7 b - - 0 1");
+        assert_eq!(
+            run(&mut cli, "solve help 1 set"),
+            "1 solution\nKg8 Ra8#\nset play\nRa8#"
+        );
+        assert!(run(&mut cli, "solve help 2").starts_with("13 solutions\n"));
+        run(&mut cli, "load-fen 7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+        assert!(run(&mut cli, "solve help 1.5").starts_with("11 solutions\n"));
+        assert_eq!(run(&mut cli, "solve help 0.5"), "1 solution\nRa8#");
+    }
+    #[test]
+    fn problem_cooks() {
+        let mut cli = Cli::new(false);
+        run(&mut cli, "load-fen 7k/8/5K2/8/8/8/8/R7 w - - 0 1");
+        assert!(run(&mut cli, "solve 2 intended Kf7").ends_with("\ncook Kg6"));
+        assert!(run(&mut cli, "solve 2 intended Kf7, Kg6").ends_with("\nno cooks"));
+        assert!(cli.execute("solve 2 intended").is_err());
+        run(&mut cli, "load-fen 7k/8/6K1/8/8/8/8/R7 b - - 0 1");
+        let helpmate = run(
+            &mut cli,
+            "solve help 2 set intended 1... Kg8 2. Kh6 Kh8 3. Ra8#",
+        );
+        assert_eq!(helpmate.matches("\ncook ").count(), 12);
+        assert!(helpmate.contains("\ncook Kg8 Kf6 Kf8 Ra8#\n"));
+        assert!(!helpmate.contains("cook Kg8 Kh6 Kh8 Ra8#"));
+    }
+    #[test]
+    fn endgame_tablebase() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("tables");
+        let dir = dir.to_str().unwrap();
+        let mut cli = Cli::new(false);
+        assert_eq!(
+            run(&mut cli, &format!("tablebase {} generate KQK", dir)),
+            format!("tables in {}: KK KQK", dir)
+        );
+        assert!(cli.execute(&format!("tablebase {}", dir)).is_err());
+        run(&mut cli, "load-fen k7/8/1K6/8/8/8/7Q/8 w - - 0 1");
+        assert_eq!(
+            run(&mut cli, &format!("tablebase {}", dir)),
+            "Qh8# mate in 1"
+        );
+        run(&mut cli, "move Qh8");
+        assert_eq!(run(&mut cli, &format!("tablebase {}", dir)), "checkmated");
+        assert!(cli
+            .execute(&format!("tablebase {} generate KXK", dir))
+            .is_err());
+        fs::write(temp.path().join("tables/KQK.htb"), "not a table").unwrap();
+        assert!(cli
+            .execute(&format!("tablebase {} generate KRK", dir))
+            .unwrap_err()
+            .starts_with("corrupt table file"));
+    }
+    #[test]
+    fn flipped_board() {
+        let mut cli = Cli::new(true);
+        let shown = run(&mut cli, "flip");
+        let lines: Vec<&str> = shown.lines().collect();
+        assert_eq!(lines[0], "  h g f e d c b a");
+        assert_eq!(lines[1], "1 R N B K Q B N R 1");
+        assert_eq!(lines[10], "White to play");
+        assert_eq!(lines.len(), 11);
+        let shown = run(&mut cli, "move e4");
+        assert_eq!(shown.lines().last().unwrap(), "B00 King's Pawn Game");
+        assert_eq!(run(&mut cli, "opening"), "B00 King's Pawn Game");
+    }
+    #[test]
+    fn batch() {
+        let script = "# scripted game\nmove e4\n\nmove e5\nfen\nmove Qh5\nmove Ke7\nmove Qxe5#\nquit\nmove a3\n";
+        let mut cli = Cli::new(false);
+        let mut out = Vec::new();
+        run_batch(&mut cli, script, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("> move e4\ne4\n> move e5\ne5\n> fen\n"));
+        assert!(out.ends_with("> move Qxe5#\nQxe5#\n1-0 (White won)\n> quit\n"));
+
+        let mut cli = Cli::new(false);
+        let err = run_batch(&mut cli, "move e4\nmove e4\n", &mut Vec::new()).unwrap_err();
+        assert_eq!(err, (2, "illegal move 'e4'".to_string()));
+    }
+}
@@ -0,0 +1,544 @@
+use hansing_chess::board::Board;
+use hansing_chess::color::Color;
+use hansing_chess::fen::STANDARD_FEN;
+use hansing_chess::movedata::MoveData;
+use hansing_chess::polyglot::Book;
+use hansing_chess::search::{mate_in, SearchLimits, SearchResult};
+use hansing_chess::smp::search_parallel;
+use hansing_chess::tt::TranspositionTable;
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_HASH_MB: usize = 16;
+//Kept in reserve so the engine never flags because of overhead
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+fn main() {
+    let stdin = io::stdin();
+    let mut engine = Engine::new(Arc::new(Mutex::new(io::stdout())));
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.stop();
+}
+
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+fn send(out: &Output, line: &str) {
+    let mut out = out.lock().unwrap();
+    writeln!(out, "{}", line).ok();
+    out.flush().ok();
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct GoParams {
+    depth: Option<i32>,
+    nodes: Option<u64>,
+    movetime: Option<u64>,
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: Option<u64>,
+    binc: Option<u64>,
+    movestogo: Option<u64>,
+    infinite: bool,
+    ponder: bool,
+}
+
+fn parse_go(args: &[&str]) -> GoParams {
+    let mut go = GoParams::default();
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        let mut takes_value = true;
+        match args[i] {
+            "depth" => go.depth = value.map(|v| v as i32),
+            "nodes" => go.nodes = value,
+            "movetime" => go.movetime = value,
+            "wtime" => go.wtime = value,
+            "btime" => go.btime = value,
+            "winc" => go.winc = value,
+            "binc" => go.binc = value,
+            "movestogo" => go.movestogo = value,
+            "infinite" => {
+                go.infinite = true;
+                takes_value = false;
+            }
+            "ponder" => {
+                go.ponder = true;
+                takes_value = false;
+            }
+            _ => takes_value = false,
+        }
+        i += if takes_value { 2 } else { 1 };
+    }
+    go
+}
+
+//How long to think, None when the search is only bounded by depth, nodes or stop
+fn time_budget(go: &GoParams, to_act: Color) -> Option<Duration> {
+    if let Some(movetime) = go.movetime {
+        return Some(Duration::from_millis(movetime));
+    }
+    let (time, inc) = match to_act {
+        Color::White => (go.wtime?, go.winc.unwrap_or(0)),
+        Color::Black => (go.btime?, go.binc.unwrap_or(0)),
+    };
+    let time = Duration::from_millis(time);
+    let moves_to_go = go.movestogo.unwrap_or(30).max(1) as u32;
+    let budget = time / moves_to_go + Duration::from_millis(inc) * 3 / 4;
+    let available = time
+        .checked_sub(MOVE_OVERHEAD)
+        .unwrap_or_else(|| Duration::from_millis(1));
+    Some(budget.min(available))
+}
+
+//position startpos|fen <fen> [moves <move>...]
+fn parse_position(args: &[&str]) -> Result<Board, String> {
+    let moves_at = args
+        .iter()
+        .position(|a| *a == "moves")
+        .unwrap_or(args.len());
+    let mut board = match args.first() {
+        Some(&"startpos") => Board::from_fen(STANDARD_FEN).unwrap(),
+        Some(&"fen") => Board::from_fen(&args[1..moves_at].join(" ")).map_err(|e| e.to_string())?,
+        _ => return Err("expected startpos or fen".to_string()),
+    };
+    for note in args.iter().skip(moves_at + 1) {
+        match MoveData::from_uci(&board, note) {
+            Some(m) => board.make_move(m),
+            None => return Err(format!("illegal move {}", note)),
+        }
+    }
+    Ok(board)
+}
+
+fn info_lines(result: &SearchResult, elapsed: Duration) -> Vec<String> {
+    let ms = elapsed.as_millis() as u64;
+    let nps = result.nodes * 1000 / ms.max(1);
+    result
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let score = match mate_in(line.score) {
+                Some(n) => format!("mate {}", n),
+                None => format!("cp {}", line.score),
+            };
+            let pv: Vec<String> = line.pv.iter().map(|m| m.to_uci()).collect();
+            format!(
+                "info depth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+                result.depth,
+                i + 1,
+                score,
+                result.nodes,
+                nps,
+                ms,
+                pv.join(" ")
+            )
+        })
+        .collect()
+}
+
+fn best_move_line(result: &SearchResult) -> String {
+    match (result.pv.first(), result.pv.get(1)) {
+        (Some(best), Some(ponder)) => {
+            format!("bestmove {} ponder {}", best.to_uci(), ponder.to_uci())
+        }
+        (Some(best), None) => format!("bestmove {}", best.to_uci()),
+        //No legal moves, the protocol still wants an answer
+        _ => "bestmove 0000".to_string(),
+    }
+}
+
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    hold: Arc<AtomicBool>, //Set while pondering or infinite, bestmove waits until it is cleared
+    budget: Option<Duration>,
+    handle: thread::JoinHandle<()>,
+}
+
+struct Engine {
+    out: Output,
+    board: Board,
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    multi_pv: usize,
+    own_book: bool,
+    book: Option<Book>,
+    search: Option<RunningSearch>,
+}
+
+impl Engine {
+    fn new(out: Output) -> Self {
+        Self {
+            out,
+            board: Board::from_fen(STANDARD_FEN).unwrap(),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            threads: 1,
+            multi_pv: 1,
+            own_book: false,
+            book: None,
+            search: None,
+        }
+    }
+
+    //Returns false on quit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((c, a)) => (*c, a),
+            None => return true,
+        };
+        match command {
+            "uci" => {
+                send(&self.out, "id name hansing-chess");
+                send(&self.out, concat!("id author ", env!("CARGO_PKG_AUTHORS")));
+                send(
+                    &self.out,
+                    &format!(
+                        "option name Hash type spin default {} min 1 max 65536",
+                        DEFAULT_HASH_MB
+                    ),
+                );
+                send(
+                    &self.out,
+                    "option name Threads type spin default 1 min 1 max 256",
+                );
+                send(
+                    &self.out,
+                    "option name MultiPV type spin default 1 min 1 max 256",
+                );
+                send(&self.out, "option name Ponder type check default false");
+                send(&self.out, "option name OwnBook type check default false");
+                send(
+                    &self.out,
+                    "option name BookFile type string default <empty>",
+                );
+                send(&self.out, "uciok");
+            }
+            "isready" => send(&self.out, "readyok"),
+            "ucinewgame" => {
+                self.stop();
+                self.tt.clear();
+                self.board = Board::from_fen(STANDARD_FEN).unwrap();
+            }
+            "position" => match parse_position(args) {
+                Ok(board) => self.board = board,
+                Err(e) => send(&self.out, &format!("info string {}", e)),
+            },
+            "go" => self.go(parse_go(args)),
+            "stop" => self.stop(),
+            "ponderhit" => self.ponderhit(),
+            "setoption" => self.set_option(args),
+            "quit" => return false,
+            _ => send(
+                &self.out,
+                &format!("info string unknown command {}", command),
+            ),
+        }
+        true
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        //setoption name <name> value <value>
+        let value_at = args
+            .iter()
+            .position(|a| *a == "value")
+            .unwrap_or(args.len());
+        let name = args
+            .get(1..value_at)
+            .unwrap_or(&[])
+            .join(" ")
+            .to_lowercase();
+        let value = args.get(value_at + 1..).unwrap_or(&[]).join(" ");
+        let number = value.parse::<usize>().ok().filter(|n| *n > 0);
+        match (name.as_str(), number) {
+            ("ownbook", _) => self.own_book = value == "true",
+            ("bookfile", _) if value.is_empty() || value == "<empty>" => self.book = None,
+            ("bookfile", _) => match Book::open(&value) {
+                Ok(book) => self.book = Some(book),
+                Err(e) => send(
+                    &self.out,
+                    &format!("info string cannot read book {}: {}", value, e),
+                ),
+            },
+            ("hash", Some(mb)) => {
+                self.stop();
+                self.tt = Arc::new(TranspositionTable::new(mb));
+            }
+            ("threads", Some(n)) => self.threads = n,
+            ("multipv", Some(n)) => self.multi_pv = n,
+            ("ponder", _) => (),
+            _ => send(&self.out, &format!("info string ignoring option {}", name)),
+        }
+    }
+
+    fn go(&mut self, go: GoParams) {
+        self.stop();
+        if let Some(m) = self.book_move(&go) {
+            send(&self.out, &format!("bestmove {}", m.to_uci()));
+            return;
+        }
+        let limits = SearchLimits {
+            depth: go.depth.unwrap_or(SearchLimits::default().depth),
+            nodes: go.nodes,
+            multi_pv: self.multi_pv,
+        };
+        let budget = time_budget(&go, self.board.to_act);
+        let stop = Arc::new(AtomicBool::new(false));
+        let hold = Arc::new(AtomicBool::new(go.infinite || go.ponder));
+        if !go.ponder {
+            if let Some(b) = budget {
+                start_timer(stop.clone(), b);
+            }
+        }
+
+        let board = self.board.clone();
+        let tt = self.tt.clone();
+        let threads = self.threads;
+        let out = self.out.clone();
+        let (search_stop, search_hold) = (stop.clone(), hold.clone());
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let result = search_parallel(&board, &limits, threads, &tt, &search_stop, &mut |r| {
+                for line in info_lines(r, start.elapsed()) {
+                    send(&out, &line);
+                }
+            });
+            while search_hold.load(Ordering::Relaxed) && !search_stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            send(&out, &best_move_line(&result));
+        });
+        self.search = Some(RunningSearch {
+            stop,
+            hold,
+            budget: if go.infinite { None } else { budget },
+            handle,
+        });
+    }
+
+    //A weighted random book move, the book is left alone when analysing or pondering
+    fn book_move(&self, go: &GoParams) -> Option<MoveData> {
+        if !self.own_book || go.infinite || go.ponder {
+            return None;
+        }
+        self.book.as_ref()?.random_move(&self.board)
+    }
+
+    //The opponent played the expected move, keep searching but on the clock
+    fn ponderhit(&mut self) {
+        if let Some(s) = &self.search {
+            s.hold.store(false, Ordering::Relaxed);
+            if let Some(b) = s.budget {
+                start_timer(s.stop.clone(), b);
+            }
+        }
+    }
+
+    //Stops any running search and waits for its bestmove
+    fn stop(&mut self) {
+        if let Some(s) = self.search.take() {
+            s.stop.store(true, Ordering::Relaxed);
+            s.handle.join().ok();
+        }
+    }
+
+    #[cfg(test)]
+    fn wait(&mut self) {
+        if let Some(s) = self.search.take() {
+            s.handle.join().ok();
+        }
+    }
+}
+
+fn start_timer(stop: Arc<AtomicBool>, budget: Duration) {
+    thread::spawn(move || {
+        thread::sleep(budget);
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+#[cfg(test)]
+mod test_uci {
+    use super::*;
+    use hansing_chess::polyglot::{encode_move, polyglot_key, BookEntry};
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    fn engine() -> (Engine, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let out: Output = Arc::new(Mutex::new(Shared(buffer.clone())));
+        (Engine::new(out), buffer)
+    }
+    fn output(buffer: &Arc<Mutex<Vec<u8>>>) -> Vec<String> {
+        String::from_utf8(buffer.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+    #[test]
+    fn go_params() {
+        let args: Vec<&str> = "wtime 1000 btime 2000 winc 10 binc 20 movestogo 5 ponder"
+            .split_whitespace()
+            .collect();
+        let go = parse_go(&args);
+        assert_eq!(go.wtime, Some(1000));
+        assert_eq!(go.binc, Some(20));
+        assert_eq!(go.movestogo, Some(5));
+        assert!(go.ponder);
+        assert!(!go.infinite);
+        let go = parse_go(&["depth", "3", "infinite"]);
+        assert_eq!(go.depth, Some(3));
+        assert!(go.infinite);
+    }
+    #[test]
+    fn budget() {
+        let go = parse_go(&["movetime", "250"]);
+        assert_eq!(
+            time_budget(&go, Color::Black),
+            Some(Duration::from_millis(250))
+        );
+        let go = parse_go(&["wtime", "30000", "winc", "400", "movestogo", "10"]);
+        assert_eq!(
+            time_budget(&go, Color::White),
+            Some(Duration::from_millis(3300))
+        );
+        assert_eq!(time_budget(&go, Color::Black), None);
+        //Never more than what is on the clock
+        let go = parse_go(&["btime", "100", "binc", "1000"]);
+        assert_eq!(
+            time_budget(&go, Color::Black),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(time_budget(&parse_go(&["infinite"]), Color::White), None);
+    }
+    #[test]
+    fn position() {
+        let board = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        let fen: Vec<&str> = "fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1"
+            .split_whitespace()
+            .collect();
+        let board = parse_position(&fen).unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+        assert!(parse_position(&["fen", "nonsense"]).is_err());
+    }
+    #[test]
+    fn handshake_and_options() {
+        let (mut engine, buffer) = engine();
+        engine.handle("uci");
+        engine.handle("setoption name Threads value 2");
+        engine.handle("setoption name MultiPV value 2");
+        engine.handle("setoption name Hash value 1");
+        engine.handle("isready");
+        let out = output(&buffer);
+        assert_eq!(out[0], "id name hansing-chess");
+        assert!(out.contains(&"uciok".to_string()));
+        assert_eq!(out.last().unwrap(), "readyok");
+        assert_eq!(engine.threads, 2);
+        assert_eq!(engine.multi_pv, 2);
+    }
+    #[test]
+    fn go_depth() {
+        let (mut engine, buffer) = engine();
+        engine.handle("setoption name MultiPV value 2");
+        engine.handle("position startpos moves e2e4");
+        engine.handle("go depth 2");
+        engine.wait();
+        let out = output(&buffer);
+        assert!(out
+            .iter()
+            .any(|l| l.starts_with("info depth 2 multipv 2 score cp")));
+        let best = out.last().unwrap();
+        assert!(best.starts_with("bestmove "));
+        let m = best.split_whitespace().nth(1).unwrap();
+        assert!(MoveData::from_uci(&engine.board, m).is_some());
+    }
+    #[test]
+    fn infinite_waits_for_stop() {
+        let (mut engine, buffer) = engine();
+        engine.handle("position fen 7k/8/8/8/8/8/8/K6R w - - 0 1");
+        engine.handle("go depth 1 infinite");
+        thread::sleep(Duration::from_millis(100));
+        assert!(!output(&buffer).iter().any(|l| l.starts_with("bestmove")));
+        engine.handle("stop");
+        assert!(output(&buffer).last().unwrap().starts_with("bestmove"));
+    }
+    #[test]
+    fn ponderhit_starts_clock() {
+        let (mut engine, buffer) = engine();
+        engine.handle("position startpos");
+        engine.handle("go ponder movetime 20");
+        thread::sleep(Duration::from_millis(50));
+        assert!(!output(&buffer).iter().any(|l| l.starts_with("bestmove")));
+        engine.handle("ponderhit");
+        engine.wait();
+        assert!(output(&buffer).last().unwrap().starts_with("bestmove"));
+    }
+    #[test]
+    fn own_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.bin");
+        let board = parse_position(&["startpos", "moves", "e2e4"]).unwrap();
+        let entry = BookEntry {
+            key: polyglot_key(&board),
+            move_code: encode_move(&MoveData::from_uci(&board, "c7c5").unwrap()),
+            weight: 1,
+            learn: 0,
+        };
+        std::fs::write(&path, Book::from_entries(vec![entry]).to_bytes()).unwrap();
+
+        let (mut engine, buffer) = engine();
+        engine.handle(&format!("setoption name BookFile value {}", path.display()));
+        engine.handle("position startpos moves e2e4");
+        //Only used once OwnBook is switched on
+        engine.handle("go depth 1");
+        engine.wait();
+        engine.handle("setoption name OwnBook value true");
+        engine.handle("go depth 1");
+        engine.wait();
+        assert_eq!(output(&buffer).last().unwrap(), "bestmove c7c5");
+        //Out of book the engine searches as usual
+        engine.handle("position startpos moves d2d4");
+        engine.handle("go depth 1");
+        engine.wait();
+        assert!(output(&buffer)
+            .iter()
+            .any(|l| l.starts_with("info depth 1")));
+        engine.handle("setoption name BookFile value /nonexistent/book.bin");
+        assert!(output(&buffer)
+            .last()
+            .unwrap()
+            .starts_with("info string cannot read book"));
+    }
+    #[test]
+    fn mated_position() {
+        let (mut engine, buffer) = engine();
+        engine.handle("position fen 7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+        engine.handle("go depth 2");
+        engine.wait();
+        assert_eq!(output(&buffer).last().unwrap(), "bestmove 0000");
+    }
+}
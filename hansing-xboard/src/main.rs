@@ -0,0 +1,633 @@
+use hansing_chess::board::Board;
+use hansing_chess::color::Color;
+use hansing_chess::game::{Game, GameResult, Termination};
+use hansing_chess::movedata::MoveData;
+use hansing_chess::polyglot::Book;
+use hansing_chess::search::{mate_in, SearchLimits, SearchResult};
+use hansing_chess::smp::search_parallel;
+use hansing_chess::tt::TranspositionTable;
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_HASH_MB: usize = 16;
+//Kept in reserve so the engine never flags because of overhead
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+//Used when the interface has not sent any time control
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+//CECP reports mate scores as 100000 + moves to mate
+const MATE_SCORE: i32 = 100000;
+
+fn main() {
+    let stdin = io::stdin();
+    let mut engine = Engine::new(Arc::new(Mutex::new(io::stdout())));
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.finish(true);
+}
+
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+fn send(out: &Output, line: &str) {
+    let mut out = out.lock().unwrap();
+    writeln!(out, "{}", line).ok();
+    out.flush().ok();
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct TimeControl {
+    moves_per_session: u32, //0 means the whole game
+    base: Duration,
+    increment: Duration,
+    per_move: Option<Duration>, //Exact time per move from st
+    depth: Option<i32>,         //From sd
+    clock: Option<Duration>,    //Our remaining time, from level until the first time command
+}
+
+//level MPS BASE INC where BASE is minutes or minutes:seconds
+fn parse_level(args: &[&str]) -> Option<(u32, Duration, Duration)> {
+    if args.len() != 3 {
+        return None;
+    }
+    let moves = args[0].parse().ok()?;
+    let mut base = args[1].split(':');
+    let minutes: u64 = base.next()?.parse().ok()?;
+    let seconds: u64 = match base.next() {
+        Some(s) => s.parse().ok()?,
+        None => 0,
+    };
+    let increment: f64 = args[2].parse().ok()?;
+    Some((
+        moves,
+        Duration::from_secs(minutes * 60 + seconds),
+        Duration::from_millis((increment * 1000.0) as u64),
+    ))
+}
+
+//How long to think on this move, fullmove is the board's fullmove number
+fn time_budget(tc: &TimeControl, fullmove: u32) -> Option<Duration> {
+    if let Some(per_move) = tc.per_move {
+        return Some(per_move.checked_sub(MOVE_OVERHEAD).unwrap_or(per_move / 2));
+    }
+    let clock = match tc.clock {
+        Some(c) => c,
+        None if tc.depth.is_some() => return None,
+        None => return Some(DEFAULT_MOVE_TIME),
+    };
+    let moves_to_go = if tc.moves_per_session > 0 {
+        tc.moves_per_session - (fullmove.max(1) - 1) % tc.moves_per_session
+    } else {
+        30
+    };
+    let budget = clock / moves_to_go + tc.increment * 3 / 4;
+    let available = clock
+        .checked_sub(MOVE_OVERHEAD)
+        .unwrap_or_else(|| Duration::from_millis(1));
+    Some(budget.min(available))
+}
+
+//Thinking output: ply score time nodes pv, with time in centiseconds
+fn thinking_line(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match mate_in(result.score) {
+        Some(n) if n > 0 => MATE_SCORE + n,
+        Some(n) => -MATE_SCORE + n,
+        None => result.score,
+    };
+    let pv: Vec<String> = result.pv.iter().map(|m| m.to_uci()).collect();
+    format!(
+        "{} {} {} {} {}",
+        result.depth,
+        score,
+        elapsed.as_millis() / 10,
+        result.nodes,
+        pv.join(" ")
+    )
+}
+
+//The result with a comment on how the game ended, None while it is ongoing
+fn result_line(game: &Game) -> Option<String> {
+    let score = match game.result {
+        GameResult::Won(Color::White) => "1-0",
+        GameResult::Won(Color::Black) => "0-1",
+        GameResult::Tied => "1/2-1/2",
+        GameResult::Ongoing => return None,
+    };
+    let comment = match (game.result, game.termination) {
+        (GameResult::Won(c), Some(Termination::Checkmate)) => format!("{} mates", c),
+        (GameResult::Won(c), Some(Termination::Timeout)) => format!("{} wins on time", c),
+        (GameResult::Won(c), Some(Termination::Resignation)) => {
+            format!("{} resigns", c.inverse())
+        }
+        (GameResult::Won(c), Some(Termination::Adjudication)) => {
+            format!("{} wins by adjudication", c)
+        }
+        (GameResult::Won(c), _) => format!("{} wins", c),
+        (_, Some(Termination::Stalemate)) => "Stalemate".to_string(),
+        (_, Some(Termination::Timeout)) => "Time forfeit without mating material".to_string(),
+        (_, Some(Termination::Agreement)) => "Draw agreed".to_string(),
+        (_, Some(Termination::Adjudication)) => "Draw by adjudication".to_string(),
+        _ => "Draw".to_string(),
+    };
+    Some(format!("{} {{{}}}", score, comment))
+}
+
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    discard: Arc<AtomicBool>, //Set when the move should not be played, e.g. on force or new
+    handle: thread::JoinHandle<()>,
+}
+
+struct Engine {
+    out: Output,
+    game: Arc<Mutex<Game>>, //Shared with the search thread, which plays its move when done
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    force: bool,
+    engine_color: Color,
+    post: bool,
+    tc: TimeControl,
+    own_book: bool,
+    book: Option<Book>,
+    search: Option<RunningSearch>,
+}
+
+impl Engine {
+    fn new(out: Output) -> Self {
+        Self {
+            out,
+            game: Arc::new(Mutex::new(Game::new())),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            threads: 1,
+            force: false,
+            engine_color: Color::Black,
+            post: false,
+            tc: TimeControl::default(),
+            own_book: false,
+            book: None,
+            search: None,
+        }
+    }
+
+    fn game(&self) -> MutexGuard<'_, Game> {
+        self.game.lock().unwrap()
+    }
+
+    //Returns false on quit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((c, a)) => (*c, a),
+            None => return true,
+        };
+        match command {
+            "xboard" | "accepted" | "rejected" | "hard" | "easy" | "random" | "computer" => (),
+            "protover" => send(
+                &self.out,
+                "feature myname=\"hansing-chess\" setboard=1 usermove=1 ping=1 playother=1 \
+                 san=0 colors=0 sigint=0 sigterm=0 analyze=0 memory=1 smp=1 \
+                 option=\"Own Book -check 0\" option=\"Book File -file \" done=1",
+            ),
+            "new" => {
+                self.finish(true);
+                self.tt.clear();
+                *self.game() = Game::new();
+                self.force = false;
+                self.engine_color = Color::Black;
+                self.tc.depth = None;
+                self.tc.per_move = None;
+                self.tc.clock = Some(self.tc.base).filter(|b| !b.is_zero());
+            }
+            "force" => {
+                self.finish(true);
+                self.force = true;
+            }
+            "go" => {
+                self.finish(true);
+                self.force = false;
+                let to_act = self.game().board.to_act;
+                self.engine_color = to_act;
+                self.think();
+            }
+            "playother" => {
+                self.finish(true);
+                self.force = false;
+                let to_act = self.game().board.to_act;
+                self.engine_color = to_act.inverse();
+            }
+            "usermove" => match args.first() {
+                Some(note) => self.user_move(note),
+                None => send(&self.out, "Error (missing move): usermove"),
+            },
+            "setboard" => {
+                self.finish(true);
+                match Board::from_fen(&args.join(" ")) {
+                    Ok(board) => *self.game() = Game::from_board(board),
+                    Err(e) => send(&self.out, &format!("tellusererror Illegal position: {}", e)),
+                }
+            }
+            "undo" => {
+                self.finish(true);
+                self.game().undo_move();
+            }
+            "remove" => {
+                self.finish(true);
+                let mut game = self.game();
+                game.undo_move();
+                game.undo_move();
+            }
+            "level" => match parse_level(args) {
+                Some((moves, base, increment)) => {
+                    self.tc.moves_per_session = moves;
+                    self.tc.base = base;
+                    self.tc.increment = increment;
+                    self.tc.per_move = None;
+                    self.tc.clock = Some(base).filter(|b| !b.is_zero());
+                }
+                None => send(&self.out, &format!("Error (bad arguments): {}", line)),
+            },
+            "st" => match args.first().and_then(|a| a.parse::<u64>().ok()) {
+                Some(seconds) => self.tc.per_move = Some(Duration::from_secs(seconds)),
+                None => send(&self.out, &format!("Error (bad arguments): {}", line)),
+            },
+            "sd" => match args.first().and_then(|a| a.parse::<i32>().ok()) {
+                Some(depth) => self.tc.depth = Some(depth.max(1)),
+                None => send(&self.out, &format!("Error (bad arguments): {}", line)),
+            },
+            "time" => {
+                if let Some(cs) = args.first().and_then(|a| a.parse::<u64>().ok()) {
+                    self.tc.clock = Some(Duration::from_millis(cs * 10));
+                }
+            }
+            "otim" => (),
+            "memory" => {
+                if let Some(mb) = args.first().and_then(|a| a.parse::<usize>().ok()) {
+                    self.finish(true);
+                    self.tt = Arc::new(TranspositionTable::new(mb.max(1)));
+                }
+            }
+            "cores" => {
+                if let Some(n) = args.first().and_then(|a| a.parse::<usize>().ok()) {
+                    self.threads = n.max(1);
+                }
+            }
+            "option" => self.set_option(&args.join(" ")),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => {
+                self.finish(false);
+                send(&self.out, &format!("pong {}", args.first().unwrap_or(&"")));
+            }
+            "?" => {
+                if let Some(s) = &self.search {
+                    s.stop.store(true, Ordering::Relaxed);
+                }
+            }
+            "result" => {
+                self.finish(true);
+                self.force = true;
+            }
+            "quit" => return false,
+            //Without usermove=1 moves arrive on their own
+            _ => {
+                let legal = MoveData::from_uci(&self.game().board, command).is_some();
+                if legal {
+                    self.user_move(command);
+                } else {
+                    send(&self.out, &format!("Error (unknown command): {}", command));
+                }
+            }
+        }
+        true
+    }
+
+    fn user_move(&mut self, note: &str) {
+        self.finish(false);
+        let mut game = self.game();
+        let m = match MoveData::from_uci(&game.board, note) {
+            Some(m) if game.result == GameResult::Ongoing => m,
+            _ => {
+                send(&self.out, &format!("Illegal move: {}", note));
+                return;
+            }
+        };
+        game.make_move(m);
+        if let Some(result) = result_line(&game) {
+            send(&self.out, &result);
+        } else if !self.force && game.board.to_act == self.engine_color {
+            drop(game);
+            self.think();
+        }
+    }
+
+    //option <name>=<value>
+    fn set_option(&mut self, option: &str) {
+        let (name, value) = match option.find('=') {
+            Some(at) => (&option[..at], &option[at + 1..]),
+            None => (option, ""),
+        };
+        match name {
+            "Own Book" => self.own_book = value == "1",
+            "Book File" if value.is_empty() => self.book = None,
+            "Book File" => match Book::open(value) {
+                Ok(book) => self.book = Some(book),
+                Err(e) => send(
+                    &self.out,
+                    &format!("tellusererror Cannot read book {}: {}", value, e),
+                ),
+            },
+            _ => send(&self.out, &format!("Error (unknown option): {}", name)),
+        }
+    }
+
+    //A weighted random book move when the engine uses its own book
+    fn book_move(&self) -> Option<MoveData> {
+        if !self.own_book {
+            return None;
+        }
+        self.book.as_ref()?.random_move(&self.game().board)
+    }
+
+    fn think(&mut self) {
+        if self.game().result != GameResult::Ongoing {
+            return;
+        }
+        if let Some(m) = self.book_move() {
+            play(&self.out, &mut self.game(), m);
+            return;
+        }
+        let limits = SearchLimits {
+            depth: self.tc.depth.unwrap_or(SearchLimits::default().depth),
+            nodes: None,
+            multi_pv: 1,
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let board = self.game().board.clone();
+        if let Some(b) = time_budget(&self.tc, board.fullmove_number) {
+            start_timer(stop.clone(), b);
+        }
+        let discard = Arc::new(AtomicBool::new(false));
+
+        let tt = self.tt.clone();
+        let threads = self.threads;
+        let post = self.post;
+        let out = self.out.clone();
+        let (search_stop, search_discard, game) =
+            (stop.clone(), discard.clone(), self.game.clone());
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let result = search_parallel(&board, &limits, threads, &tt, &search_stop, &mut |r| {
+                if post {
+                    send(&out, &thinking_line(r, start.elapsed()));
+                }
+            });
+            //The lock makes sure a move is either played and announced or discarded, never half
+            let mut game = game.lock().unwrap();
+            if let (Some(m), false) = (result.best_move, search_discard.load(Ordering::Relaxed)) {
+                play(&out, &mut game, m);
+            }
+        });
+        self.search = Some(RunningSearch {
+            stop,
+            discard,
+            handle,
+        });
+    }
+
+    //Waits for the running search, which plays its move as soon as it is done. With abort the
+    //search is stopped right away and its move dropped unless it was already played.
+    fn finish(&mut self, abort: bool) {
+        let s = match self.search.take() {
+            Some(s) => s,
+            None => return,
+        };
+        if abort {
+            let _guard = self.game();
+            s.discard.store(true, Ordering::Relaxed);
+            s.stop.store(true, Ordering::Relaxed);
+        }
+        s.handle.join().ok();
+    }
+}
+
+//Announces and plays the engine's move, followed by the result if it ends the game
+fn play(out: &Output, game: &mut Game, m: MoveData) {
+    send(out, &format!("move {}", m.to_uci()));
+    game.make_move(m);
+    if let Some(result) = result_line(game) {
+        send(out, &result);
+    }
+}
+
+fn start_timer(stop: Arc<AtomicBool>, budget: Duration) {
+    thread::spawn(move || {
+        thread::sleep(budget);
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+#[cfg(test)]
+mod test_xboard {
+    use super::*;
+    use hansing_chess::polyglot::{encode_move, polyglot_key, BookEntry};
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    fn engine() -> (Engine, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let out: Output = Arc::new(Mutex::new(Shared(buffer.clone())));
+        (Engine::new(out), buffer)
+    }
+    fn output(buffer: &Arc<Mutex<Vec<u8>>>) -> Vec<String> {
+        String::from_utf8(buffer.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+    #[test]
+    fn level() {
+        assert_eq!(
+            parse_level(&["40", "5", "0"]),
+            Some((40, Duration::from_secs(300), Duration::from_secs(0)))
+        );
+        assert_eq!(
+            parse_level(&["0", "2:30", "0.5"]),
+            Some((0, Duration::from_secs(150), Duration::from_millis(500)))
+        );
+        assert_eq!(parse_level(&["0", "x", "0"]), None);
+    }
+    #[test]
+    fn result_comments() {
+        let mut game = Game::new();
+        assert_eq!(result_line(&game), None);
+        game.resign(Color::Black).unwrap();
+        assert_eq!(result_line(&game).unwrap(), "1-0 {Black resigns}");
+        let mut game = Game::new();
+        game.offer_draw(Color::White).unwrap();
+        game.make_move(MoveData::from_uci(&game.board, "e2e4").unwrap());
+        game.accept_draw(Color::Black).unwrap();
+        assert_eq!(result_line(&game).unwrap(), "1/2-1/2 {Draw agreed}");
+        let stalemate = Board::from_fen("k7/8/1Q6/8/8/8/8/K7 b - - 0 1").unwrap();
+        let mut game = Game::from_board(stalemate);
+        game.check_for_game_over();
+        assert_eq!(result_line(&game).unwrap(), "1/2-1/2 {Stalemate}");
+        let mut game = Game::new();
+        game.adjudicate(GameResult::Won(Color::Black)).unwrap();
+        assert_eq!(
+            result_line(&game).unwrap(),
+            "0-1 {Black wins by adjudication}"
+        );
+    }
+    #[test]
+    fn budget() {
+        let mut tc = TimeControl::default();
+        assert_eq!(time_budget(&tc, 1), Some(DEFAULT_MOVE_TIME));
+        tc.depth = Some(3);
+        assert_eq!(time_budget(&tc, 1), None);
+        tc.moves_per_session = 40;
+        tc.clock = Some(Duration::from_secs(20));
+        //Move 31 of a 40 move session leaves 10 moves for the clock
+        assert_eq!(time_budget(&tc, 31), Some(Duration::from_secs(2)));
+        tc.per_move = Some(Duration::from_secs(1));
+        assert_eq!(time_budget(&tc, 31), Some(Duration::from_millis(950)));
+
+        //Without a time command the level's base time is on the clock
+        let (mut engine, _) = engine();
+        engine.handle("level 40 5 0");
+        assert_eq!(
+            time_budget(&engine.tc, 1),
+            Some(Duration::from_millis(7500))
+        );
+        engine.handle("time 6000");
+        assert_eq!(
+            time_budget(&engine.tc, 1),
+            Some(Duration::from_millis(1500))
+        );
+        engine.handle("new");
+        assert_eq!(
+            time_budget(&engine.tc, 1),
+            Some(Duration::from_millis(7500))
+        );
+    }
+    #[test]
+    fn features() {
+        let (mut engine, buffer) = engine();
+        engine.handle("xboard");
+        engine.handle("protover 2");
+        engine.handle("ping 7");
+        let out = output(&buffer);
+        assert!(out[0].starts_with("feature "));
+        assert!(out[0].contains("setboard=1"));
+        assert!(out[0].ends_with("done=1"));
+        assert_eq!(out[1], "pong 7");
+    }
+    #[test]
+    fn engine_replies_to_user_move() {
+        let (mut engine, buffer) = engine();
+        engine.handle("new");
+        engine.handle("sd 2");
+        engine.handle("post");
+        engine.handle("usermove e2e4");
+        engine.handle("ping 1");
+        let out = output(&buffer);
+        assert!(out.iter().any(|l| l.starts_with("2 ")));
+        let reply = out.iter().find(|l| l.starts_with("move ")).unwrap();
+        assert_eq!(out.last().unwrap(), "pong 1");
+        assert_eq!(engine.game().moves.len(), 2);
+        assert_eq!(format!("move {}", engine.game().moves[1].to_uci()), *reply);
+    }
+    #[test]
+    fn force_undo_and_remove() {
+        let (mut engine, buffer) = engine();
+        engine.handle("force");
+        engine.handle("usermove e2e4");
+        engine.handle("e7e5");
+        engine.handle("usermove g1f3");
+        engine.handle("usermove e2e4");
+        assert_eq!(engine.game().moves.len(), 3);
+        assert_eq!(output(&buffer), vec!["Illegal move: e2e4"]);
+        engine.handle("undo");
+        assert_eq!(engine.game().moves.len(), 2);
+        engine.handle("remove");
+        assert!(engine.game().moves.is_empty());
+        assert_eq!(engine.game().board.to_act, Color::White);
+    }
+    #[test]
+    fn go_plays_side_to_move() {
+        let (mut engine, buffer) = engine();
+        engine.handle("setboard 7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+        engine.handle("sd 3");
+        engine.handle("go");
+        //Played and reported as soon as the search ends, without waiting for another command
+        engine.search.take().unwrap().handle.join().unwrap();
+        let out = output(&buffer);
+        assert_eq!(out, vec!["move a1a8", "1-0 {White mates}"]);
+        assert_eq!(engine.game().result, GameResult::Won(Color::White));
+        engine.handle("ping 2");
+        assert_eq!(output(&buffer).last().unwrap(), "pong 2");
+    }
+    #[test]
+    fn bad_setboard() {
+        let (mut engine, buffer) = engine();
+        engine.handle("setboard nonsense");
+        assert!(output(&buffer)[0].starts_with("tellusererror"));
+        assert_eq!(engine.game().moves.len(), 0);
+    }
+    #[test]
+    fn own_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.bin");
+        let mut board = Board::new_standard();
+        board.make_move(MoveData::from_uci(&board, "e2e4").unwrap());
+        let entry = BookEntry {
+            key: polyglot_key(&board),
+            move_code: encode_move(&MoveData::from_uci(&board, "e7e5").unwrap()),
+            weight: 1,
+            learn: 0,
+        };
+        std::fs::write(&path, Book::from_entries(vec![entry]).to_bytes()).unwrap();
+
+        let (mut engine, buffer) = engine();
+        engine.handle("protover 2");
+        assert!(output(&buffer)[0].contains("option=\"Book File -file \""));
+        engine.handle(&format!("option Book File={}", path.display()));
+        engine.handle("option Own Book=1");
+        engine.handle("new");
+        engine.handle("usermove e2e4");
+        assert_eq!(output(&buffer).last().unwrap(), "move e7e5");
+        assert_eq!(engine.game().moves.len(), 2);
+        engine.handle("option Book File=/nonexistent/book.bin");
+        assert!(output(&buffer)
+            .last()
+            .unwrap()
+            .starts_with("tellusererror Cannot read book"));
+    }
+    #[test]
+    fn move_now() {
+        let (mut engine, buffer) = engine();
+        engine.handle("st 30");
+        engine.handle("go");
+        thread::sleep(Duration::from_millis(50));
+        engine.handle("?");
+        engine.handle("ping 3");
+        let out = output(&buffer);
+        assert!(out[0].starts_with("move "));
+        assert_eq!(engine.game().moves.len(), 1);
+    }
+}